@@ -0,0 +1,173 @@
+use std::{
+    env,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use super::Clip;
+use crate::{
+    Result,
+    pwd::{Pwd, zero_bytes},
+};
+
+/// tmux buffer holding copied values, so clearing leaves others alone
+const TMUX_BUFFER: &str = "kdbx";
+
+/// A clipboard program and how to set, clear and read it
+pub struct Program {
+    /// Reads the value from STDIN
+    pub set: Vec<String>,
+    /// Clears the clipboard. `None` sets an empty value instead
+    pub clear: Option<Vec<String>>,
+    /// Prints the clipboard, when it can be read back
+    pub get: Option<Vec<String>>,
+}
+
+impl Program {
+    pub fn wl_copy() -> Self {
+        Program::new(
+            &["wl-copy"],
+            Some(&["wl-copy", "--clear"]),
+            Some(&["wl-paste", "--no-newline"]),
+        )
+    }
+
+    pub fn xclip() -> Self {
+        Program::new(
+            &["xclip", "-selection", "clipboard"],
+            None,
+            Some(&["xclip", "-selection", "clipboard", "-out"]),
+        )
+    }
+
+    pub fn xsel() -> Self {
+        Program::new(
+            &["xsel", "--clipboard", "--input"],
+            Some(&["xsel", "--clipboard", "--clear"]),
+            Some(&["xsel", "--clipboard", "--output"]),
+        )
+    }
+
+    pub fn pbcopy() -> Self {
+        Program::new(&["pbcopy"], None, Some(&["pbpaste"]))
+    }
+
+    pub fn tmux() -> Self {
+        Program::new(
+            &["tmux", "load-buffer", "-b", TMUX_BUFFER, "-"],
+            Some(&["tmux", "delete-buffer", "-b", TMUX_BUFFER]),
+            Some(&["tmux", "show-buffer", "-b", TMUX_BUFFER]),
+        )
+    }
+
+    /// A shell command reading the value from STDIN, never read back
+    pub fn shell(command: &str) -> Self {
+        Program::new(&["sh", "-c", command], None, None)
+    }
+
+    fn new(set: &[&str], clear: Option<&[&str]>, get: Option<&[&str]>) -> Self {
+        let owned = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        Program {
+            set: owned(set),
+            clear: clear.map(owned),
+            get: get.map(owned),
+        }
+    }
+}
+
+/// None of the programs can offer a hint for clipboard managers, only one
+/// type of content is copied
+impl Clip for Program {
+    fn set(&self, val: Option<&Pwd>) -> Result<()> {
+        match (val, self.clear.as_ref()) {
+            (None, Some(clear)) => run(clear, None),
+            (val, _) => run(&self.set, Some(val.map(Pwd::expose).unwrap_or_default())),
+        }
+    }
+
+    fn get(&self) -> Option<Result<Pwd>> {
+        let (program, args) = self.get.as_ref()?.split_first()?;
+        let output = match Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+        {
+            Ok(output) if output.status.success() => output,
+            Ok(mut output) => {
+                zero_bytes(&mut output.stdout);
+                let e = io::Error::other(format!("`{program}` exited with {}", output.status));
+                return Some(Err(e.into()));
+            }
+            Err(e) => return Some(Err(e.into())),
+        };
+
+        // not text, so not what was copied
+        Some(Ok(Pwd::from_utf8(output.stdout).unwrap_or_default()))
+    }
+}
+
+fn run(args: &[String], input: Option<&str>) -> Result<()> {
+    let (program, args) = args.split_first().expect("program name");
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        // programs serving the selection keep these open
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("Unable to run `{program}`: {e}"))?;
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin.write_all(input.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("`{program}` exited with {status}")).into());
+    }
+
+    Ok(())
+}
+
+/// Whether `program` is an executable in `PATH`
+pub fn in_path(program: &str) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+    env::split_paths(&path).any(|dir| {
+        Path::new(&dir)
+            .join(program)
+            .metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn pipes_value_to_command() {
+        let file = env::temp_dir().join(format!("kdbx-clipboard-{}", std::process::id()));
+        let program = Program::shell(&format!("cat > {}", file.display()));
+
+        program.set(Some(&"test123".to_string().into())).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "test123");
+        program.set(None).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "");
+        fs::remove_file(&file).unwrap();
+
+        assert!(Program::shell("exit 3").set(None).is_err());
+        assert!(in_path("sh"));
+        assert!(!in_path("kdbx-no-such-program"));
+    }
+}
@@ -0,0 +1,374 @@
+mod command;
+mod osc52;
+
+use std::{
+    env, fmt,
+    sync::{Mutex, OnceLock, PoisonError},
+};
+
+#[cfg(all(feature = "clipboard", target_os = "macos"))]
+use arboard::SetExtApple as _;
+#[cfg(all(feature = "clipboard", unix, not(target_os = "macos")))]
+use arboard::SetExtLinux as _;
+#[cfg(all(feature = "clipboard", windows))]
+use arboard::SetExtWindows as _;
+use clap::ValueEnum;
+use log::*;
+
+use self::command::{Program, in_path};
+use crate::{Result, pwd::Pwd};
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+/// The value last copied, to tell whether the clipboard still holds it
+static COPIED: Mutex<Option<Pwd>> = Mutex::new(None);
+
+/// A clipboard copied values go to
+trait Clip {
+    /// Sets the value, or clears the clipboard with `None`
+    fn set(&self, val: Option<&Pwd>) -> Result<()>;
+
+    /// The text held, `None` when it can't be read back
+    fn get(&self) -> Option<Result<Pwd>> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub backend: Backend,
+    /// Shell command of the `command` backend
+    pub command: Option<String>,
+}
+
+/// Where copied values go
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// The first one available: the given command, OSC 52 over SSH without
+    /// a display, wl-copy on Wayland, the system clipboard, xclip or xsel on
+    /// X11, then tmux
+    #[default]
+    Auto,
+    /// The desktop's clipboard, built in with the `clipboard` feature. The
+    /// only one keeping values out of clipboard managers' history
+    System,
+    /// Terminal escape sequence, works over SSH, inside tmux and screen
+    Osc52,
+    WlCopy,
+    Xclip,
+    Xsel,
+    Pbcopy,
+    /// A tmux buffer
+    Tmux,
+    /// The command given with `--clipboard-command`, reading from STDIN
+    Command,
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.pad(value.get_name())
+    }
+}
+
+impl Backend {
+    /// The backend `Auto` stands for, given the environment and the
+    /// programs found
+    fn resolve(
+        self,
+        has_command: bool,
+        var: impl Fn(&str) -> Option<String>,
+        has_program: impl Fn(&str) -> bool,
+    ) -> Backend {
+        if self != Backend::Auto {
+            return self;
+        }
+        if has_command {
+            return Backend::Command;
+        }
+
+        let set = |name| var(name).is_some_and(|value: String| !value.is_empty());
+        let builtin = cfg!(feature = "clipboard");
+
+        if cfg!(target_os = "macos") {
+            return if builtin {
+                Backend::System
+            } else {
+                Backend::Pbcopy
+            };
+        }
+        if var("SSH_TTY").is_some() && !set("DISPLAY") && !set("WAYLAND_DISPLAY") {
+            return Backend::Osc52;
+        }
+        if set("WAYLAND_DISPLAY") && has_program("wl-copy") {
+            return Backend::WlCopy;
+        }
+        if set("DISPLAY") && !builtin {
+            if has_program("xclip") {
+                return Backend::Xclip;
+            }
+            if has_program("xsel") {
+                return Backend::Xsel;
+            }
+        }
+        if !builtin && set("TMUX") && has_program("tmux") {
+            return Backend::Tmux;
+        }
+
+        Backend::System
+    }
+
+    /// Whether copied values are marked so clipboard managers keep them out
+    /// of their history. Only the built-in clipboard does it, none of the
+    /// programs can.
+    fn marks_sensitive(self) -> bool {
+        self == Backend::System && cfg!(feature = "clipboard")
+    }
+}
+
+/// Picks the backend used by `set_clipboard`, once, before anything is copied
+pub fn configure(settings: Settings) {
+    if SETTINGS.set(settings).is_err() {
+        warn!("clipboard is already configured");
+    }
+}
+
+fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(Settings::default)
+}
+
+/// Sets the clipboard to `val`, or clears it with `None`. Clearing only
+/// happens when the clipboard still holds the value copied last, so what was
+/// copied since is left alone.
+pub fn set_clipboard(val: Option<Pwd>) -> Result<()> {
+    let settings = settings();
+    let backend = backend();
+    debug!("setting the clipboard with the {backend} backend");
+    let clip = clip(backend, settings.command.as_deref())?;
+
+    let mut copied = COPIED.lock().unwrap_or_else(PoisonError::into_inner);
+    match val {
+        Some(val) => clip.set(Some(&val)).map(|()| *copied = Some(val)),
+        None => match copied.take() {
+            Some(expected) => wipe(clip.as_ref(), &expected),
+            None => {
+                debug!("nothing was copied, leaving the clipboard");
+                Ok(())
+            }
+        },
+    }
+    .map_err(|e| {
+        warn!("could not set the clipboard: {e}");
+        e
+    })
+}
+
+/// A warning for backends leaving copied values to clipboard managers,
+/// which may keep them in their history
+pub fn unmarked_warning() -> Option<String> {
+    let backend = backend();
+    (!backend.marks_sensitive()).then(|| {
+        format!(
+            "The `{backend}` clipboard can't mark values as sensitive, clipboard managers may \
+             keep them in their history"
+        )
+    })
+}
+
+fn backend() -> Backend {
+    let settings = settings();
+    settings.backend.resolve(
+        settings.command.is_some(),
+        |name| env::var(name).ok(),
+        in_path,
+    )
+}
+
+fn clip(backend: Backend, command: Option<&str>) -> Result<Box<dyn Clip>> {
+    Ok(match backend {
+        Backend::Osc52 => Box::new(osc52::Osc52),
+        Backend::WlCopy => Box::new(Program::wl_copy()),
+        Backend::Xclip => Box::new(Program::xclip()),
+        Backend::Xsel => Box::new(Program::xsel()),
+        Backend::Pbcopy => Box::new(Program::pbcopy()),
+        Backend::Tmux => Box::new(Program::tmux()),
+        Backend::Command => match command {
+            Some(command) => Box::new(Program::shell(command)),
+            None => return Err("No clipboard command given, see --clipboard-command".into()),
+        },
+        Backend::Auto | Backend::System => Box::new(System),
+    })
+}
+
+/// Clears the clipboard unless it holds something else than `expected`.
+/// Clipboards that can't be read back are cleared anyway.
+fn wipe(clip: &dyn Clip, expected: &Pwd) -> Result<()> {
+    match clip.get() {
+        Some(Ok(current)) if current != *expected => {
+            info!("the clipboard changed since, leaving it");
+            Ok(())
+        }
+        _ => clip.set(None),
+    }
+}
+
+/// The desktop's clipboard, marking values so clipboard managers keep them
+/// out of their history
+struct System;
+
+#[cfg(feature = "clipboard")]
+impl Clip for System {
+    fn set(&self, val: Option<&Pwd>) -> Result<()> {
+        let mut clipboard = arboard::Clipboard::new()?;
+        match val {
+            Some(val) => clipboard.set().exclude_from_history().text(val.expose())?,
+            None => clipboard.clear()?,
+        }
+        Ok(())
+    }
+
+    fn get(&self) -> Option<Result<Pwd>> {
+        let text = match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.get_text()) {
+            Ok(text) => Ok(text.into()),
+            // e.g. an image copied since
+            Err(arboard::Error::ContentNotAvailable) => Ok(Pwd::default()),
+            Err(e) => Err(e.into()),
+        };
+        Some(text)
+    }
+}
+
+#[cfg(not(feature = "clipboard"))]
+impl Clip for System {
+    fn set(&self, _: Option<&Pwd>) -> Result<()> {
+        Err("Feature clipboard is not available.".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, fs};
+
+    use super::*;
+
+    fn resolve(backend: Backend, vars: &[(&str, &str)], programs: &[&str]) -> Backend {
+        backend.resolve(
+            false,
+            |name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            },
+            |program| programs.contains(&program),
+        )
+    }
+
+    #[test]
+    fn picks_osc52_over_ssh_without_display() {
+        if cfg!(target_os = "macos") {
+            return;
+        }
+        let ssh = ("SSH_TTY", "/dev/pts/3");
+
+        assert_eq!(resolve(Backend::Auto, &[ssh], &[]), Backend::Osc52);
+        assert_eq!(
+            resolve(Backend::Auto, &[ssh, ("DISPLAY", "")], &[]),
+            Backend::Osc52
+        );
+        assert_eq!(
+            resolve(Backend::Auto, &[ssh, ("DISPLAY", ":0")], &[]),
+            Backend::System
+        );
+        assert_eq!(resolve(Backend::Auto, &[], &[]), Backend::System);
+        assert_eq!(resolve(Backend::System, &[ssh], &[]), Backend::System);
+        assert_eq!(resolve(Backend::Osc52, &[], &[]), Backend::Osc52);
+    }
+
+    #[test]
+    fn picks_programs_found() {
+        if cfg!(target_os = "macos") {
+            return;
+        }
+        let all = ["wl-copy", "xclip", "xsel", "tmux"];
+        let x11 = [("DISPLAY", ":0")];
+
+        assert_eq!(
+            resolve(Backend::Auto, &[("WAYLAND_DISPLAY", "wayland-0")], &all),
+            Backend::WlCopy
+        );
+        assert_eq!(
+            resolve(Backend::Auto, &[("WAYLAND_DISPLAY", "wayland-0")], &[]),
+            Backend::System
+        );
+        if cfg!(feature = "clipboard") {
+            assert_eq!(resolve(Backend::Auto, &x11, &all), Backend::System);
+        } else {
+            assert_eq!(resolve(Backend::Auto, &x11, &all), Backend::Xclip);
+            assert_eq!(resolve(Backend::Auto, &x11, &["xsel"]), Backend::Xsel);
+            assert_eq!(
+                resolve(Backend::Auto, &[("TMUX", "/tmp/tmux")], &all),
+                Backend::Tmux
+            );
+        }
+        assert_eq!(
+            Backend::Auto.resolve(true, |_| None, |_| false),
+            Backend::Command
+        );
+    }
+
+    #[test]
+    fn only_system_clipboard_marks_sensitive() {
+        assert_eq!(
+            Backend::System.marks_sensitive(),
+            cfg!(feature = "clipboard")
+        );
+        for backend in [
+            Backend::Osc52,
+            Backend::WlCopy,
+            Backend::Xclip,
+            Backend::Command,
+        ] {
+            assert!(!backend.marks_sensitive());
+        }
+    }
+
+    #[test]
+    fn wipes_only_own_value() {
+        let file = env::temp_dir().join(format!("kdbx-wipe-{}", std::process::id()));
+        let clip = Program {
+            set: vec![
+                "sh".into(),
+                "-c".into(),
+                format!("cat > {}", file.display()),
+            ],
+            clear: None,
+            get: Some(vec!["cat".into(), file.display().to_string()]),
+        };
+        let copied: Pwd = "test123".to_string().into();
+
+        clip.set(Some(&copied)).unwrap();
+        fs::write(&file, "copied since").unwrap();
+        wipe(&clip, &copied).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "copied since");
+
+        clip.set(Some(&copied)).unwrap();
+        wipe(&clip, &copied).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn wipes_unreadable_clipboard() {
+        struct Unreadable(RefCell<Vec<bool>>);
+
+        impl Clip for Unreadable {
+            fn set(&self, val: Option<&Pwd>) -> Result<()> {
+                self.0.borrow_mut().push(val.is_some());
+                Ok(())
+            }
+        }
+
+        let clip = Unreadable(RefCell::default());
+        wipe(&clip, &"test123".to_string().into()).unwrap();
+        assert_eq!(*clip.0.borrow(), [false]);
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{self, Write},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use super::Clip;
+use crate::{
+    Result,
+    pwd::{Pwd, zero_bytes},
+};
+
+/// screen drops longer DCS strings, so they are sent in pieces
+const SCREEN_CHUNK: usize = 76;
+
+/// Multiplexers standing between us and the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Multiplexer {
+    Tmux,
+    Screen,
+}
+
+impl Multiplexer {
+    fn detect(var: impl Fn(&str) -> Option<String>) -> Option<Multiplexer> {
+        if var("TMUX").is_some() {
+            Some(Multiplexer::Tmux)
+        } else if var("STY").is_some() && var("TERM").is_some_and(|term| term.starts_with("screen"))
+        {
+            Some(Multiplexer::Screen)
+        } else {
+            None
+        }
+    }
+}
+
+/// Asks the terminal to set its clipboard, an empty value clearing it. The
+/// sequence goes to the controlling terminal so it works with STDOUT piped.
+/// Terminals rarely allow reading it back, so it's always cleared.
+pub struct Osc52;
+
+impl Clip for Osc52 {
+    fn set(&self, val: Option<&Pwd>) -> Result<()> {
+        let multiplexer = Multiplexer::detect(|name| env::var(name).ok());
+        let mut sequence = sequence(val.map(Pwd::expose).unwrap_or_default(), multiplexer);
+
+        let written = OpenOptions::new()
+            .write(true)
+            .open("/dev/tty")
+            .and_then(|mut tty| {
+                tty.write_all(&sequence)?;
+                tty.flush()
+            });
+        zero_bytes(&mut sequence);
+
+        written.map_err(|e: io::Error| format!("Unable to write to the terminal: {e}").into())
+    }
+}
+
+fn sequence(text: &str, multiplexer: Option<Multiplexer>) -> Vec<u8> {
+    let mut encoded = STANDARD.encode(text).into_bytes();
+    let mut osc = [b"\x1b]52;c;", encoded.as_slice(), b"\x07"].concat();
+    zero_bytes(&mut encoded);
+
+    let wrapped = match multiplexer {
+        None => return osc,
+        // tmux passes it on with `allow-passthrough` once ESCs are doubled
+        Some(Multiplexer::Tmux) => {
+            let mut wrapped = b"\x1bPtmux;".to_vec();
+            for &byte in &osc {
+                if byte == 0x1b {
+                    wrapped.push(0x1b);
+                }
+                wrapped.push(byte);
+            }
+            wrapped.extend_from_slice(b"\x1b\\");
+            wrapped
+        }
+        Some(Multiplexer::Screen) => {
+            let mut wrapped = Vec::new();
+            for chunk in osc.chunks(SCREEN_CHUNK) {
+                wrapped.extend_from_slice(b"\x1bP");
+                wrapped.extend_from_slice(chunk);
+                wrapped.extend_from_slice(b"\x1b\\");
+            }
+            wrapped
+        }
+    };
+    zero_bytes(&mut osc);
+
+    wrapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_for_multiplexers() {
+        assert_eq!(sequence("test123", None), b"\x1b]52;c;dGVzdDEyMw==\x07");
+        assert_eq!(sequence("", None), b"\x1b]52;c;\x07");
+        assert_eq!(
+            sequence("test123", Some(Multiplexer::Tmux)),
+            b"\x1bPtmux;\x1b\x1b]52;c;dGVzdDEyMw==\x07\x1b\\"
+        );
+
+        let screen = sequence(&"x".repeat(100), Some(Multiplexer::Screen));
+        let pieces = screen.split(|&b| b == b'P').count() - 1;
+        assert_eq!(pieces, 2);
+        assert!(screen.starts_with(b"\x1bP\x1b]52;c;eHh4"));
+        assert!(screen.ends_with(b"\x07\x1b\\"));
+    }
+
+    #[test]
+    fn detects_multiplexers() {
+        let detect = |vars: &[(&str, &str)]| {
+            Multiplexer::detect(|name| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+
+        assert_eq!(
+            detect(&[("TMUX", "/tmp/tmux"), ("TERM", "screen")]),
+            Some(Multiplexer::Tmux)
+        );
+        assert_eq!(
+            detect(&[("STY", "1.pts"), ("TERM", "screen.xterm")]),
+            Some(Multiplexer::Screen)
+        );
+        assert_eq!(detect(&[("TERM", "screen")]), None);
+        assert_eq!(detect(&[]), None);
+    }
+}
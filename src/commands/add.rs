@@ -3,10 +3,20 @@ use std::path::PathBuf;
 use clap::ValueHint;
 use keepass::db::{Entry, Node, Value};
 
-use crate::{Result, STDIN, keepass::save_database, utils::open_database_interactively};
+use crate::{
+    Result, STDIN,
+    keepass::save_database,
+    otp::otpauth_uri,
+    qr,
+    utils::{ask_password_to_save, open_database_interactively},
+};
 
 #[derive(clap::Args)]
 pub struct Args {
+    /// Read TOTP from a QR code image (PNG/JPEG) instead of asking for it
+    #[arg(long, value_name = "IMAGE", value_hint = ValueHint::FilePath)]
+    totp_qr: Option<PathBuf>,
+
     /// Store password for the database in the OS's keyring
     #[arg(short = 'p', long)]
     use_keyring: bool,
@@ -28,7 +38,13 @@ pub(crate) fn run(args: Args) -> Result<()> {
     if !args.database.exists() {
         return Err("File does not exist".to_string().into());
     }
-    let (db, password) = open_database_interactively(
+    let totp_qr = args
+        .totp_qr
+        .as_deref()
+        .map(qr::decode_otpauth)
+        .transpose()?;
+
+    let (db, mut credentials) = open_database_interactively(
         &args.database,
         args.key_file.as_deref(),
         args.use_keyring,
@@ -47,20 +63,15 @@ pub(crate) fn run(args: Args) -> Result<()> {
         put!("Password: ");
         STDIN.read_password()
     };
-    let totp_raw: crate::pwd::Pwd = {
+    let totp_raw = if let Some(uri) = totp_qr {
+        uri
+    } else {
         put!("TOTP (otpauth:// or secret): ");
         let totp_raw = STDIN.read_password();
-        if totp_raw.starts_with("otpauth://") {
+        if totp_raw.expose().starts_with("otpauth://") {
             totp_raw
-        } else if !totp_raw.trim().is_empty() {
-            format!(
-                "otpauth://totp/{}:{}?secret={}&period=30&digits=6&issuer={}",
-                entry_title,
-                entry_username,
-                &totp_raw[..],
-                entry_title
-            )
-            .into()
+        } else if !totp_raw.expose().trim().is_empty() {
+            otpauth_uri(&entry_title, &entry_username, totp_raw.expose())
         } else {
             totp_raw
         }
@@ -75,18 +86,19 @@ pub(crate) fn run(args: Args) -> Result<()> {
         .insert("UserName".to_string(), Value::Unprotected(entry_username));
     entry.fields.insert(
         "Password".to_string(),
-        Value::Protected(entry_password.as_bytes().into()),
+        Value::Protected(entry_password.expose().as_bytes().into()),
     );
-    if !totp_raw.trim().is_empty() {
+    if !totp_raw.expose().trim().is_empty() {
         entry.fields.insert(
             "otp".to_string(),
-            Value::Protected(totp_raw.as_bytes().into()),
+            Value::Protected(totp_raw.expose().as_bytes().into()),
         );
     }
     let mut db = db;
     db.root.children.push(Node::Entry(entry));
 
-    save_database(db, &args.database, args.key_file.as_deref(), password)?;
+    ask_password_to_save(&args.database, args.key_file.as_deref(), &mut credentials)?;
+    save_database(db, &args.database, args.key_file.as_deref(), credentials)?;
 
     Ok(())
 }
@@ -0,0 +1,255 @@
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+use chrono::Utc;
+use clap::ValueHint;
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    Result,
+    hibp::HashList,
+    keepass::{EntryPath, WrappedEntry, get_entries},
+    utils::open_database_interactively,
+};
+
+/// Sites known to offer TOTP based two-factor authentication
+const TOTP_DOMAINS: &[&str] = &[
+    "amazon.com",
+    "apple.com",
+    "atlassian.com",
+    "bitbucket.org",
+    "cloudflare.com",
+    "digitalocean.com",
+    "discord.com",
+    "docker.com",
+    "dropbox.com",
+    "facebook.com",
+    "github.com",
+    "gitlab.com",
+    "google.com",
+    "heroku.com",
+    "instagram.com",
+    "linkedin.com",
+    "live.com",
+    "microsoft.com",
+    "npmjs.com",
+    "okta.com",
+    "paypal.com",
+    "proton.me",
+    "pypi.org",
+    "reddit.com",
+    "slack.com",
+    "steampowered.com",
+    "stripe.com",
+    "twitch.tv",
+    "twitter.com",
+    "x.com",
+    "zoom.us",
+];
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Minimal acceptable password strength score (0-4)
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(0..=4))]
+    min_score: u8,
+
+    /// Report passwords unchanged for more than DAYS. 0 means no check
+    #[arg(long, value_name = "DAYS", default_value_t = 365)]
+    max_age: u32,
+
+    /// Check passwords against a local Have I Been Pwned SHA-1 list (ordered by
+    /// hash)
+    #[arg(long, value_name = "FILE", value_hint = ValueHint::FilePath)]
+    hibp_file: Option<PathBuf>,
+
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+
+    /// Store password for the database in the OS's keyring
+    #[arg(short = 'p', long)]
+    use_keyring: bool,
+
+    /// Remove database's password from OS's keyring and exit
+    #[arg(short = 'P', long)]
+    remove_key: bool,
+
+    /// KDBX file path
+    #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
+    database: PathBuf,
+
+    /// Path to the key file unlocking the database
+    #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
+    key_file: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    Empty,
+    Weak,
+    Breached,
+    Reused,
+    Stale,
+    MissingTotp,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Kind::Empty => write!(f, "empty"),
+            Kind::Weak => write!(f, "weak"),
+            Kind::Breached => write!(f, "breached"),
+            Kind::Reused => write!(f, "reused"),
+            Kind::Stale => write!(f, "stale"),
+            Kind::MissingTotp => write!(f, "missing_totp"),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Finding {
+    kind: Kind,
+    entry: String,
+    detail: String,
+}
+
+pub(crate) fn run(args: Args) -> Result<()> {
+    if !args.database.exists() {
+        return Err("File does not exist".to_string().into());
+    }
+    let (db, _) = open_database_interactively(
+        &args.database,
+        args.key_file.as_deref(),
+        args.use_keyring,
+        args.remove_key,
+        false,
+    )?;
+
+    let mut hibp = args
+        .hibp_file
+        .as_deref()
+        .map(HashList::open)
+        .transpose()
+        .map_err(|e| format!("Unable to open HIBP file: {e}"))?;
+
+    let entries = get_entries(&db.root, "");
+    let findings = audit(&entries, &args, hibp.as_mut())?;
+
+    if args.json {
+        wout!("{}", serde_json::to_string_pretty(&findings)?);
+    } else {
+        for finding in findings.iter() {
+            wout!("{}: {} ({})", finding.kind, finding.entry, finding.detail);
+        }
+    }
+
+    if !findings.is_empty() {
+        return Err(format!("{} issue(s) found", findings.len()).into());
+    }
+
+    Ok(())
+}
+
+fn audit(
+    entries: &[WrappedEntry],
+    args: &Args,
+    mut hibp: Option<&mut HashList>,
+) -> Result<Vec<Finding>> {
+    let mut by_password: HashMap<&str, usize> = HashMap::new();
+    for e in entries.iter() {
+        let password = e.entry.get_password().unwrap_or_default();
+        if !password.is_empty() {
+            *by_password.entry(password).or_default() += 1;
+        }
+    }
+
+    let now = Utc::now().naive_utc();
+    let mut findings = Vec::new();
+
+    for e in entries.iter() {
+        let path = e.entry_path();
+        let password = e.entry.get_password().unwrap_or_default();
+
+        if password.is_empty() {
+            findings.push(Finding {
+                kind: Kind::Empty,
+                entry: path.clone(),
+                detail: "no password set".to_string(),
+            });
+        } else {
+            let user_inputs = [
+                e.entry.get_title().unwrap_or_default(),
+                e.entry.get_username().unwrap_or_default(),
+            ];
+            let score = zxcvbn::zxcvbn(password, &user_inputs).score() as u8;
+            if score < args.min_score {
+                findings.push(Finding {
+                    kind: Kind::Weak,
+                    entry: path.clone(),
+                    detail: format!("strength score {score}/4"),
+                });
+            }
+
+            if let Some(hibp) = hibp.as_deref_mut()
+                && let Some(count) = hibp.count(password)?
+            {
+                findings.push(Finding {
+                    kind: Kind::Breached,
+                    entry: path.clone(),
+                    detail: format!("seen {count} times in data breaches"),
+                });
+            }
+
+            let shared = by_password.get(password).copied().unwrap_or_default();
+            if shared > 1 {
+                findings.push(Finding {
+                    kind: Kind::Reused,
+                    entry: path.clone(),
+                    detail: format!("same password in {} other entries", shared - 1),
+                });
+            }
+
+            if args.max_age > 0
+                && let Some(modified) = e.entry.times.get_last_modification()
+            {
+                let days = (now - *modified).num_days();
+                if days > i64::from(args.max_age) {
+                    findings.push(Finding {
+                        kind: Kind::Stale,
+                        entry: path.clone(),
+                        detail: format!("unchanged for {days} days"),
+                    });
+                }
+            }
+        }
+
+        if !e.has_totp()
+            && let Some(domain) = e.entry.get_url().and_then(totp_domain)
+        {
+            findings.push(Finding {
+                kind: Kind::MissingTotp,
+                entry: path,
+                detail: format!("{domain} supports TOTP"),
+            });
+        }
+    }
+
+    Ok(findings)
+}
+
+fn totp_domain(url: &str) -> Option<&'static str> {
+    let url = url.trim();
+    let url = if url.contains("://") {
+        Url::parse(url)
+    } else {
+        Url::parse(&format!("https://{url}"))
+    }
+    .ok()?;
+    let host = url.host_str()?.to_ascii_lowercase();
+
+    TOTP_DOMAINS
+        .iter()
+        .find(|domain| host == **domain || host.ends_with(&format!(".{domain}")))
+        .copied()
+}
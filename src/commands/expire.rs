@@ -0,0 +1,129 @@
+use std::path::PathBuf;
+
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta, Utc};
+use clap::ValueHint;
+
+use crate::{
+    Result,
+    keepass::{find_entry, find_entry_by_uuid_mut, get_entries, save_database},
+    utils::{ask_password_to_save, open_database_interactively, parse_duration, skim},
+};
+
+#[derive(clap::Args)]
+#[group(id = "expiry", required = true, args = ["at", "in", "clear"])]
+pub struct Args {
+    entry: Option<String>,
+
+    /// Expire at the given UTC date, e.g. 2030-01-31 or "2030-01-31 18:00"
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    at: Option<NaiveDateTime>,
+
+    /// Expire after the given period from now, e.g. 30d, 12h, 2w
+    #[arg(long = "in", value_name = "DURATION", value_parser = parse_duration)]
+    r#in: Option<TimeDelta>,
+
+    /// Remove the expiry from the entry
+    #[arg(long)]
+    clear: bool,
+
+    /// Show entries without group(s)
+    #[arg(short = 'G', long)]
+    no_group: bool,
+
+    /// Do not ask any interactive question
+    #[arg(short = 'n', long)]
+    no_interaction: bool,
+
+    /// Preview entry during picking
+    #[arg(short = 'v', long)]
+    preview: bool,
+
+    /// Use all available screen for picker
+    #[arg(short, long)]
+    full_screen: bool,
+
+    /// Store password for the database in the OS's keyring
+    #[arg(short = 'p', long)]
+    use_keyring: bool,
+
+    /// Remove database's password from OS's keyring and exit
+    #[arg(short = 'P', long)]
+    remove_key: bool,
+
+    /// KDBX file path
+    #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
+    database: PathBuf,
+
+    /// Path to the key file unlocking the database
+    #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
+    key_file: Option<PathBuf>,
+}
+
+pub(crate) fn run(args: Args) -> Result<()> {
+    if !args.database.exists() {
+        return Err("File does not exist".to_string().into());
+    }
+    let (mut db, mut credentials) = open_database_interactively(
+        &args.database,
+        args.key_file.as_deref(),
+        args.use_keyring,
+        args.remove_key,
+        args.no_interaction,
+    )?;
+
+    let query = args.entry.as_ref().map(String::as_ref);
+
+    let uuid = match query.and_then(|query| find_entry(query, &db.root)) {
+        Some(entry) => *entry.get_uuid(),
+        None if args.no_interaction => return Err("Not found".to_string().into()),
+        None => match skim(
+            &get_entries(&db.root, ""),
+            query.map(String::from),
+            args.no_group,
+            args.preview,
+            args.full_screen,
+            false,
+        ) {
+            Some(wrapped_entry) => *wrapped_entry.entry.get_uuid(),
+            None => return Ok(()),
+        },
+    };
+
+    let expiry = match (args.at, args.r#in) {
+        (Some(at), _) => Some(at),
+        (None, Some(period)) => Some(
+            Utc::now()
+                .naive_utc()
+                .checked_add_signed(period)
+                .ok_or("Duration is too long")?,
+        ),
+        (None, None) => None,
+    };
+
+    let entry = find_entry_by_uuid_mut(&mut db.root, &uuid).expect("picked entry exists");
+    match expiry {
+        Some(time) => {
+            entry.times.expires = true;
+            entry.times.set_expiry(time);
+        }
+        None => entry.times.expires = false,
+    }
+    entry.update_history();
+
+    ask_password_to_save(&args.database, args.key_file.as_deref(), &mut credentials)?;
+    save_database(db, &args.database, args.key_file.as_deref(), credentials)?;
+
+    Ok(())
+}
+
+fn parse_date(value: &str) -> std::result::Result<NaiveDateTime, String> {
+    let value = value.trim();
+
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M"))
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S"))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|d| d.and_time(Default::default()))
+        })
+        .map_err(|_| format!("invalid date `{value}`, expected YYYY-MM-DD [HH:MM[:SS]]"))
+}
@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use chrono::{TimeDelta, Utc};
+use clap::ValueHint;
+
+use crate::{
+    Result,
+    keepass::{EntryPath, expiry, get_entries},
+    utils::{open_database_interactively, parse_duration},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Show entries expiring within this period, e.g. 30d, 12h, 2w
+    #[arg(short, long, default_value = "30d", value_parser = parse_duration)]
+    within: TimeDelta,
+
+    /// Show entries without group(s)
+    #[arg(short = 'G', long)]
+    no_group: bool,
+
+    /// Store password for the database in the OS's keyring
+    #[arg(short = 'p', long)]
+    use_keyring: bool,
+
+    /// Remove database's password from OS's keyring and exit
+    #[arg(short = 'P', long)]
+    remove_key: bool,
+
+    /// KDBX file path
+    #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
+    database: PathBuf,
+
+    /// Path to the key file unlocking the database
+    #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
+    key_file: Option<PathBuf>,
+}
+
+pub(crate) fn run(args: Args) -> Result<()> {
+    if !args.database.exists() {
+        return Err("File does not exist".to_string().into());
+    }
+    let (db, _) = open_database_interactively(
+        &args.database,
+        args.key_file.as_deref(),
+        args.use_keyring,
+        args.remove_key,
+        false,
+    )?;
+
+    let now = Utc::now().naive_utc();
+    let deadline = now
+        .checked_add_signed(args.within)
+        .ok_or("Duration is too long")?;
+
+    let mut entries = get_entries(&db.root, "")
+        .into_iter()
+        .filter_map(|e| expiry(e.entry).map(|time| (time, e)))
+        .filter(|(time, _)| *time <= deadline)
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|(time, _)| *time);
+
+    for (time, e) in entries.iter() {
+        let title = if args.no_group {
+            e.get_title()
+        } else {
+            e.entry_path()
+        };
+        let state = if *time <= now { "expired" } else { "expires" };
+        wout!("{} ({} {})", title, state, time.format("%Y-%m-%d %H:%M"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use clap::ValueHint;
+
+use crate::{
+    Result, STDIN,
+    import::{self, Token},
+    keepass::save_database,
+    utils::{ask_password_to_save, open_database_interactively},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// `otpauth-migration://`/`otpauth://` URIs, QR code images holding them,
+    /// or Aegis/andOTP JSON backups
+    #[arg(required = true, value_hint = ValueHint::AnyPath)]
+    sources: Vec<String>,
+
+    /// Do not ask any interactive question
+    #[arg(short = 'n', long)]
+    no_interaction: bool,
+
+    /// Store password for the database in the OS's keyring
+    #[arg(short = 'p', long)]
+    use_keyring: bool,
+
+    /// Remove database's password from OS's keyring and exit
+    #[arg(short = 'P', long)]
+    remove_key: bool,
+
+    /// KDBX file path
+    #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
+    database: PathBuf,
+
+    /// Path to the key file unlocking the database
+    #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
+    key_file: Option<PathBuf>,
+}
+
+pub(crate) fn run(args: Args) -> Result<()> {
+    if !args.database.exists() {
+        return Err("File does not exist".to_string().into());
+    }
+    // Backups are read first, their passwords asked for before the database's
+    let tokens = read_tokens(&args.sources)?;
+
+    let (mut db, mut credentials) = open_database_interactively(
+        &args.database,
+        args.key_file.as_deref(),
+        args.use_keyring,
+        args.remove_key,
+        args.no_interaction,
+    )?;
+    for line in import::merge(&mut db.root, tokens) {
+        wout!("{}", line);
+    }
+
+    ask_password_to_save(&args.database, args.key_file.as_deref(), &mut credentials)?;
+    save_database(db, &args.database, args.key_file.as_deref(), credentials)?;
+
+    Ok(())
+}
+
+fn read_tokens(sources: &[String]) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    for source in sources {
+        tokens.extend(import::read_source(source, &|| {
+            put!("Password for `{}`: ", source);
+            STDIN.read_password()
+        })?);
+    }
+
+    if tokens.is_empty() {
+        return Err("Nothing to import".into());
+    }
+
+    Ok(tokens)
+}
@@ -1,9 +1,19 @@
-use std::{fs::File, path::PathBuf};
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
 
 use clap::ValueHint;
 use keepass::db::Database;
 
-use crate::{Result, STDIN, keepass::new_database_key, pwd::Pwd};
+use crate::{
+    Result, STDIN,
+    keepass::new_database_key,
+    keyfile::{self, Format},
+    password_source,
+    pinentry::{self, Pinentry},
+    pwd::Pwd,
+};
 
 #[derive(clap::Args)]
 pub struct Args {
@@ -14,17 +24,37 @@ pub struct Args {
     /// Path to the key file unlocking the database
     #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
     key_file: Option<PathBuf>,
+
+    /// Write a new XML v2.0 key file at the key file path first
+    #[arg(long, requires = "key_file")]
+    generate_keyfile: bool,
+
+    /// Lock the database with the key file alone
+    #[arg(long, requires = "key_file")]
+    no_password: bool,
 }
 
 pub(crate) fn run(args: Args) -> Result<()> {
     if args.database.exists() {
         return Err("File exists".to_string().into());
     }
-    let password = read_password("Password: ");
-    let confirm = read_password("Confirm: ");
-    if password != confirm {
-        return Err("Passwords do not match".into());
+    if args.generate_keyfile && args.key_file.as_ref().is_some_and(|path| path.exists()) {
+        return Err("Key file exists".into());
     }
+    let password = if args.no_password {
+        None
+    } else if let Some(password) = password_source::read() {
+        Some(password?)
+    } else {
+        let (password, confirm) = match pinentry::program() {
+            Some(program) => read_pinentry(program)?,
+            None => (read_password("Password: "), read_password("Confirm: ")),
+        };
+        if password != confirm {
+            return Err("Passwords do not match".into());
+        }
+        Some(password)
+    };
 
     let database_name = read_db_name();
 
@@ -32,9 +62,25 @@ pub(crate) fn run(args: Args) -> Result<()> {
 
     db.meta.database_name = Some(database_name);
 
-    let key = new_database_key(args.key_file.as_deref(), password)?;
+    let generated = args.key_file.as_deref().filter(|_| args.generate_keyfile);
+    if let Some(key_file) = generated {
+        keyfile::generate(key_file, Format::XmlV2)?;
+    }
+
+    let saved = save(db, &args.database, args.key_file.as_deref(), password);
+    // A key file of no database is of no use
+    if saved.is_err()
+        && let Some(key_file) = generated
+    {
+        let _ = fs::remove_file(key_file);
+    }
+
+    saved
+}
 
-    db.save(&mut File::create(args.database)?, key)?;
+fn save(db: Database, path: &Path, key_file: Option<&Path>, password: Option<Pwd>) -> Result<()> {
+    let key = new_database_key(key_file, password)?;
+    db.save(&mut File::create(path)?, key)?;
 
     Ok(())
 }
@@ -44,6 +90,15 @@ fn read_password(prompt: &str) -> Pwd {
     STDIN.read_password()
 }
 
+fn read_pinentry(program: &Path) -> Result<(Pwd, Pwd)> {
+    let mut pinentry = Pinentry::spawn(program)?;
+    pinentry.set_description("Enter the password of the new database")?;
+    let password = pinentry.get_pin("Password:", None)?.ok_or("Cancelled")?;
+    let confirm = pinentry.get_pin("Confirm:", None)?.ok_or("Cancelled")?;
+
+    Ok((password, confirm))
+}
+
 fn read_db_name() -> String {
     put!("Database name: ");
     STDIN.read_text()
@@ -0,0 +1,51 @@
+use std::{fs, path::PathBuf};
+
+use clap::ValueHint;
+
+use crate::{
+    Result,
+    keyfile::{self, Format},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Write a new random key file, readable by the owner only
+    Generate(GenerateArgs),
+    /// Tell the format of a key file and check its hash
+    Verify(VerifyArgs),
+}
+
+#[derive(clap::Args)]
+struct GenerateArgs {
+    /// Path of the key file to create
+    #[arg(value_hint = ValueHint::FilePath)]
+    path: PathBuf,
+
+    /// Key file format
+    #[arg(short, long, value_enum, default_value_t = Format::default())]
+    format: Format,
+}
+
+#[derive(clap::Args)]
+struct VerifyArgs {
+    /// Path of the key file
+    #[arg(value_hint = ValueHint::FilePath)]
+    path: PathBuf,
+}
+
+pub(crate) fn run(args: Args) -> Result<()> {
+    match args.command {
+        Command::Generate(args) => keyfile::generate(&args.path, args.format),
+        Command::Verify(args) => {
+            let kind = keyfile::inspect(&fs::read(&args.path)?)?;
+            wout!("{}", kind);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::path::PathBuf;
+
+use clap::{ValueEnum, ValueHint};
+
+use crate::{
+    Result,
+    keyring::{self, Backend, Stored},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Show the active backend and which backends are usable
+    Status(StatusArgs),
+}
+
+#[derive(clap::Args)]
+struct StatusArgs {
+    /// Also tell whether a password is stored for the database
+    #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
+    database: Option<PathBuf>,
+
+    /// Path to the key file unlocking the database
+    #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
+    key_file: Option<PathBuf>,
+}
+
+pub(crate) fn run(args: Args) -> Result<()> {
+    match args.command {
+        Command::Status(args) => status(args),
+    }
+}
+
+fn status(args: StatusArgs) -> Result<()> {
+    let settings = keyring::settings();
+    let active = settings.backend;
+    wout!("Backend: {}", active);
+    if let Some(ttl) = settings.expiry.ttl {
+        wout!("Forget after: {}s", ttl);
+    }
+    if let Some(idle) = settings.expiry.idle {
+        wout!("Forget when idle for: {}s", idle);
+    }
+    wout!("Cache: {}", settings.cache);
+    wout!("");
+
+    let width = Backend::value_variants()
+        .iter()
+        .map(|backend| backend.to_string().len())
+        .max()
+        .unwrap_or_default();
+    for backend in Backend::value_variants() {
+        let marker = if *backend == active { "*" } else { " " };
+        match keyring::probe(*backend) {
+            Ok(()) => wout!("{} {:width$}  available", marker, backend),
+            Err(e) => wout!("{} {:width$}  unavailable: {}", marker, backend, e),
+        }
+    }
+
+    if let Some(database) = args.database {
+        // read only, so asking doesn't keep the password from expiring
+        let stored = match keyring::peek_db_path(&database, args.key_file.as_deref()) {
+            Stored::Nothing => "not stored".to_string(),
+            Stored::Expired => "expired".to_string(),
+            Stored::Password { expires_in: None } => "stored".to_string(),
+            Stored::Password {
+                expires_in: Some(secs),
+            } => format!("stored, forgotten in {secs}s"),
+        };
+
+        wout!("");
+        wout!("Password for `{}`: {}", database.to_string_lossy(), stored);
+    }
+
+    Ok(())
+}
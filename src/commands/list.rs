@@ -4,8 +4,8 @@ use clap::ValueHint;
 
 use crate::{
     Result,
-    keepass::{EntryPath, get_entries},
-    utils::open_database_interactively,
+    keepass::{EXPIRED_MARKER, EntryPath, get_all_entries},
+    utils::{open_databases_interactively, pair_key_files},
 };
 
 #[derive(clap::Args)]
@@ -22,33 +22,35 @@ pub struct Args {
     #[arg(short = 'P', long)]
     remove_key: bool,
 
-    /// KDBX file path
+    /// KDBX file path. Give it multiple times to search several databases
     #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
-    database: PathBuf,
+    database: Vec<PathBuf>,
 
-    /// Path to the key file unlocking the database
+    /// Path to the key file unlocking the database. With several databases,
+    /// one for each given with `-d`, in the same order
     #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
-    key_file: Option<PathBuf>,
+    key_file: Vec<PathBuf>,
+
+    /// A database unlocked with its own key file, next to those given with
+    /// `-d`. Can be repeated
+    #[arg(long, num_args = 2, value_names = ["DATABASE", "KEY_FILE"], value_hint = ValueHint::FilePath)]
+    pair: Vec<PathBuf>,
 }
 
 pub(crate) fn run(args: Args) -> Result<()> {
-    if !args.database.exists() {
+    let databases = pair_key_files(&args.database, &args.key_file, &args.pair)?;
+    if !databases.iter().all(|(database, _)| database.exists()) {
         return Err("File does not exist".to_string().into());
     }
-    let (db, _) = open_database_interactively(
-        &args.database,
-        args.key_file.as_deref(),
-        args.use_keyring,
-        args.remove_key,
-        false,
-    )?;
-
-    let entries = &get_entries(&db.root, "");
+    let dbs = open_databases_interactively(&databases, args.use_keyring, args.remove_key, false)?;
+
+    let entries = &get_all_entries(&dbs);
     for e in entries.iter() {
+        let marker = if e.is_expired() { EXPIRED_MARKER } else { "" };
         if args.no_group {
-            wout!("{}", e.get_title());
+            wout!("{}{}", e.get_title(), marker);
         } else {
-            wout!("{}", e.entry_path());
+            wout!("{}{}", e.entry_path(), marker);
         }
     }
 
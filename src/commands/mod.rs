@@ -1,7 +1,15 @@
 pub mod add;
+pub mod audit;
 pub mod completion;
+pub mod expire;
+pub mod expiring;
+pub mod import;
 pub mod init;
+pub mod keyfile;
+pub mod keyring;
 pub mod list;
+pub mod open;
 pub mod pwd;
 pub mod show;
 pub mod totp;
+pub mod tui;
@@ -0,0 +1,158 @@
+use std::{
+    io,
+    path::PathBuf,
+    sync::{Arc, Mutex, atomic},
+    thread, time,
+};
+
+use clap::ValueHint;
+
+use crate::{
+    CANCEL, Result, STDIN,
+    keepass::OpenedDatabase,
+    pwd::Pwd,
+    repl::{Ask, Editor, Flow, Input, Session},
+    utils::{is_tty, open_database_interactively, parse_duration},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Timeout in seconds before clearing the clipboard. 0 means no clean-up
+    #[arg(short, long, default_value_t = crate::DEFAULT_TIMEOUT)]
+    timeout: u8,
+
+    /// Lock the database after this long without a command, e.g. 10m.
+    /// Unsaved changes are kept until it's unlocked. 0 never locks
+    #[arg(long, value_name = "DURATION", default_value = "5m", value_parser = parse_duration)]
+    lock_after: chrono::TimeDelta,
+
+    /// Store password for the database in the OS's keyring
+    #[arg(short = 'p', long)]
+    use_keyring: bool,
+
+    /// Remove database's password from OS's keyring and exit
+    #[arg(short = 'P', long)]
+    remove_key: bool,
+
+    /// KDBX file path
+    #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
+    database: PathBuf,
+
+    /// Path to the key file unlocking the database
+    #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
+    key_file: Option<PathBuf>,
+}
+
+/// Asks on the terminal, which the line editor leaves in cooked mode
+struct Terminal;
+
+impl Ask for Terminal {
+    fn text(&mut self, prompt: &str) -> String {
+        put!("{prompt}");
+        STDIN.read_text()
+    }
+
+    fn secret(&mut self, prompt: &str) -> Pwd {
+        put!("{prompt}");
+        STDIN.read_password()
+    }
+}
+
+pub(crate) fn run(args: Args) -> Result<()> {
+    if !args.database.exists() {
+        return Err("File does not exist".to_string().into());
+    }
+    if !STDIN.is_tty() || !is_tty(io::stdout()) {
+        return Err("`open` needs a terminal".into());
+    }
+
+    let (db, credentials) = open_database_interactively(
+        &args.database,
+        args.key_file.as_deref(),
+        args.use_keyring,
+        args.remove_key,
+        false,
+    )?;
+    let name = args
+        .database
+        .file_stem()
+        .unwrap_or(args.database.as_os_str())
+        .to_string_lossy()
+        .into_owned();
+    let opened = OpenedDatabase {
+        name,
+        path: args.database,
+        key_file: args.key_file,
+        db,
+        credentials,
+    };
+
+    let lock_after = args
+        .lock_after
+        .to_std()
+        .ok()
+        .filter(|after| !after.is_zero());
+    let clipboard_timeout =
+        (args.timeout > 0).then(|| time::Duration::from_secs(u64::from(args.timeout)));
+    let session = Arc::new(Mutex::new(Session::new(
+        opened,
+        lock_after,
+        clipboard_timeout,
+    )));
+
+    if lock_after.is_some() || clipboard_timeout.is_some() {
+        let session = Arc::downgrade(&session);
+        thread::spawn(move || {
+            while let Some(session) = session.upgrade() {
+                if let Ok(mut session) = session.lock() {
+                    let now = time::Instant::now();
+                    session.clear_clipboard_if_due(now);
+                    session.lock_if_idle(now);
+                }
+                drop(session);
+                thread::sleep(time::Duration::from_secs(1));
+            }
+        });
+    }
+
+    wout!("Type `help` for the commands");
+    let result = run_loop(&session);
+    // the watchdog may hold the last reference when leaving, so the session
+    // could be dropped after the process exits
+    if let Ok(mut session) = session.lock() {
+        session.clear_clipboard();
+    }
+    result
+}
+
+fn run_loop(session: &Arc<Mutex<Session>>) -> Result<()> {
+    let mut editor = Editor::new(session.clone());
+
+    while !CANCEL.load(atomic::Ordering::SeqCst) {
+        let line = match editor.read_line()? {
+            Input::Line(line) => line,
+            Input::End => "exit".to_string(),
+            Input::Cancelled => continue,
+        };
+
+        let mut session = session.lock().map_err(|_| "Session poisoned")?;
+        if session.is_locked() {
+            // what was typed may be the password, so it's neither run nor kept
+            match session.unlock(Terminal.secret("Password: ")) {
+                Ok(()) => wout!("Unlocked"),
+                Err(e) => werr!("{e}"),
+            }
+            continue;
+        }
+        editor.remember(&line);
+
+        match session.execute(&line, &mut Terminal) {
+            Ok(Flow::Exit) => break,
+            Ok(Flow::Continue(output)) if output.is_empty() => {}
+            Ok(Flow::Continue(output)) => wout!("{output}"),
+            Err(e) => werr!("{e}"),
+        }
+    }
+
+    Ok(())
+}
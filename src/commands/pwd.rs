@@ -6,10 +6,10 @@ use log::*;
 
 use crate::{
     CANCEL, CANCEL_RQ_FREQ, Result,
-    clipboard::set_clipboard,
-    keepass::{find_entry, get_entries},
+    clipboard::{set_clipboard, unmarked_warning},
+    keepass::{EntryPath, find_entry_in, get_all_entries},
     pwd::Pwd,
-    utils::{is_tty, open_database_interactively, skim},
+    utils::{check_expiry, is_tty, open_databases_interactively, pair_key_files, skim},
 };
 
 #[derive(clap::Args)]
@@ -44,32 +44,45 @@ pub struct Args {
     #[arg(short = 'P', long)]
     remove_key: bool,
 
-    /// KDBX file path
+    /// Refuse to use an expired entry instead of warning
+    #[arg(long)]
+    strict: bool,
+
+    /// KDBX file path. Give it multiple times to search several databases
     #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
-    database: PathBuf,
+    database: Vec<PathBuf>,
 
-    /// Path to the key file unlocking the database
+    /// Path to the key file unlocking the database. With several databases,
+    /// one for each given with `-d`, in the same order
     #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
-    key_file: Option<PathBuf>,
+    key_file: Vec<PathBuf>,
+
+    /// A database unlocked with its own key file, next to those given with
+    /// `-d`. Can be repeated
+    #[arg(long, num_args = 2, value_names = ["DATABASE", "KEY_FILE"], value_hint = ValueHint::FilePath)]
+    pair: Vec<PathBuf>,
 }
 
 pub(crate) fn run(args: Args) -> Result<()> {
-    if !args.database.exists() {
+    let databases = pair_key_files(&args.database, &args.key_file, &args.pair)?;
+    if !databases.iter().all(|(database, _)| database.exists()) {
         return Err("File does not exist".to_string().into());
     }
-    let (db, _) = open_database_interactively(
-        &args.database,
-        args.key_file.as_deref(),
+    let dbs = open_databases_interactively(
+        &databases,
         args.use_keyring,
         args.remove_key,
         args.no_interaction,
     )?;
+    let entries = get_all_entries(&dbs);
 
     let query = args.entry.as_ref().map(String::as_ref);
 
     if let Some(query) = query
-        && let Some(entry) = find_entry(query, &db.root)
+        && let Some(entry) = find_entry_in(query, &entries).map(EntryPath::get_entry)
     {
+        check_expiry(entry, args.strict)?;
+
         // Print password to stdout when pipe used
         // e.g. `kdbx pwd example.com | cat`
         if !is_tty(io::stdout()) {
@@ -89,15 +102,16 @@ pub(crate) fn run(args: Args) -> Result<()> {
         return Err(format!("No single match for {}.", query.unwrap_or("[empty]")).into());
     }
 
-    if let Some(wrapped_entry) = skim(
-        &get_entries(&db.root, ""),
+    if let Some(sourced_entry) = skim(
+        &entries,
         query.map(String::from),
         args.no_group,
         args.preview,
         args.full_screen,
         false,
     ) {
-        clip(wrapped_entry.entry, args.timeout)?
+        check_expiry(sourced_entry.get_entry(), args.strict)?;
+        clip(sourced_entry.get_entry(), args.timeout)?
     }
 
     Ok(())
@@ -113,6 +127,9 @@ fn clip(entry: &Entry, timeout: u8) -> Result<()> {
         )
         .into());
     }
+    if let Some(warning) = unmarked_warning() {
+        werr!("{}", warning);
+    }
 
     if timeout == 0 {
         debug!("user decided to leave the password in the buffer");
@@ -4,8 +4,8 @@ use clap::ValueHint;
 
 use crate::{
     Result,
-    keepass::{find_entry, get_entries, show_entry},
-    utils::{open_database_interactively, skim},
+    keepass::{EntryPath, find_entry_in, get_all_entries, show_entry},
+    utils::{check_expiry, open_databases_interactively, pair_key_files, skim},
 };
 
 #[derive(clap::Args)]
@@ -40,31 +40,43 @@ pub struct Args {
     #[arg(long)]
     show_sensitive: bool,
 
-    /// KDBX file path
+    /// Refuse to use an expired entry instead of warning
+    #[arg(long)]
+    strict: bool,
+
+    /// KDBX file path. Give it multiple times to search several databases
     #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
-    database: PathBuf,
+    database: Vec<PathBuf>,
 
-    /// Path to the key file unlocking the database
+    /// Path to the key file unlocking the database. With several databases,
+    /// one for each given with `-d`, in the same order
     #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
-    key_file: Option<PathBuf>,
+    key_file: Vec<PathBuf>,
+
+    /// A database unlocked with its own key file, next to those given with
+    /// `-d`. Can be repeated
+    #[arg(long, num_args = 2, value_names = ["DATABASE", "KEY_FILE"], value_hint = ValueHint::FilePath)]
+    pair: Vec<PathBuf>,
 }
 
 pub(crate) fn run(args: Args) -> Result<()> {
-    if !args.database.exists() {
+    let databases = pair_key_files(&args.database, &args.key_file, &args.pair)?;
+    if !databases.iter().all(|(database, _)| database.exists()) {
         return Err("File does not exist".to_string().into());
     }
-    let (db, _) = open_database_interactively(
-        &args.database,
-        args.key_file.as_deref(),
+    let dbs = open_databases_interactively(
+        &databases,
         args.use_keyring,
         args.remove_key,
         args.no_interaction,
     )?;
+    let entries = get_all_entries(&dbs);
     let query = args.entry.as_ref().map(String::as_ref);
 
     if let Some(query) = query
-        && let Some(entry) = find_entry(query, &db.root)
+        && let Some(entry) = find_entry_in(query, &entries).map(EntryPath::get_entry)
     {
+        check_expiry(entry, args.strict)?;
         put!("{}", show_entry(entry, args.show_sensitive));
         return Ok(());
     }
@@ -73,15 +85,19 @@ pub(crate) fn run(args: Args) -> Result<()> {
         return Err("Not found".to_string().into());
     }
 
-    if let Some(wrapped_entry) = skim(
-        &get_entries(&db.root, ""),
+    if let Some(sourced_entry) = skim(
+        &entries,
         query.map(String::from),
         args.no_group,
         args.preview,
         args.full_screen,
         false,
     ) {
-        put!("{}", show_entry(wrapped_entry.entry, args.show_sensitive));
+        check_expiry(sourced_entry.get_entry(), args.strict)?;
+        put!(
+            "{}",
+            show_entry(sourced_entry.get_entry(), args.show_sensitive)
+        );
         return Ok(());
     }
 
@@ -1,18 +1,20 @@
-use std::{io, path::PathBuf};
+use std::{io, path::PathBuf, sync::atomic, thread, time};
 
 use clap::ValueHint;
-use keepass::{
-    db::{Entry, TOTP},
-    error::TOTPError,
-};
-use url::{Url, form_urlencoded};
+use keepass::db::{Entry, Value};
+use url::Url;
 
 use crate::{
-    Result,
-    clipboard::set_clipboard,
-    keepass::{find_entry, get_entries},
+    CANCEL, CANCEL_RQ_FREQ, Result, STDIN,
+    clipboard::{set_clipboard, unmarked_warning},
+    keepass::{
+        EntryPath, OpenedDatabase, SourcedEntry, find_entry_by_uuid_mut, find_entry_in,
+        get_all_entries,
+    },
+    otp::{otpauth, parse_totp, raw_otp},
     pwd::Pwd,
-    utils::{is_tty, open_database_interactively, skim},
+    qr,
+    utils::{ask_password_to_save, is_tty, open_databases_interactively, pair_key_files, skim},
 };
 
 #[derive(clap::Args)]
@@ -35,6 +37,31 @@ pub struct Args {
     #[arg(long)]
     raw: bool,
 
+    /// Keep showing codes with a countdown and the next code for matching
+    /// entries (all entries with TOTP when none given)
+    #[arg(short, long, conflicts_with_all = ["raw", "qr"])]
+    watch: bool,
+
+    /// Compute codes for the given unix timestamp instead of now
+    #[arg(long, value_name = "TIMESTAMP")]
+    at: Option<u64>,
+
+    /// Render the entry's otpauth:// URI as a QR code instead of copying
+    #[arg(long)]
+    qr: bool,
+
+    /// Also write the QR code to a PNG file
+    #[arg(long, value_name = "FILE", requires = "qr", value_hint = ValueHint::FilePath)]
+    qr_png: Option<PathBuf>,
+
+    /// Render the QR code even when stdout is not a terminal
+    #[arg(long, requires = "qr")]
+    force: bool,
+
+    /// Attach TOTP read from a QR code image (PNG/JPEG) to the entry
+    #[arg(long, value_name = "IMAGE", value_hint = ValueHint::FilePath)]
+    set_qr: Option<PathBuf>,
+
     /// Use all available screen for picker
     #[arg(short, long)]
     full_screen: bool,
@@ -47,40 +74,62 @@ pub struct Args {
     #[arg(short = 'P', long)]
     remove_key: bool,
 
-    /// KDBX file path
+    /// KDBX file path. Give it multiple times to search several databases
     #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
-    database: PathBuf,
+    database: Vec<PathBuf>,
 
-    /// Path to the key file unlocking the database
+    /// Path to the key file unlocking the database. With several databases,
+    /// one for each given with `-d`, in the same order
     #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
-    key_file: Option<PathBuf>,
+    key_file: Vec<PathBuf>,
+
+    /// A database unlocked with its own key file, next to those given with
+    /// `-d`. Can be repeated
+    #[arg(long, num_args = 2, value_names = ["DATABASE", "KEY_FILE"], value_hint = ValueHint::FilePath)]
+    pair: Vec<PathBuf>,
 }
 
 pub(crate) fn run(args: Args) -> Result<()> {
-    if !args.database.exists() {
+    let databases = pair_key_files(&args.database, &args.key_file, &args.pair)?;
+    if !databases.iter().all(|(database, _)| database.exists()) {
         return Err("File does not exist".to_string().into());
     }
-    let (db, _) = open_database_interactively(
-        &args.database,
-        args.key_file.as_deref(),
+    let uri = args.set_qr.as_deref().map(qr::decode_otpauth).transpose()?;
+
+    let dbs = open_databases_interactively(
+        &databases,
         args.use_keyring,
         args.remove_key,
         args.no_interaction,
     )?;
 
+    if let Some(uri) = uri {
+        return attach(dbs, &args, uri);
+    }
+
+    let entries = get_all_entries(&dbs);
+
+    if args.watch {
+        return watch(&entries, &args);
+    }
+
     let query = args.entry.as_ref().map(String::as_ref);
 
     if let Some(query) = query
-        && let Some(entry) = find_entry(query, &db.root)
+        && let Some(entry) = find_entry_in(query, &entries).map(EntryPath::get_entry)
     {
+        if args.qr {
+            return show_qr(entry, &args);
+        }
+
         // Print totp to stdout when pipe used
         // e.g. `kdbx totp example.com | cat`
         if !is_tty(io::stdout()) {
-            let totp = get_totp(entry, args.raw)?;
-            put!("{}", totp.as_ref());
+            let totp = get_totp(entry, args.raw, args.at)?;
+            put!("{}", totp.expose());
             return Ok(());
         }
-        return clip(entry, args.raw);
+        return clip(entry, args.raw, args.at);
     }
 
     if args.no_interaction {
@@ -93,139 +142,259 @@ pub(crate) fn run(args: Args) -> Result<()> {
         return Err(format!("No single match for {}.", query.unwrap_or("[empty]")).into());
     }
 
-    if let Some(wrapped_entry) = skim(
-        &get_entries(&db.root, ""),
+    if let Some(sourced_entry) = skim(
+        &entries,
         query.map(String::from),
         args.no_group,
         args.preview,
         args.full_screen,
         true,
     ) {
-        clip(wrapped_entry.entry, args.raw)?
+        if args.qr {
+            return show_qr(sourced_entry.get_entry(), &args);
+        }
+        clip(sourced_entry.get_entry(), args.raw, args.at)?
     }
 
     Ok(())
 }
 
-fn clip(entry: &Entry, raw: bool) -> Result<()> {
-    let totp = get_totp(entry, raw)?;
-    if set_clipboard(Some(totp)).is_err() {
-        return Err(format!(
-            "Clipboard unavailable. Try use STDOUT, i.e. `kdbx totp '{}' | cat`.",
-            entry.get_title().unwrap_or_default()
-        )
-        .into());
-    }
+fn attach(mut dbs: Vec<OpenedDatabase>, args: &Args, uri: Pwd) -> Result<()> {
+    let query = args.entry.as_ref().map(String::as_ref);
+    let entries = get_all_entries(&dbs);
+
+    let (idx, uuid) = match query.and_then(|query| find_entry_in(query, &entries)) {
+        Some(sourced_entry) => (sourced_entry.db, *sourced_entry.get_entry().get_uuid()),
+        None if args.no_interaction => return Err("Not found".to_string().into()),
+        None => match skim(
+            &entries,
+            query.map(String::from),
+            args.no_group,
+            args.preview,
+            args.full_screen,
+            false,
+        ) {
+            Some(sourced_entry) => (sourced_entry.db, *sourced_entry.get_entry().get_uuid()),
+            None => return Ok(()),
+        },
+    };
+    drop(entries);
+
+    // Only the database the entry comes from is written back
+    let mut opened = dbs.swap_remove(idx);
+    let entry = find_entry_by_uuid_mut(&mut opened.db.root, &uuid).expect("picked entry exists");
+    entry.fields.insert(
+        "otp".to_string(),
+        Value::Protected(uri.expose().as_bytes().into()),
+    );
+    entry.update_history();
+
+    ask_password_to_save(
+        &opened.path,
+        opened.key_file.as_deref(),
+        &mut opened.credentials,
+    )?;
+    opened.save()?;
 
     Ok(())
 }
 
-fn get_totp(entry: &Entry, raw: bool) -> Result<Pwd> {
-    let raw_value = entry
-        .get_raw_otp_value()
-        .ok_or_else(|| "Entry has no TOTP secret".to_string())?;
-    let trimmed = raw_value.trim();
+fn show_qr(entry: &Entry, args: &Args) -> Result<()> {
+    let uri = otpauth(entry).ok_or_else(|| "Entry has no TOTP secret".to_string())?;
+    parse_totp(uri.expose())?;
+    let uri = match Url::parse(uri.expose()) {
+        Ok(url) => Pwd::from(String::from(url)),
+        Err(_) => uri,
+    };
 
-    if trimmed.is_empty() {
-        return Err("Entry has no TOTP secret".to_string().into());
+    if let Some(path) = args.qr_png.as_deref() {
+        qr::write_png(uri.expose(), path)?;
     }
 
-    if raw {
-        return Ok(trimmed.to_string().into());
+    if !is_tty(io::stdout()) && !args.force {
+        if args.qr_png.is_some() {
+            return Ok(());
+        }
+        return Err(
+            "Refusing to print a secret QR code to a non-terminal. Use --force."
+                .to_string()
+                .into(),
+        );
     }
 
-    let code = parse_totp(trimmed)?
-        .value_now()
-        .map_err(|e| format!("Unable to compute TOTP: {e}"))?
-        .code;
+    wout!("{}", qr::render_terminal(uri.expose())?);
 
-    Ok(code.into())
+    Ok(())
 }
 
-fn parse_totp(raw_value: &str) -> Result<TOTP> {
-    match raw_value.parse::<TOTP>() {
-        Ok(otp) => Ok(otp),
-        Err(TOTPError::Base32) => {
-            let Some(normalized) = normalize_totp_secret(raw_value) else {
-                return Err("Unable to read TOTP: Base32 decoding error"
-                    .to_string()
-                    .into());
-            };
-
-            normalized
-                .parse::<TOTP>()
-                .map_err(|e| format!("Unable to read TOTP: {e}").into())
-        }
-        Err(err) => Err(format!("Unable to read TOTP: {err}").into()),
-    }
-}
+fn watch(entries: &[SourcedEntry], args: &Args) -> Result<()> {
+    let query = args.entry.as_deref().unwrap_or_default();
+    let entries = entries
+        .iter()
+        .filter(|e| e.has_totp() && e.entry_path().contains(query))
+        .collect::<Vec<_>>();
 
-fn normalize_totp_secret(raw_value: &str) -> Option<String> {
-    let mut url = Url::parse(raw_value).ok()?;
-    let mut pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
-    let mut changed = false;
+    if entries.is_empty() {
+        return Err("Not found".to_string().into());
+    }
 
-    for (key, value) in pairs.iter_mut() {
-        if key != "secret" {
-            continue;
+    let titles = entries
+        .iter()
+        .map(|e| {
+            if args.no_group {
+                e.get_title()
+            } else {
+                e.entry_path()
+            }
+        })
+        .collect::<Vec<_>>();
+    let otps = entries
+        .iter()
+        .map(|e| {
+            raw_otp(e.get_entry()).map_or_else(
+                || Err("no TOTP secret".into()),
+                |raw| parse_totp(raw.expose()),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let started = time::Instant::now();
+    let clock = || match args.at {
+        Some(at) => at + started.elapsed().as_secs(),
+        None => time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    };
+
+    let render = |now: u64| {
+        let width = titles
+            .iter()
+            .map(|t| t.chars().count())
+            .max()
+            .unwrap_or_default();
+        titles
+            .iter()
+            .zip(otps.iter())
+            .enumerate()
+            .map(|(idx, (title, otp))| {
+                let codes = otp.as_ref().map_err(ToString::to_string).and_then(|otp| {
+                    let current = otp.value_at(now).map_err(|e| e.to_string())?;
+                    let next = otp
+                        .value_at(now + current.valid_for)
+                        .map_err(|e| e.to_string())?;
+                    Ok((current, next))
+                });
+                match codes {
+                    Ok((current, next)) => format!(
+                        "{:>2}) {title:<width$}  {}  {:>2}s  next: {}",
+                        idx + 1,
+                        current.code,
+                        current.valid_for,
+                        next.code
+                    ),
+                    Err(e) => format!("{:>2}) {title:<width$}  {e}", idx + 1),
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // Print a single snapshot when pipe used
+    // e.g. `kdbx totp --watch --at 0 | cat`
+    if !is_tty(io::stdout()) {
+        for line in render(clock()) {
+            wout!("{}", line);
         }
+        return Ok(());
+    }
 
-        let normalized = normalize_base32_secret(value);
-        if normalized != *value {
-            *value = normalized;
-            changed = true;
+    let hint = if STDIN.is_tty() {
+        "Press 1-9 to copy a code, q to quit"
+    } else {
+        "Press Ctrl+C to quit"
+    };
+    let mut status = String::new();
+    let mut ticks: u64 = 0;
+
+    while !CANCEL.load(atomic::Ordering::SeqCst) {
+        if ticks.is_multiple_of(CANCEL_RQ_FREQ) {
+            if ticks > 0 {
+                // Move the cursor back to redraw the same lines in place
+                put!("\x1B[{}A", titles.len() + 1);
+            }
+            let now = clock();
+            for line in render(now) {
+                put!("\x1B[2K{}\n", line);
+            }
+            put!("\x1B[2K{} {}\n", hint, status);
         }
-    }
 
-    if !changed {
-        return None;
-    }
+        if !STDIN.is_tty() {
+            thread::sleep(time::Duration::from_millis(1_000 / CANCEL_RQ_FREQ));
+        } else if let Some(key) = STDIN.read_key(1) {
+            match key {
+                b'q' | b'Q' => break,
+                b'1'..=b'9' => {
+                    let idx = usize::from(key - b'1');
+                    if let Some(Ok(otp)) = otps.get(idx) {
+                        status = match otp.value_at(clock()) {
+                            Ok(current) => match set_clipboard(Some(current.code.into())) {
+                                Ok(()) => match unmarked_warning() {
+                                    Some(warning) => format!("(copied {}. {warning})", titles[idx]),
+                                    None => format!("(copied {})", titles[idx]),
+                                },
+                                Err(_) => "(clipboard unavailable)".to_string(),
+                            },
+                            Err(e) => format!("({e})"),
+                        };
+                        // Redraw right away to show the status
+                        ticks = ticks.next_multiple_of(CANCEL_RQ_FREQ);
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+        }
 
-    let mut serializer = form_urlencoded::Serializer::new(String::new());
-    for (key, value) in pairs {
-        serializer.append_pair(&key, &value);
+        ticks += 1;
     }
 
-    url.set_query(Some(&serializer.finish()));
+    STDIN.reset_tty();
 
-    Some(url.to_string())
+    Ok(())
 }
 
-fn normalize_base32_secret(secret: &str) -> String {
-    let mut normalized: String = secret
-        .chars()
-        .filter(|c| !c.is_whitespace() && *c != '-')
-        .map(|c| c.to_ascii_uppercase())
-        .collect();
-
-    if !normalized.len().is_multiple_of(8) {
-        normalized.push_str(&"=".repeat(8 - (normalized.len() % 8)));
+fn clip(entry: &Entry, raw: bool, at: Option<u64>) -> Result<()> {
+    let totp = get_totp(entry, raw, at)?;
+    if set_clipboard(Some(totp)).is_err() {
+        return Err(format!(
+            "Clipboard unavailable. Try use STDOUT, i.e. `kdbx totp '{}' | cat`.",
+            entry.get_title().unwrap_or_default()
+        )
+        .into());
+    }
+    if let Some(warning) = unmarked_warning() {
+        werr!("{}", warning);
     }
 
-    normalized
+    Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use keepass::db::Value;
-
-    use super::*;
-
-    #[test]
-    fn parses_secret_with_spaces() {
-        let mut entry = Entry::new();
-        entry.fields.insert(
-            "otp".to_string(),
-            Value::Unprotected(
-                "otpauth://totp/example:demo?secret=JBSW%20Y3DP%20EHPK%203PXP&issuer=example&\
-                 digits=6"
-                    .to_string(),
-            ),
-        );
+fn get_totp(entry: &Entry, raw: bool, at: Option<u64>) -> Result<Pwd> {
+    let raw_value = raw_otp(entry).ok_or_else(|| "Entry has no TOTP secret".to_string())?;
 
-        let otp = parse_totp(entry.get_raw_otp_value().unwrap()).expect("parsed totp");
+    if raw {
+        return Ok(raw_value);
+    }
 
-        assert_eq!(otp.get_secret(), "JBSWY3DPEHPK3PXP");
-        assert_eq!(otp.value_at(0).code.len(), 6);
+    let otp = parse_totp(raw_value.expose())?;
+    let code = match at {
+        Some(time) => otp.value_at(time)?,
+        None => otp
+            .value_now()
+            .map_err(|e| format!("Unable to compute TOTP: {e}"))?,
     }
+    .code;
+
+    Ok(code.into())
 }
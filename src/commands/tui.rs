@@ -0,0 +1,103 @@
+use std::{io, path::PathBuf, sync::atomic, time};
+
+use clap::ValueHint;
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+
+use crate::{
+    CANCEL, CANCEL_RQ_FREQ, Result, STDIN,
+    keepass::OpenedDatabase,
+    tui::{self, App},
+    utils::{is_tty, open_database_interactively, parse_duration},
+};
+
+#[derive(clap::Args)]
+pub struct Args {
+    /// Timeout in seconds before clearing the clipboard. 0 means no clean-up
+    #[arg(short, long, default_value_t = crate::DEFAULT_TIMEOUT)]
+    timeout: u8,
+
+    /// Lock the database after this long without a key press, e.g. 10m.
+    /// 0 never locks
+    #[arg(long, value_name = "DURATION", default_value = "5m", value_parser = parse_duration)]
+    lock_after: chrono::TimeDelta,
+
+    /// Store password for the database in the OS's keyring
+    #[arg(short = 'p', long)]
+    use_keyring: bool,
+
+    /// Remove database's password from OS's keyring and exit
+    #[arg(short = 'P', long)]
+    remove_key: bool,
+
+    /// KDBX file path
+    #[arg(short, long, env = "KDBX_DATABASE", value_hint = ValueHint::FilePath)]
+    database: PathBuf,
+
+    /// Path to the key file unlocking the database
+    #[arg(short, long, env = "KDBX_KEY_FILE", value_hint = ValueHint::FilePath)]
+    key_file: Option<PathBuf>,
+}
+
+pub(crate) fn run(args: Args) -> Result<()> {
+    if !args.database.exists() {
+        return Err("File does not exist".to_string().into());
+    }
+    if !STDIN.is_tty() || !is_tty(io::stdout()) {
+        return Err("The TUI needs a terminal".into());
+    }
+
+    let (db, credentials) = open_database_interactively(
+        &args.database,
+        args.key_file.as_deref(),
+        args.use_keyring,
+        args.remove_key,
+        false,
+    )?;
+    let name = args
+        .database
+        .file_stem()
+        .unwrap_or(args.database.as_os_str())
+        .to_string_lossy()
+        .into_owned();
+    let opened = OpenedDatabase {
+        name,
+        path: args.database,
+        key_file: args.key_file,
+        db,
+        credentials,
+    };
+
+    let lock_after = args
+        .lock_after
+        .to_std()
+        .ok()
+        .filter(|after| !after.is_zero());
+    let clipboard_timeout =
+        (args.timeout > 0).then(|| time::Duration::from_secs(u64::from(args.timeout)));
+    let mut app = App::new(opened, lock_after, clipboard_timeout);
+
+    let mut terminal = ratatui::init();
+    let result = run_loop(&mut terminal, &mut app);
+    ratatui::restore();
+    STDIN.reset_tty();
+
+    result
+}
+
+fn run_loop(terminal: &mut ratatui::DefaultTerminal, app: &mut App) -> Result<()> {
+    let tick = time::Duration::from_millis(1_000 / CANCEL_RQ_FREQ);
+
+    while !app.should_quit() && !CANCEL.load(atomic::Ordering::SeqCst) {
+        terminal.draw(|frame| tui::draw(frame, app))?;
+
+        if event::poll(tick)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            app.handle_key(key);
+        }
+        app.tick(time::Instant::now());
+    }
+
+    Ok(())
+}
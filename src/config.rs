@@ -0,0 +1,458 @@
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{Arg, ArgAction, ArgMatches, Command, Id, builder::ArgPredicate};
+use log::*;
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use crate::Result;
+
+const CONFIG_ENV: &str = "KDBX_CONFIG";
+const PROFILE_ENV: &str = "KDBX_PROFILE";
+
+/// User configuration, read from `$XDG_CONFIG_HOME/kdbx/config.toml`.
+///
+/// Keys are long flag names. They become the flags' default values, so
+/// anything given on the command line still wins. Switches turned on get a
+/// `--no-<flag>` (`--<flag>` for `no-` ones) to turn them off again:
+///
+/// ```toml
+/// [defaults]
+/// no-group = true
+///
+/// [commands.pwd]
+/// timeout = 30
+///
+/// [profiles.work]
+/// database = "~/work.kdbx"
+/// key-file = "~/.keys/work.key"
+/// use-keyring = true
+///
+/// [profiles.all]
+/// profiles = ["personal", "work"]
+/// ```
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Flags applied to every command accepting them
+    defaults: Table,
+    /// Flags applied to a single command
+    commands: HashMap<String, Table>,
+    /// Named sets of flags selected with `--profile`
+    profiles: HashMap<String, Table>,
+}
+
+impl Config {
+    pub fn load() -> Result<Config> {
+        let Some(path) = config_path() else {
+            return Ok(Config::default());
+        };
+        if !path.exists() {
+            debug!("no config file at {}", path.to_string_lossy());
+            return Ok(Config::default());
+        }
+
+        let contents = fs::read_to_string(&path)?;
+        toml::from_str(&contents)
+            .map_err(|e| format!("Invalid config `{}`: {e}", path.to_string_lossy()).into())
+    }
+
+    /// Builds the command line matches, using the config as flags' defaults
+    pub fn get_matches(&self, cmd: Command) -> Result<ArgMatches> {
+        let args = env::args_os().collect::<Vec<_>>();
+        let profile = find_profile(&args).or_else(|| env::var(PROFILE_ENV).ok());
+
+        let cmd = self.apply(cmd, profile.as_deref())?;
+
+        Ok(cmd.get_matches_from(args))
+    }
+
+    fn apply(&self, mut cmd: Command, profile: Option<&str>) -> Result<Command> {
+        cmd = set_defaults(cmd, None, &self.defaults, false)?;
+
+        for (name, flags) in self.commands.iter() {
+            if cmd.find_subcommand(name).is_none() {
+                return Err(format!("Invalid config: unknown command `{name}`").into());
+            }
+            cmd = set_defaults(cmd, Some(name), flags, false)?;
+        }
+
+        if let Some(profile) = profile {
+            let flags = self.profile(profile)?;
+            // a profile is picked explicitly, so it overrides the environment
+            cmd = set_defaults(cmd, None, &flags, true)?;
+        }
+
+        Ok(cmd)
+    }
+
+    /// Flags of the profile. A group profile (one listing other `profiles`)
+    /// gets the databases and key files of all its members.
+    fn profile(&self, name: &str) -> Result<Table> {
+        let get = |name: &str| {
+            self.profiles
+                .get(name)
+                .ok_or_else(|| format!("Profile `{name}` not found"))
+        };
+        let mut flags = get(name)?.clone();
+
+        let Some(members) = flags.remove("profiles") else {
+            return Ok(flags);
+        };
+        let Value::Array(members) = members else {
+            return Err("Invalid config: `profiles` must be a list".into());
+        };
+
+        let mut databases = Vec::new();
+        let mut pairs = Vec::new();
+        for name in members.iter() {
+            let name = name
+                .as_str()
+                .ok_or("Invalid config: `profiles` must be a list of names")?;
+            let member = get(name)?;
+            if member.contains_key("profiles") {
+                return Err("Invalid config: profile groups can't be nested".into());
+            }
+
+            let database = member.get("database").map(as_list).unwrap_or_default();
+            let key_file = member.get("key-file").map(as_list).unwrap_or_default();
+            if !key_file.is_empty() && key_file.len() != database.len() {
+                return Err(format!(
+                    "Invalid config: profile `{name}` needs a key file for each database"
+                )
+                .into());
+            }
+
+            // Members' key files are paired with their databases, as
+            // `--pair DATABASE KEY_FILE`
+            if key_file.is_empty() {
+                databases.extend(database);
+            } else {
+                pairs.extend(database.into_iter().zip(key_file).flat_map(<[_; 2]>::from));
+            }
+        }
+
+        if !databases.is_empty() {
+            flags.insert("database".to_string(), Value::Array(databases));
+        }
+        if !pairs.is_empty() {
+            flags.insert("pair".to_string(), Value::Array(pairs));
+        }
+
+        Ok(flags)
+    }
+}
+
+fn as_list(value: &Value) -> Vec<Value> {
+    match value {
+        Value::Array(values) => values.clone(),
+        value => vec![value.clone()],
+    }
+}
+
+fn config_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os(CONFIG_ENV) {
+        return Some(PathBuf::from(path));
+    }
+
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    Some(dir.join(crate::BIN_NAME).join("config.toml"))
+}
+
+/// Looks for `--profile NAME` before clap parses anything, since the
+/// defaults depend on it
+fn find_profile(args: &[OsString]) -> Option<String> {
+    let mut args = args.iter().skip(1).map(|arg| arg.to_string_lossy());
+
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--profile" {
+            return args.next().map(|name| name.into_owned());
+        }
+        if let Some(name) = arg.strip_prefix("--profile=") {
+            return Some(name.to_string());
+        }
+    }
+
+    None
+}
+
+/// Sets the flags as defaults of the given subcommand or, when none, of
+/// every subcommand having them
+fn set_defaults(
+    mut cmd: Command,
+    subcommand: Option<&str>,
+    flags: &Table,
+    override_env: bool,
+) -> Result<Command> {
+    for (flag, value) in flags.iter() {
+        let values = to_values(flag, value)?;
+        let mut found = false;
+
+        // Global flags belong to the top command and are propagated from there
+        let global = cmd
+            .get_arguments()
+            .find(|arg| arg.is_global_set() && arg.get_long() == Some(flag.as_str()))
+            .map(|arg| arg.get_id().clone());
+        if subcommand.is_none()
+            && let Some(id) = global
+        {
+            let negatable = is_switch_on(&cmd, &id, &values);
+            cmd = cmd.mut_arg(&id, |arg| {
+                let arg = arg.default_values(values.clone());
+                if override_env { arg.env(None) } else { arg }
+            });
+            if negatable {
+                cmd = add_negation(cmd, &id, flag, true);
+            }
+            continue;
+        }
+
+        let names = cmd
+            .get_subcommands()
+            .map(|sc| sc.get_name().to_string())
+            .filter(|name| subcommand.is_none_or(|subcommand| subcommand == name))
+            .collect::<Vec<_>>();
+
+        for name in names {
+            cmd = cmd.mut_subcommand(&name, |sc| {
+                // Several values only fit flags which can be repeated
+                let Some(id) = sc
+                    .get_arguments()
+                    .find(|arg| arg.get_long() == Some(flag.as_str()))
+                    .filter(|arg| values.len() < 2 || matches!(arg.get_action(), ArgAction::Append))
+                    .map(|arg| arg.get_id().clone())
+                else {
+                    return sc;
+                };
+
+                found = true;
+                let negatable = is_switch_on(&sc, &id, &values);
+                let sc = sc.mut_arg(&id, |arg| {
+                    let arg = arg.default_values(values.clone()).required(false);
+                    if override_env { arg.env(None) } else { arg }
+                });
+                if negatable {
+                    add_negation(sc, &id, flag, false)
+                } else {
+                    sc
+                }
+            });
+        }
+
+        if !found {
+            return Err(format!("Invalid config: unknown flag `{flag}`").into());
+        }
+    }
+
+    Ok(cmd)
+}
+
+fn is_switch_on(cmd: &Command, id: &Id, values: &[String]) -> bool {
+    values == ["true"]
+        && cmd
+            .get_arguments()
+            .any(|arg| arg.get_id() == id && matches!(arg.get_action(), ArgAction::SetTrue))
+}
+
+/// A switch turned on by default has no way to be turned off, so it gets an
+/// opposite flag bringing it back to `false`
+fn add_negation(cmd: Command, id: &Id, flag: &str, global: bool) -> Command {
+    let long = match flag.strip_prefix("no-") {
+        Some(flag) => flag.to_string(),
+        None => format!("no-{flag}"),
+    };
+    if cmd
+        .get_arguments()
+        .any(|arg| arg.get_long() == Some(long.as_str()))
+    {
+        return cmd;
+    }
+
+    let negation = format!("{id}_off");
+    cmd.arg(
+        Arg::new(negation.clone())
+            .long(long)
+            .action(ArgAction::SetTrue)
+            .global(global)
+            .help(format!("Turn off `--{flag}` set in the config")),
+    )
+    .mut_arg(id, |arg| {
+        arg.default_value_if(negation, ArgPredicate::Equals("true".into()), Some("false"))
+    })
+}
+
+fn to_values(flag: &str, value: &Value) -> Result<Vec<String>> {
+    match value {
+        Value::String(s) => Ok(vec![expand_home(s)]),
+        Value::Integer(i) => Ok(vec![i.to_string()]),
+        Value::Float(f) => Ok(vec![f.to_string()]),
+        Value::Boolean(b) => Ok(vec![b.to_string()]),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Array(_) | Value::Table(_) => {
+                    Err(format!("Invalid config: nested value for `{flag}`").into())
+                }
+                value => to_values(flag, value).map(|mut v| v.remove(0)),
+            })
+            .collect(),
+        _ => Err(format!("Invalid config: unsupported value for `{flag}`").into()),
+    }
+}
+
+fn expand_home(value: &str) -> String {
+    match (value.strip_prefix("~/"), env::var("HOME")) {
+        (Some(rest), Ok(home)) => Path::new(&home).join(rest).to_string_lossy().into_owned(),
+        _ => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+    use crate::{Cli, keyring::Backend};
+
+    const CONFIG: &str = r#"
+        [defaults]
+        no-group = true
+        database = "/tmp/default.kdbx"
+        keyring-backend = "none"
+
+        [commands.pwd]
+        timeout = 30
+
+        [profiles.work]
+        database = "/tmp/work.kdbx"
+        key-file = "/tmp/work.key"
+        use-keyring = true
+    "#;
+
+    fn parse(config: &str, args: &[&str]) -> ArgMatches {
+        let config: Config = toml::from_str(config).expect("valid config");
+        let profile = find_profile(&args.iter().map(OsString::from).collect::<Vec<_>>());
+        let cmd = config
+            .apply(Cli::command(), profile.as_deref())
+            .expect("applied config");
+
+        let matches = cmd.try_get_matches_from(args).expect("parsed args");
+        matches.subcommand().expect("subcommand").1.clone()
+    }
+
+    #[test]
+    fn uses_config_as_defaults() {
+        let matches = parse(CONFIG, &["kdbx", "pwd"]);
+
+        assert_eq!(matches.get_one::<u8>("timeout"), Some(&30));
+        assert_eq!(matches.get_one::<bool>("no_group"), Some(&true));
+        assert_eq!(matches.get_one::<bool>("use_keyring"), Some(&false));
+        assert_eq!(
+            matches.get_one::<Backend>("keyring_backend"),
+            Some(&Backend::None)
+        );
+        assert_eq!(
+            matches.get_one::<PathBuf>("database"),
+            Some(&PathBuf::from("/tmp/default.kdbx"))
+        );
+    }
+
+    #[test]
+    fn explicit_flags_win() {
+        let matches = parse(
+            CONFIG,
+            &[
+                "kdbx",
+                "pwd",
+                "--profile",
+                "work",
+                "-t",
+                "5",
+                "-d",
+                "a.kdbx",
+            ],
+        );
+
+        assert_eq!(matches.get_one::<u8>("timeout"), Some(&5));
+        assert_eq!(matches.get_one::<bool>("use_keyring"), Some(&true));
+        assert_eq!(
+            matches.get_one::<PathBuf>("database"),
+            Some(&PathBuf::from("a.kdbx"))
+        );
+        assert_eq!(
+            matches.get_one::<PathBuf>("key_file"),
+            Some(&PathBuf::from("/tmp/work.key"))
+        );
+    }
+
+    #[test]
+    fn merges_profile_group() {
+        let config = r#"
+            [profiles.personal]
+            database = "/tmp/personal.kdbx"
+
+            [profiles.work]
+            database = "/tmp/work.kdbx"
+            key-file = "/tmp/work.key"
+
+            [profiles.all]
+            profiles = ["personal", "work"]
+        "#;
+        let matches = parse(config, &["kdbx", "list", "--profile", "all"]);
+
+        assert_eq!(
+            matches
+                .get_many::<PathBuf>("database")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            [&PathBuf::from("/tmp/personal.kdbx")]
+        );
+        assert_eq!(
+            matches
+                .get_many::<PathBuf>("pair")
+                .unwrap()
+                .collect::<Vec<_>>(),
+            [
+                &PathBuf::from("/tmp/work.kdbx"),
+                &PathBuf::from("/tmp/work.key")
+            ]
+        );
+        assert!(matches.get_many::<PathBuf>("key_file").is_none());
+    }
+
+    #[test]
+    fn turns_off_configured_switches() {
+        let matches = parse(CONFIG, &["kdbx", "pwd", "--group"]);
+        assert_eq!(matches.get_one::<bool>("no_group"), Some(&false));
+
+        let matches = parse(
+            CONFIG,
+            &["kdbx", "pwd", "--profile", "work", "--no-use-keyring"],
+        );
+        assert_eq!(matches.get_one::<bool>("use_keyring"), Some(&false));
+
+        let matches = parse(CONFIG, &["kdbx", "pwd", "--profile", "work", "--no-group"]);
+        assert_eq!(matches.get_one::<bool>("no_group"), Some(&true));
+        assert_eq!(matches.get_one::<bool>("use_keyring"), Some(&true));
+    }
+
+    #[test]
+    fn rejects_unknown_flags() {
+        let config: Config = toml::from_str("[commands.list]\ntimeout = 3").unwrap();
+
+        assert!(config.apply(Cli::command(), None).is_err());
+    }
+}
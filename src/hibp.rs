@@ -0,0 +1,115 @@
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{self, BufRead, BufReader, Seek, SeekFrom},
+    path::Path,
+};
+
+use sha1::{Digest, Sha1};
+
+const HASH_LEN: usize = 40;
+
+/// Have I Been Pwned "ordered by hash" SHA-1 list (`HASH:COUNT` per line),
+/// searched in place so multi-GB files are never loaded into memory
+pub struct HashList {
+    file: File,
+    len: u64,
+}
+
+impl HashList {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        Ok(HashList { file, len })
+    }
+
+    /// Returns how many times the password appears in breaches, if at all
+    pub fn count(&mut self, password: &str) -> io::Result<Option<u64>> {
+        let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        self.find(&hash)
+    }
+
+    fn find(&mut self, hash: &str) -> io::Result<Option<u64>> {
+        // The matching line, if any, always starts within [lo, hi)
+        let (mut lo, mut hi) = (0, self.len);
+
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let Some((start, line)) = self.line_at(mid)? else {
+                hi = mid;
+                continue;
+            };
+            if start >= hi {
+                hi = mid;
+                continue;
+            }
+
+            let (line_hash, count) = line.trim_end().split_once(':').unwrap_or((&line, ""));
+            match hash.cmp(&line_hash.to_ascii_uppercase()) {
+                Ordering::Equal => return Ok(Some(count.trim().parse().unwrap_or(1))),
+                Ordering::Less => hi = mid,
+                Ordering::Greater => lo = start + line.len() as u64,
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads the first whole line starting at or after `pos`
+    fn line_at(&mut self, pos: u64) -> io::Result<Option<(u64, String)>> {
+        let start = if pos == 0 {
+            0
+        } else {
+            self.file.seek(SeekFrom::Start(pos - 1))?;
+            let mut skipped = Vec::new();
+            BufReader::new(&self.file).read_until(b'\n', &mut skipped)?;
+            pos - 1 + skipped.len() as u64
+        };
+
+        if start >= self.len {
+            return Ok(None);
+        }
+
+        self.file.seek(SeekFrom::Start(start))?;
+        let mut line = String::with_capacity(HASH_LEN + 16);
+        BufReader::new(&self.file).read_line(&mut line)?;
+
+        Ok(Some((start, line)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn finds_every_hash() {
+        let path = std::env::temp_dir().join(format!("kdbx-hibp-{}.txt", std::process::id()));
+        let mut passwords = (0..500)
+            .map(|i| {
+                let password = format!("password{i}");
+                (hex::encode_upper(Sha1::digest(&password)), password)
+            })
+            .collect::<Vec<_>>();
+        passwords.sort();
+
+        let mut file = File::create(&path).unwrap();
+        for (count, (hash, _)) in passwords.iter().enumerate() {
+            write!(file, "{hash}:{count}\r\n").unwrap();
+        }
+        drop(file);
+
+        let mut list = HashList::open(&path).unwrap();
+        let found = passwords
+            .iter()
+            .map(|(_, password)| list.count(password).unwrap())
+            .collect::<Vec<_>>();
+        let missing = list.count("not in the list").unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(found, (0..500).map(Some).collect::<Vec<_>>());
+        assert_eq!(missing, None);
+    }
+}
@@ -0,0 +1,226 @@
+use aes_gcm::{Aes256Gcm, KeyInit, aead::Aead};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{Kind, Token, decode_secret};
+use crate::{Result, pwd::Pwd};
+
+const PASSWORD_SLOT: u8 = 1;
+
+#[derive(Deserialize)]
+struct Vault {
+    header: Header,
+    db: Value,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    slots: Option<Vec<Slot>>,
+    params: Option<KeyParams>,
+}
+
+#[derive(Deserialize)]
+struct Slot {
+    #[serde(rename = "type")]
+    kind: u8,
+    key: String,
+    key_params: KeyParams,
+    #[serde(default)]
+    n: u64,
+    #[serde(default)]
+    r: u32,
+    #[serde(default)]
+    p: u32,
+    #[serde(default)]
+    salt: String,
+}
+
+#[derive(Deserialize)]
+struct KeyParams {
+    nonce: String,
+    tag: String,
+}
+
+#[derive(Deserialize)]
+struct Db {
+    entries: Vec<DbEntry>,
+}
+
+#[derive(Deserialize)]
+struct DbEntry {
+    #[serde(rename = "type")]
+    kind: String,
+    name: String,
+    #[serde(default)]
+    issuer: String,
+    info: Info,
+}
+
+#[derive(Deserialize)]
+struct Info {
+    secret: String,
+    #[serde(default = "default_algo")]
+    algo: String,
+    #[serde(default = "default_digits")]
+    digits: u32,
+    #[serde(default = "default_period")]
+    period: u64,
+    #[serde(default)]
+    counter: u64,
+}
+
+fn default_algo() -> String {
+    "SHA1".to_string()
+}
+
+fn default_digits() -> u32 {
+    6
+}
+
+fn default_period() -> u64 {
+    30
+}
+
+pub fn is_vault(json: &Value) -> bool {
+    json.get("header").is_some() && json.get("db").is_some()
+}
+
+/// Reads an Aegis vault, decrypting it with the password when needed
+pub fn parse_vault(json: Value, ask_password: &dyn Fn() -> Pwd) -> Result<Vec<Token>> {
+    let vault: Vault =
+        serde_json::from_value(json).map_err(|e| format!("Invalid Aegis vault: {e}"))?;
+
+    let db: Db = match (vault.header.slots, vault.header.params, vault.db) {
+        (Some(slots), Some(params), Value::String(content)) => {
+            let password = ask_password();
+            let master_key = unlock_master_key(&slots, password.expose())?;
+            let content = BASE64
+                .decode(content)
+                .map_err(|e| format!("Invalid Aegis vault: {e}"))?;
+            let plain = decrypt(&master_key, &params, &content)
+                .map_err(|_| "Unable to decrypt Aegis vault".to_string())?;
+            serde_json::from_slice(&plain).map_err(|e| format!("Invalid Aegis vault: {e}"))?
+        }
+        (_, _, db @ Value::Object(_)) => {
+            serde_json::from_value(db).map_err(|e| format!("Invalid Aegis vault: {e}"))?
+        }
+        _ => return Err("Invalid Aegis vault: unexpected db format".into()),
+    };
+
+    db.entries.into_iter().map(to_token).collect()
+}
+
+fn unlock_master_key(slots: &[Slot], password: &str) -> Result<Vec<u8>> {
+    for slot in slots.iter().filter(|s| s.kind == PASSWORD_SLOT) {
+        let salt = hex::decode(&slot.salt).map_err(|e| format!("Invalid Aegis slot: {e}"))?;
+        if !slot.n.is_power_of_two() {
+            return Err("Invalid Aegis vault".into());
+        }
+        let log_n = slot.n.ilog2() as u8;
+        let params = scrypt::Params::new(log_n, slot.r, slot.p, 32)
+            .map_err(|e| format!("Invalid Aegis slot: {e}"))?;
+
+        let mut key = [0u8; 32];
+        scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key)
+            .map_err(|e| format!("Invalid Aegis slot: {e}"))?;
+
+        let encrypted = hex::decode(&slot.key).map_err(|e| format!("Invalid Aegis slot: {e}"))?;
+        if let Ok(master_key) = decrypt(&key, &slot.key_params, &encrypted) {
+            return Ok(master_key);
+        }
+    }
+
+    Err("Invalid Aegis vault password".into())
+}
+
+fn decrypt(key: &[u8], params: &KeyParams, content: &[u8]) -> Result<Vec<u8>> {
+    let nonce = hex::decode(&params.nonce)?;
+    let mut payload = content.to_vec();
+    payload.extend(hex::decode(&params.tag)?);
+
+    let cipher = Aes256Gcm::new_from_slice(key).map_err(|e| e.to_string())?;
+    if nonce.len() != 12 {
+        return Err("Invalid nonce length".into());
+    }
+
+    cipher
+        .decrypt(nonce.as_slice().into(), payload.as_slice())
+        .map_err(|e| e.to_string().into())
+}
+
+fn to_token(entry: DbEntry) -> Result<Token> {
+    let info = entry.info;
+    let kind = match entry.kind.as_str() {
+        "totp" => Kind::Totp,
+        "hotp" => Kind::Hotp(info.counter),
+        "steam" => Kind::Steam,
+        other => return Err(format!("Unsupported Aegis entry type `{other}`").into()),
+    };
+
+    Ok(Token {
+        issuer: entry.issuer,
+        name: entry.name,
+        secret: decode_secret(&info.secret)?,
+        algorithm: super::algorithm(&info.algo)?,
+        digits: info.digits,
+        period: super::period(info.period, kind)?,
+        kind,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(path: &str) -> Value {
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    fn assert_tokens(tokens: &[Token]) {
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(
+            tokens[0].to_uri().expose(),
+            "otpauth://totp/Example:alice%40example.com?secret=JBSWY3DPEHPK3PXP&issuer=Example&\
+             algorithm=SHA256&digits=8&period=60"
+        );
+        assert_eq!(tokens[1].kind, Kind::Hotp(7));
+        assert_eq!(tokens[2].kind, Kind::Steam);
+    }
+
+    #[test]
+    fn reads_plain_vault() {
+        let tokens = parse_vault(read("tests/files/aegis.json"), &|| unreachable!()).unwrap();
+
+        assert_tokens(&tokens);
+    }
+
+    #[test]
+    fn reads_encrypted_vault() {
+        let json = read("tests/files/aegis-encrypted.json");
+        let tokens = parse_vault(json, &|| "test".to_string().into()).unwrap();
+
+        assert_tokens(&tokens);
+    }
+
+    #[test]
+    fn rejects_wrong_password() {
+        let json = read("tests/files/aegis-encrypted.json");
+        let err = parse_vault(json, &|| "wrong".to_string().into())
+            .err()
+            .unwrap();
+
+        assert_eq!(err.to_string(), "Invalid Aegis vault password");
+    }
+
+    #[test]
+    fn rejects_invalid_scrypt_cost() {
+        let mut json = read("tests/files/aegis-encrypted.json");
+        json["header"]["slots"][0]["n"] = 1000.into();
+        let err = parse_vault(json, &|| "test".to_string().into())
+            .err()
+            .unwrap();
+
+        assert_eq!(err.to_string(), "Invalid Aegis vault");
+    }
+}
@@ -0,0 +1,102 @@
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{Kind, Token, decode_secret};
+use crate::Result;
+
+#[derive(Deserialize)]
+struct BackupEntry {
+    secret: String,
+    #[serde(default)]
+    issuer: String,
+    #[serde(default)]
+    label: String,
+    #[serde(default = "default_digits")]
+    digits: u32,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default = "default_algorithm")]
+    algorithm: String,
+    #[serde(default = "default_period")]
+    period: u64,
+    #[serde(default)]
+    counter: u64,
+}
+
+fn default_algorithm() -> String {
+    "SHA1".to_string()
+}
+
+fn default_digits() -> u32 {
+    6
+}
+
+fn default_period() -> u64 {
+    30
+}
+
+/// Reads a plain andOTP JSON backup
+pub fn parse_backup(json: Value) -> Result<Vec<Token>> {
+    let entries: Vec<BackupEntry> =
+        serde_json::from_value(json).map_err(|e| format!("Invalid andOTP backup: {e}"))?;
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let kind = match entry.kind.to_ascii_uppercase().as_str() {
+                "TOTP" => Kind::Totp,
+                "HOTP" => Kind::Hotp(entry.counter),
+                "STEAM" => Kind::Steam,
+                other => return Err(format!("Unsupported andOTP entry type `{other}`").into()),
+            };
+
+            Ok(Token {
+                issuer: entry.issuer,
+                name: entry.label,
+                secret: decode_secret(&entry.secret)?,
+                algorithm: super::algorithm(&entry.algorithm)?,
+                digits: entry.digits,
+                period: super::period(entry.period, kind)?,
+                kind,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_backup() {
+        let json =
+            serde_json::from_str(&std::fs::read_to_string("tests/files/andotp.json").unwrap())
+                .unwrap();
+        let tokens = parse_backup(json).unwrap();
+
+        assert_eq!(tokens.len(), 3);
+        assert_eq!(tokens[0].title(), "Example");
+        assert_eq!(tokens[0].account(), "alice@example.com");
+        assert_eq!(tokens[1].algorithm, "SHA512");
+        assert_eq!(tokens[1].kind, Kind::Hotp(3));
+        assert_eq!(tokens[2].title(), "Steam");
+        assert_eq!(tokens[2].account(), "gamer");
+        assert_eq!(
+            tokens[2].to_uri().expose(),
+            "otpauth://totp/Steam:gamer?secret=JBSWY3DPEHPK3PXP&algorithm=SHA1&digits=5&period=30&\
+             encoder=steam"
+        );
+    }
+
+    #[test]
+    fn rejects_zero_period() {
+        let json = serde_json::json!([
+            {"secret": "JBSWY3DPEHPK3PXP", "label": "alice", "type": "TOTP", "period": 0}
+        ]);
+
+        assert_eq!(
+            parse_backup(json).err().unwrap().to_string(),
+            "Invalid TOTP period 0"
+        );
+    }
+}
@@ -0,0 +1,358 @@
+mod aegis;
+mod andotp;
+mod google;
+
+use std::{fs, path::Path};
+
+use keepass::db::{Entry, Group, Node, TOTPAlgorithm, Value};
+use serde_json::Value as Json;
+use url::form_urlencoded;
+use uuid::Uuid;
+
+use crate::{
+    Result,
+    keepass::{EntryPath, find_entry_by_uuid_mut, get_entries},
+    otp::{Encoder, encode_label, hotp_counter, normalize_base32_secret, parse_otp},
+    pwd::Pwd,
+    qr,
+    secret::Secret,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Kind {
+    Totp,
+    Hotp(u64),
+    Steam,
+}
+
+/// A single authenticator account ready to be stored in the `otp` field
+pub struct Token {
+    pub issuer: String,
+    pub name: String,
+    pub secret: Secret,
+    pub algorithm: &'static str,
+    pub digits: u32,
+    pub period: u64,
+    pub kind: Kind,
+}
+
+impl Default for Token {
+    fn default() -> Self {
+        Token {
+            issuer: String::new(),
+            name: String::new(),
+            secret: Secret::default(),
+            algorithm: "SHA1",
+            digits: 6,
+            period: 30,
+            kind: Kind::Totp,
+        }
+    }
+}
+
+impl Token {
+    /// Account name without the `issuer:` prefix some exporters keep in it
+    pub fn account(&self) -> &str {
+        match self.name.split_once(':') {
+            Some((prefix, account)) if self.issuer.is_empty() || prefix == self.issuer => {
+                account.trim()
+            }
+            _ => self.name.trim(),
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        if !self.issuer.is_empty() {
+            return &self.issuer;
+        }
+        match self.name.split_once(':') {
+            Some((prefix, _)) => prefix.trim(),
+            None => self.name.trim(),
+        }
+    }
+
+    /// The `otpauth://` URI, holding the secret
+    pub fn to_uri(&self) -> Pwd {
+        let label = if self.title().is_empty() || self.title() == self.account() {
+            encode_label(self.account())
+        } else {
+            format!(
+                "{}:{}",
+                encode_label(self.title()),
+                encode_label(self.account())
+            )
+        };
+
+        let secret = Pwd::from(base32::encode(
+            base32::Alphabet::Rfc4648 { padding: true },
+            self.secret.expose(),
+        ));
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+        serializer.append_pair("secret", secret.expose());
+        if !self.issuer.is_empty() {
+            serializer.append_pair("issuer", &self.issuer);
+        }
+        serializer.append_pair("algorithm", self.algorithm);
+        serializer.append_pair("digits", &self.digits.to_string());
+
+        let kind = match self.kind {
+            Kind::Totp => {
+                serializer.append_pair("period", &self.period.to_string());
+                "totp"
+            }
+            Kind::Hotp(counter) => {
+                serializer.append_pair("counter", &counter.to_string());
+                "hotp"
+            }
+            Kind::Steam => {
+                serializer.append_pair("period", &self.period.to_string());
+                serializer.append_pair("encoder", "steam");
+                "totp"
+            }
+        };
+
+        let query = Pwd::from(serializer.finish());
+        format!("otpauth://{kind}/{label}?{}", query.expose()).into()
+    }
+}
+
+/// Reads tokens from a migration/otpauth URI, a QR code image holding one,
+/// or an Aegis/andOTP JSON backup. HOTP tokens keep their counter, though
+/// only time-based codes can be computed.
+pub fn read_source(source: &str, ask_password: &dyn Fn() -> Pwd) -> Result<Vec<Token>> {
+    let path = Path::new(source);
+    let contents = if path.is_file() {
+        let data = fs::read(path)?;
+        if matches!(data.trim_ascii_start().first(), Some(b'{' | b'[')) {
+            return read_json(&data, ask_password);
+        }
+        qr::decode_image(path)?
+    } else {
+        vec![source.to_string()]
+    };
+
+    let mut tokens = Vec::new();
+    for content in contents {
+        let content = content.trim();
+        if content.starts_with("otpauth-migration://") {
+            tokens.extend(google::parse_migration(content)?);
+        } else if content.starts_with("otpauth://") {
+            tokens.push(from_otpauth(content)?);
+        } else {
+            return Err(format!("Unsupported import source `{source}`").into());
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn read_json(data: &[u8], ask_password: &dyn Fn() -> Pwd) -> Result<Vec<Token>> {
+    let json: Json = serde_json::from_slice(data).map_err(|e| format!("Invalid backup: {e}"))?;
+
+    if json.is_array() {
+        andotp::parse_backup(json)
+    } else if aegis::is_vault(&json) {
+        aegis::parse_vault(json, ask_password)
+    } else {
+        Err("Unsupported backup format".into())
+    }
+}
+
+fn decode_secret(secret: &str) -> Result<Secret> {
+    let normalized = Pwd::from(normalize_base32_secret(secret));
+    let mut decoded = base32::decode(
+        base32::Alphabet::Rfc4648 { padding: true },
+        normalized.expose(),
+    )
+    .ok_or("Unable to read TOTP: Base32 decoding error")?;
+
+    Ok(Secret::new(&mut decoded))
+}
+
+/// A period of 0 can't count time steps
+fn period(period: u64, kind: Kind) -> Result<u64> {
+    if period == 0 && !matches!(kind, Kind::Hotp(_)) {
+        return Err("Invalid TOTP period 0".into());
+    }
+    Ok(period)
+}
+
+fn algorithm(name: &str) -> Result<&'static str> {
+    match name.to_ascii_uppercase().as_str() {
+        "SHA1" => Ok("SHA1"),
+        "SHA256" => Ok("SHA256"),
+        "SHA512" => Ok("SHA512"),
+        other => Err(format!("Unsupported hash algorithm `{other}`").into()),
+    }
+}
+
+fn from_otpauth(uri: &str) -> Result<Token> {
+    let otp = parse_otp(uri)?;
+    let (issuer, name) = match otp.totp.label.split_once(':') {
+        Some((issuer, name)) => (issuer.to_string(), name.to_string()),
+        None => (String::new(), otp.totp.label.clone()),
+    };
+
+    Ok(Token {
+        issuer: otp.totp.issuer.clone().unwrap_or(issuer),
+        name: decode_label(&name),
+        secret: otp.secret()?,
+        algorithm: match otp.totp.algorithm {
+            TOTPAlgorithm::Sha1 => "SHA1",
+            TOTPAlgorithm::Sha256 => "SHA256",
+            TOTPAlgorithm::Sha512 => "SHA512",
+        },
+        digits: otp.totp.digits,
+        period: otp.totp.period,
+        kind: match (hotp_counter(uri), otp.encoder) {
+            (Some(counter), _) => Kind::Hotp(counter),
+            (None, Encoder::Default) => Kind::Totp,
+            (None, Encoder::Steam) => Kind::Steam,
+        },
+    })
+}
+
+fn decode_label(label: &str) -> String {
+    form_urlencoded::parse(format!("l={label}").as_bytes())
+        .next()
+        .map(|(_, v)| v.into_owned())
+        .unwrap_or_default()
+}
+
+/// Attaches each token to the matching entry (by title and, when ambiguous,
+/// username) or creates a new entry at the root. Returns a report per token.
+pub fn merge(root: &mut Group, tokens: Vec<Token>) -> Vec<String> {
+    tokens
+        .into_iter()
+        .map(|token| {
+            let otp = Value::Protected(token.to_uri().expose().as_bytes().into());
+
+            if let Some((uuid, path)) = find_match(root, &token) {
+                let entry = find_entry_by_uuid_mut(root, &uuid).expect("matched entry exists");
+                entry.fields.insert("otp".to_string(), otp);
+                entry.update_history();
+                return format!("Updated: {path}");
+            }
+
+            let mut entry = Entry::new();
+            entry.fields.insert(
+                "Title".to_string(),
+                Value::Unprotected(token.title().to_string()),
+            );
+            entry.fields.insert(
+                "UserName".to_string(),
+                Value::Unprotected(token.account().to_string()),
+            );
+            entry.fields.insert("otp".to_string(), otp);
+            root.children.push(Node::Entry(entry));
+
+            format!("Created: /{}/{}", root.name, token.title())
+        })
+        .collect()
+}
+
+fn find_match(root: &Group, token: &Token) -> Option<(Uuid, String)> {
+    let title = token.title();
+    if title.is_empty() {
+        return None;
+    }
+
+    let entries = get_entries(root, "");
+    let candidates = entries
+        .iter()
+        .filter(|e| {
+            e.entry
+                .get_title()
+                .is_some_and(|t| t.trim().eq_ignore_ascii_case(title))
+        })
+        .collect::<Vec<_>>();
+
+    let matched = match candidates.as_slice() {
+        [single] => Some(*single),
+        _ => {
+            let mut by_account = candidates.iter().filter(|e| {
+                e.entry
+                    .get_username()
+                    .is_some_and(|u| u.trim() == token.account())
+            });
+            match (by_account.next(), by_account.next()) {
+                (Some(single), None) => Some(*single),
+                _ => None,
+            }
+        }
+    };
+
+    matched.map(|e| (*e.entry.get_uuid(), e.entry_path()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title: &str, username: &str) -> Entry {
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected(title.to_string()));
+        entry.fields.insert(
+            "UserName".to_string(),
+            Value::Unprotected(username.to_string()),
+        );
+        entry
+    }
+
+    #[test]
+    fn keeps_hotp_counter() {
+        let uri = "otpauth://hotp/ACME%20Co:bob?secret=JBSWY3DPEHPK3PXP&issuer=ACME%20Co&digits=6&\
+                   counter=5";
+        let tokens = read_source(uri, &|| unreachable!()).unwrap();
+
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].kind, Kind::Hotp(5));
+        assert_eq!(
+            tokens[0].to_uri().expose(),
+            "otpauth://hotp/ACME%20Co:bob?secret=JBSWY3DPEHPK3PXP&issuer=ACME+Co&algorithm=SHA1&\
+             digits=6&counter=5"
+        );
+    }
+
+    #[test]
+    fn merges_into_matching_entries() {
+        let mut root = Group::new("Root");
+        let mut child = Group::new("web");
+        child
+            .children
+            .push(Node::Entry(entry("example", "alice@example.com")));
+        child.children.push(Node::Entry(entry("ACME Co", "carol")));
+        child.children.push(Node::Entry(entry("ACME Co", "dave")));
+        root.children.push(Node::Group(child));
+
+        let tokens = vec![
+            Token {
+                issuer: "Example".to_string(),
+                name: "Example:alice@example.com".to_string(),
+                secret: Secret::new(&mut b"Hello!\xDE\xAD\xBE\xEF".to_vec()),
+                ..Default::default()
+            },
+            Token {
+                issuer: "ACME Co".to_string(),
+                name: "bob".to_string(),
+                secret: Secret::new(&mut b"Hello!\xDE\xAD\xBE\xEF".to_vec()),
+                ..Default::default()
+            },
+        ];
+
+        let report = merge(&mut root, tokens);
+
+        assert_eq!(
+            report,
+            vec!["Updated: /Root/web/example", "Created: /Root/ACME Co"]
+        );
+        let entries = get_entries(&root, "");
+        assert!(entries[0].has_totp());
+        assert!(!entries[1].has_totp());
+        assert_eq!(entries[3].get_title(), "ACME Co");
+        assert_eq!(entries[3].entry.get_username(), Some("bob"));
+        assert!(entries[3].has_totp());
+    }
+}
@@ -0,0 +1,764 @@
+//! Just enough of the KDBX 4 format to open a database with an already
+//! transformed key. `keepass` only takes passwords and key files and always
+//! runs the KDF, so the payload is re-encrypted in memory for a throwaway key
+//! behind a single AES round before being handed over.
+
+use std::{collections::HashMap, io};
+
+use aes::{
+    Aes256,
+    cipher::{
+        BlockDecryptMut, BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit, StreamCipher,
+        block_padding::NoPadding, generic_array::GenericArray,
+    },
+};
+use hmac::{Hmac, Mac};
+use keepass::{
+    Database, DatabaseKey,
+    config::{DatabaseVersion, KdfConfig},
+    error::{
+        CryptographyError, DatabaseIntegrityError, DatabaseKeyError, DatabaseOpenError,
+        DatabaseSaveError, KdfConfigError, OuterCipherConfigError, VariantDictionaryError,
+    },
+};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{keyfile, pwd::zero_bytes};
+
+const VERSION_SIZE: usize = 12;
+
+const HEADER_END: u8 = 0;
+const HEADER_CIPHER_ID: u8 = 2;
+const HEADER_MASTER_SEED: u8 = 4;
+const HEADER_ENCRYPTION_IV: u8 = 7;
+const HEADER_KDF_PARAMETERS: u8 = 11;
+
+const CIPHER_AES256: [u8; 16] = [
+    0x31, 0xc1, 0xf2, 0xe6, 0xbf, 0x71, 0x43, 0x50, 0xbe, 0x58, 0x05, 0x21, 0x6a, 0xfc, 0x5a, 0xff,
+];
+const CIPHER_TWOFISH: [u8; 16] = [
+    0xad, 0x68, 0xf2, 0x9f, 0x57, 0x6f, 0x4b, 0xb9, 0xa3, 0x6a, 0xd4, 0x7a, 0xf9, 0x65, 0x34, 0x6c,
+];
+const CIPHER_CHACHA20: [u8; 16] = [
+    0xd6, 0x03, 0x8a, 0x2b, 0x8b, 0x6f, 0x4c, 0xb5, 0xa5, 0x24, 0x33, 0x9a, 0x31, 0xdb, 0xb5, 0x9a,
+];
+
+const KDF_AES_KDBX3: [u8; 16] = [
+    0xc9, 0xd9, 0xf3, 0x9a, 0x62, 0x8a, 0x44, 0x60, 0xbf, 0x74, 0x0d, 0x08, 0xc1, 0x8a, 0x4f, 0xea,
+];
+const KDF_AES_KDBX4: [u8; 16] = [
+    0x7c, 0x02, 0xbb, 0x82, 0x79, 0xa7, 0x4a, 0xc0, 0x92, 0x7d, 0x11, 0x4a, 0x00, 0x64, 0x82, 0x38,
+];
+const KDF_ARGON2D: [u8; 16] = [
+    0xef, 0x63, 0x6d, 0xdf, 0x8c, 0x29, 0x44, 0x4b, 0x91, 0xf7, 0xa9, 0xa4, 0x03, 0xe3, 0x0a, 0x0c,
+];
+const KDF_ARGON2ID: [u8; 16] = [
+    0x9e, 0x29, 0x8b, 0x19, 0x56, 0xdb, 0x47, 0x73, 0xb2, 0x3d, 0xfc, 0x3e, 0xc6, 0xf0, 0xa1, 0xe6,
+];
+
+/// Marks a transformed key stored in the keyring, i.e.
+/// `\0kdbx-key1;KDF_HASH\nKEY` where both are hex encoded
+const CACHE_HEADER: &str = "\0kdbx-key1;";
+
+/// Key derived by the KDF of a database from its composite key (password
+/// and key file). It only opens copies having the very same KDF parameters
+/// and seed, so saving takes the password again for a new seed.
+#[derive(Clone)]
+pub struct TransformedKey {
+    /// Raw KDF parameters of the header the key was derived for
+    kdf: Vec<u8>,
+    key: [u8; 32],
+}
+
+impl Drop for TransformedKey {
+    fn drop(&mut self) {
+        zero_bytes(&mut self.key);
+    }
+}
+
+impl TransformedKey {
+    /// Runs the database's KDF over the password and key file
+    pub fn derive(
+        data: &[u8],
+        password: &str,
+        keyfile: Option<&[u8]>,
+    ) -> Result<TransformedKey, DatabaseOpenError> {
+        let header = Header::parse(data)?;
+        derive_for(header.get(HEADER_KDF_PARAMETERS)?, password, keyfile)
+    }
+
+    /// Checks that the password and key file derive this very key
+    pub fn verify(&self, password: &str, keyfile: Option<&[u8]>) -> Result<(), DatabaseOpenError> {
+        let derived = derive_for(&self.kdf, password, keyfile)?;
+        let diff = self
+            .key
+            .iter()
+            .zip(derived.key)
+            .fold(0, |diff, (a, b)| diff | (a ^ b));
+        if diff != 0 {
+            return Err(DatabaseKeyError::IncorrectKey.into());
+        }
+        Ok(())
+    }
+
+    /// Text kept in the keyring, bound to a hash of the KDF parameters
+    pub fn encode(&self) -> String {
+        format!(
+            "{CACHE_HEADER}{}\n{}",
+            hex::encode(Sha256::digest(&self.kdf)),
+            hex::encode(self.key)
+        )
+    }
+
+    pub fn is_encoded(stored: &str) -> bool {
+        stored.starts_with(CACHE_HEADER)
+    }
+
+    /// Reads a key kept in the keyring, as long as it was derived for the
+    /// KDF parameters the database currently has
+    pub fn decode(stored: &str, data: &[u8]) -> Result<TransformedKey, DatabaseOpenError> {
+        let invalid = || DatabaseOpenError::from(DatabaseKeyError::IncorrectKey);
+
+        let (kdf_hash, key) = stored
+            .strip_prefix(CACHE_HEADER)
+            .and_then(|stored| stored.split_once('\n'))
+            .ok_or_else(invalid)?;
+        let mut decoded = [0; 32];
+        hex::decode_to_slice(key, &mut decoded).map_err(|_| invalid())?;
+
+        let kdf = Header::parse(data)?.get(HEADER_KDF_PARAMETERS)?.to_vec();
+        if hex::encode(Sha256::digest(&kdf)) != kdf_hash {
+            return Err(invalid());
+        }
+
+        Ok(TransformedKey { kdf, key: decoded })
+    }
+}
+
+fn derive_for(
+    kdf: &[u8],
+    password: &str,
+    keyfile: Option<&[u8]>,
+) -> Result<TransformedKey, DatabaseOpenError> {
+    let (config, seed) = parse_kdf(kdf)?;
+
+    let mut composite = Sha256::new();
+    composite.update(Sha256::digest(password.as_bytes()));
+    if let Some(keyfile) = keyfile {
+        composite.update(keyfile::key_element(keyfile));
+    }
+
+    Ok(TransformedKey {
+        kdf: kdf.to_vec(),
+        key: transform(&config, &seed, &composite.finalize().into())?,
+    })
+}
+
+/// Opens the database with the key instead of the password
+pub fn open(data: &[u8], key: &TransformedKey) -> Result<Database, DatabaseOpenError> {
+    if Header::parse(data)?.get(HEADER_KDF_PARAMETERS)? != key.kdf {
+        return Err(DatabaseKeyError::IncorrectKey.into());
+    }
+    let (config, _) = parse_kdf(&key.kdf)?;
+
+    let throwaway_kdf = throwaway_kdf_parameters();
+    let (throwaway_config, seed) = parse_kdf(&throwaway_kdf)?;
+    let throwaway = transform(&throwaway_config, &seed, &throwaway_composite_key())?;
+
+    let data = rekey(data, &key.key, &throwaway_kdf, &throwaway)?;
+    let mut db = Database::parse(&data, throwaway_database_key())?;
+    db.config.kdf_config = config;
+
+    Ok(db)
+}
+
+/// Serializes the database for the password and key file under a new KDF
+/// seed, along with the key derived for it
+pub fn save(
+    db: &Database,
+    password: &str,
+    keyfile: Option<&[u8]>,
+) -> Result<(Vec<u8>, TransformedKey), DatabaseSaveError> {
+    let mut database_key = DatabaseKey::new().with_password(password);
+    if let Some(keyfile) = keyfile {
+        database_key = database_key.with_keyfile(&mut io::Cursor::new(keyfile))?;
+    }
+
+    let mut data = Vec::new();
+    db.save(&mut data, database_key)?;
+    let key = TransformedKey::derive(&data, password, keyfile)
+        .map_err(|e| DatabaseSaveError::Io(io::Error::other(e.to_string())))?;
+
+    Ok((data, key))
+}
+
+fn throwaway_database_key() -> DatabaseKey {
+    DatabaseKey::new().with_password("")
+}
+
+fn throwaway_composite_key() -> [u8; 32] {
+    Sha256::digest(Sha256::digest(b"")).into()
+}
+
+/// One round of AES-KDF with a zero seed
+fn throwaway_kdf_parameters() -> Vec<u8> {
+    let mut vd = 0x100u16.to_le_bytes().to_vec();
+    for (value_type, key, value) in [
+        (0x42, "$UUID", &KDF_AES_KDBX4[..]),
+        (0x05, "R", &1u64.to_le_bytes()[..]),
+        (0x42, "S", &[0; 32][..]),
+    ] {
+        vd.push(value_type);
+        vd.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        vd.extend_from_slice(key.as_bytes());
+        vd.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        vd.extend_from_slice(value);
+    }
+    vd.push(0);
+
+    vd
+}
+
+/// Outer header, fields kept raw so it can be written back as is
+struct Header<'a> {
+    version: &'a [u8],
+    /// `(type, value)` in file order, the end field included
+    fields: Vec<(u8, &'a [u8])>,
+    len: usize,
+}
+
+impl<'a> Header<'a> {
+    fn parse(data: &'a [u8]) -> Result<Header<'a>, DatabaseOpenError> {
+        if !matches!(DatabaseVersion::parse(data)?, DatabaseVersion::KDB4(_)) {
+            return Err(DatabaseOpenError::UnsupportedVersion);
+        }
+
+        let incomplete = || DatabaseIntegrityError::IncompleteOuterHeader {
+            missing_field: "end of header".to_string(),
+        };
+        let mut fields = Vec::new();
+        let mut pos = VERSION_SIZE;
+        loop {
+            let field_type = *data.get(pos).ok_or_else(incomplete)?;
+            let len = data
+                .get(pos + 1..pos + 5)
+                .map(|len| u32::from_le_bytes(len.try_into().expect("4 bytes")) as usize)
+                .ok_or_else(incomplete)?;
+            let value = data.get(pos + 5..pos + 5 + len).ok_or_else(incomplete)?;
+            fields.push((field_type, value));
+            pos += 5 + len;
+
+            if field_type == HEADER_END {
+                break;
+            }
+        }
+
+        Ok(Header {
+            version: &data[..VERSION_SIZE],
+            fields,
+            len: pos,
+        })
+    }
+
+    fn get(&self, field_type: u8) -> Result<&'a [u8], DatabaseOpenError> {
+        self.fields
+            .iter()
+            .find(|(t, _)| *t == field_type)
+            .map(|(_, value)| *value)
+            .ok_or_else(|| {
+                DatabaseIntegrityError::IncompleteOuterHeader {
+                    missing_field: format!("field {field_type}"),
+                }
+                .into()
+            })
+    }
+
+    /// Raw header with other KDF parameters
+    fn dump(&self, kdf: &[u8]) -> Vec<u8> {
+        let mut out = self.version.to_vec();
+        for (field_type, value) in self.fields.iter() {
+            let value = if *field_type == HEADER_KDF_PARAMETERS {
+                kdf
+            } else {
+                value
+            };
+            out.push(*field_type);
+            out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            out.extend_from_slice(value);
+        }
+
+        out
+    }
+}
+
+/// Re-encrypts the database for another transformed key and KDF parameters
+fn rekey(
+    data: &[u8],
+    from: &[u8; 32],
+    kdf: &[u8],
+    to: &[u8; 32],
+) -> Result<Vec<u8>, DatabaseOpenError> {
+    let header = Header::parse(data)?;
+    let master_seed = header.get(HEADER_MASTER_SEED)?;
+    let cipher = header.get(HEADER_CIPHER_ID)?;
+    let iv = header.get(HEADER_ENCRYPTION_IV)?;
+
+    let header_data = &data[..header.len];
+    let (hash, rest) = data[header.len..]
+        .split_at_checked(32)
+        .ok_or(DatabaseIntegrityError::HeaderHashMismatch)?;
+    let (header_hmac, blocks) = rest
+        .split_at_checked(32)
+        .ok_or(DatabaseKeyError::IncorrectKey)?;
+    if Sha256::digest(header_data).as_slice() != hash {
+        return Err(DatabaseIntegrityError::HeaderHashMismatch.into());
+    }
+
+    let from_hmac_key = hmac_key(master_seed, from);
+    if hmac(&block_key(u64::MAX, &from_hmac_key), &[header_data]) != header_hmac {
+        return Err(DatabaseKeyError::IncorrectKey.into());
+    }
+
+    let mut payload = read_blocks(blocks, &from_hmac_key)?;
+    decrypt(cipher, &master_key(master_seed, from), iv, &mut payload)?;
+    encrypt(cipher, &master_key(master_seed, to), iv, &mut payload)?;
+
+    let header_data = header.dump(kdf);
+    let hmac_key = hmac_key(master_seed, to);
+    let mut out = header_data.clone();
+    out.extend_from_slice(&Sha256::digest(&header_data));
+    out.extend_from_slice(&hmac(&block_key(u64::MAX, &hmac_key), &[&header_data]));
+    for (idx, block) in [&payload[..], &[]].into_iter().enumerate() {
+        let idx = (idx as u64).to_le_bytes();
+        let len = (block.len() as u32).to_le_bytes();
+        out.extend_from_slice(&hmac(
+            &block_key(u64::from_le_bytes(idx), &hmac_key),
+            &[&idx, &len, block],
+        ));
+        out.extend_from_slice(&len);
+        out.extend_from_slice(block);
+    }
+    zero_bytes(&mut payload);
+
+    Ok(out)
+}
+
+fn master_key(master_seed: &[u8], key: &[u8; 32]) -> [u8; 32] {
+    Sha256::new()
+        .chain_update(master_seed)
+        .chain_update(key)
+        .finalize()
+        .into()
+}
+
+fn hmac_key(master_seed: &[u8], key: &[u8; 32]) -> [u8; 64] {
+    Sha512::new()
+        .chain_update(master_seed)
+        .chain_update(key)
+        .chain_update([1])
+        .finalize()
+        .into()
+}
+
+/// Key of the block with the given index, the header uses the last one
+fn block_key(idx: u64, hmac_key: &[u8; 64]) -> [u8; 64] {
+    Sha512::new()
+        .chain_update(idx.to_le_bytes())
+        .chain_update(hmac_key)
+        .finalize()
+        .into()
+}
+
+fn hmac(key: &[u8; 64], data: &[&[u8]]) -> [u8; 32] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    for data in data {
+        mac.update(data);
+    }
+
+    mac.finalize().into_bytes().into()
+}
+
+fn read_blocks(mut data: &[u8], hmac_key: &[u8; 64]) -> Result<Vec<u8>, DatabaseOpenError> {
+    let mut out = Vec::new();
+
+    for idx in 0.. {
+        let mismatch = || DatabaseKeyError::IncorrectKey;
+        let (block_hmac, rest) = data.split_at_checked(32).ok_or_else(mismatch)?;
+        let (len, rest) = rest.split_at_checked(4).ok_or_else(mismatch)?;
+        let size = u32::from_le_bytes(len.try_into().expect("4 bytes")) as usize;
+        let (block, rest) = rest.split_at_checked(size).ok_or_else(mismatch)?;
+
+        let hmac = hmac(&block_key(idx, hmac_key), &[&idx.to_le_bytes(), len, block]);
+        if hmac != block_hmac {
+            return Err(mismatch().into());
+        }
+        if block.is_empty() {
+            break;
+        }
+
+        out.extend_from_slice(block);
+        data = rest;
+    }
+
+    Ok(out)
+}
+
+/// Undoes the outer encryption in place. Block ciphers keep their padding,
+/// as the payload is encrypted back right away.
+fn decrypt(
+    cipher: &[u8],
+    key: &[u8; 32],
+    iv: &[u8],
+    payload: &mut [u8],
+) -> Result<(), DatabaseOpenError> {
+    match cipher {
+        c if c == CIPHER_AES256 => {
+            cbc::Decryptor::<Aes256>::new_from_slices(key, iv)
+                .map_err(crypto)?
+                .decrypt_padded_mut::<NoPadding>(payload)
+                .map_err(crypto)?;
+        }
+        c if c == CIPHER_TWOFISH => {
+            cbc::Decryptor::<twofish::Twofish>::new_from_slices(key, iv)
+                .map_err(crypto)?
+                .decrypt_padded_mut::<NoPadding>(payload)
+                .map_err(crypto)?;
+        }
+        c if c == CIPHER_CHACHA20 => chacha20(key, iv, payload)?,
+        cid => return Err(unknown_cipher(cid)),
+    }
+
+    Ok(())
+}
+
+fn encrypt(
+    cipher: &[u8],
+    key: &[u8; 32],
+    iv: &[u8],
+    payload: &mut [u8],
+) -> Result<(), DatabaseOpenError> {
+    let len = payload.len();
+    match cipher {
+        c if c == CIPHER_AES256 => {
+            cbc::Encryptor::<Aes256>::new_from_slices(key, iv)
+                .map_err(crypto)?
+                .encrypt_padded_mut::<NoPadding>(payload, len)
+                .map_err(crypto)?;
+        }
+        c if c == CIPHER_TWOFISH => {
+            cbc::Encryptor::<twofish::Twofish>::new_from_slices(key, iv)
+                .map_err(crypto)?
+                .encrypt_padded_mut::<NoPadding>(payload, len)
+                .map_err(crypto)?;
+        }
+        c if c == CIPHER_CHACHA20 => chacha20(key, iv, payload)?,
+        cid => return Err(unknown_cipher(cid)),
+    }
+
+    Ok(())
+}
+
+fn chacha20(key: &[u8; 32], iv: &[u8], payload: &mut [u8]) -> Result<(), DatabaseOpenError> {
+    chacha20::ChaCha20::new_from_slices(key, iv)
+        .map_err(crypto)?
+        .apply_keystream(payload);
+    Ok(())
+}
+
+fn unknown_cipher(cid: &[u8]) -> DatabaseOpenError {
+    DatabaseIntegrityError::OuterCipher(OuterCipherConfigError::InvalidOuterCipherID {
+        cid: cid.to_vec(),
+    })
+    .into()
+}
+
+fn crypto(e: impl Into<CryptographyError>) -> DatabaseOpenError {
+    DatabaseIntegrityError::Cryptography(e.into()).into()
+}
+
+/// Reads the KDF parameters, a variant dictionary, into a config and seed
+fn parse_kdf(data: &[u8]) -> Result<(KdfConfig, Vec<u8>), DatabaseOpenError> {
+    let mut values = HashMap::new();
+    let mut pos = 2;
+    while let Some(&value_type) = data.get(pos)
+        && value_type != 0
+    {
+        let read_len = |pos: usize| {
+            data.get(pos..pos + 4)
+                .map(|len| u32::from_le_bytes(len.try_into().expect("4 bytes")) as usize)
+        };
+        let entry = read_len(pos + 1).and_then(|key_len| {
+            let key = data.get(pos + 5..pos + 5 + key_len)?;
+            let value_len = read_len(pos + 5 + key_len)?;
+            let start = pos + 9 + key_len;
+            Some((key, data.get(start..start + value_len)?))
+        });
+        let Some((key, value)) = entry else {
+            return Err(kdf_error(VariantDictionaryError::InvalidValueType {
+                value_type,
+            }));
+        };
+
+        values.insert(String::from_utf8_lossy(key).into_owned(), value);
+        pos += 9 + key.len() + value.len();
+    }
+
+    let missing = |key: &str| {
+        kdf_error(VariantDictionaryError::MissingKey {
+            key: key.to_string(),
+        })
+    };
+    let get = |key: &str| values.get(key).copied().ok_or_else(|| missing(key));
+    let get_u64 = |key: &str| -> Result<u64, DatabaseOpenError> {
+        let value = get(key)?.try_into().map_err(|_| missing(key))?;
+        Ok(u64::from_le_bytes(value))
+    };
+    let get_u32 = |key: &str| -> Result<u32, DatabaseOpenError> {
+        let value = get(key)?.try_into().map_err(|_| missing(key))?;
+        Ok(u32::from_le_bytes(value))
+    };
+
+    let uuid = get("$UUID")?;
+    if uuid == KDF_AES_KDBX4 || uuid == KDF_AES_KDBX3 {
+        let rounds = get_u64("R")?;
+        return Ok((KdfConfig::Aes { rounds }, get("S")?.to_vec()));
+    }
+    if uuid != KDF_ARGON2D && uuid != KDF_ARGON2ID {
+        return Err(kdf_error(KdfConfigError::InvalidKDFUUID {
+            uuid: uuid.to_vec(),
+        }));
+    }
+
+    let memory = get_u64("M")?;
+    let iterations = get_u64("I")?;
+    let parallelism = get_u32("P")?;
+    let version = match get_u32("V")? {
+        0x10 => argon2::Version::Version10,
+        0x13 => argon2::Version::Version13,
+        version => return Err(kdf_error(KdfConfigError::InvalidKDFVersion { version })),
+    };
+
+    let config = if uuid == KDF_ARGON2D {
+        KdfConfig::Argon2 {
+            iterations,
+            memory,
+            parallelism,
+            version,
+        }
+    } else {
+        KdfConfig::Argon2id {
+            iterations,
+            memory,
+            parallelism,
+            version,
+        }
+    };
+
+    Ok((config, get("S")?.to_vec()))
+}
+
+fn kdf_error(e: impl Into<KdfConfigError>) -> DatabaseOpenError {
+    DatabaseIntegrityError::KdfSettings(e.into()).into()
+}
+
+/// Same derivation as `keepass` does
+fn transform(
+    config: &KdfConfig,
+    seed: &[u8],
+    composite_key: &[u8; 32],
+) -> Result<[u8; 32], DatabaseOpenError> {
+    let (memory, iterations, parallelism, version, variant) = match config {
+        KdfConfig::Aes { rounds } => {
+            let cipher = Aes256::new_from_slice(seed).map_err(crypto)?;
+            let mut key = *composite_key;
+            let (block1, block2) = key.split_at_mut(16);
+            for _ in 0..*rounds {
+                cipher.encrypt_block(GenericArray::from_mut_slice(block1));
+                cipher.encrypt_block(GenericArray::from_mut_slice(block2));
+            }
+
+            let transformed = Sha256::digest(key).into();
+            zero_bytes(&mut key);
+            return Ok(transformed);
+        }
+        KdfConfig::Argon2 {
+            memory,
+            iterations,
+            parallelism,
+            version,
+        } => (
+            memory,
+            iterations,
+            parallelism,
+            version,
+            argon2::Variant::Argon2d,
+        ),
+        KdfConfig::Argon2id {
+            memory,
+            iterations,
+            parallelism,
+            version,
+        } => (
+            memory,
+            iterations,
+            parallelism,
+            version,
+            argon2::Variant::Argon2id,
+        ),
+    };
+
+    let config = argon2::Config {
+        thread_mode: argon2::ThreadMode::Parallel,
+        ad: &[],
+        hash_length: 32,
+        lanes: *parallelism,
+        mem_cost: (memory / 1024) as u32,
+        secret: &[],
+        time_cost: *iterations as u32,
+        variant,
+        version: *version,
+    };
+    let mut key = argon2::hash_raw(composite_key, seed, &config).map_err(crypto)?;
+    let transformed = key.as_slice().try_into().expect("32 bytes hash");
+    zero_bytes(&mut key);
+
+    Ok(transformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn derive() -> (Vec<u8>, TransformedKey) {
+        let data = fs::read("tests/files/test.kdbx").unwrap();
+        let keyfile = fs::read("tests/files/secret").unwrap();
+        let key = TransformedKey::derive(&data, "test123", Some(&keyfile)).unwrap();
+        (data, key)
+    }
+
+    fn open_with_password(data: &[u8]) -> Database {
+        let key = DatabaseKey::new()
+            .with_password("test123")
+            .with_keyfile(&mut fs::File::open("tests/files/secret").unwrap())
+            .unwrap();
+        Database::parse(data, key).unwrap()
+    }
+
+    #[test]
+    fn opens_with_transformed_key() {
+        let (data, key) = derive();
+
+        let db = open(&data, &key).unwrap();
+
+        assert_eq!(db, open_with_password(&data));
+    }
+
+    #[test]
+    fn rejects_wrong_key() {
+        let data = fs::read("tests/files/test.kdbx").unwrap();
+        let key = TransformedKey::derive(&data, "test123", None).unwrap();
+
+        assert!(matches!(
+            open(&data, &key),
+            Err(DatabaseOpenError::Key(DatabaseKeyError::IncorrectKey))
+        ));
+    }
+
+    #[test]
+    fn verifies_password_against_key() {
+        let (_, key) = derive();
+        let keyfile = fs::read("tests/files/secret").unwrap();
+
+        assert!(key.verify("test123", Some(&keyfile)).is_ok());
+        assert!(key.verify("wrong", Some(&keyfile)).is_err());
+        assert!(key.verify("test123", None).is_err());
+    }
+
+    fn kdf_parameters(data: &[u8]) -> Vec<u8> {
+        Header::parse(data)
+            .unwrap()
+            .get(HEADER_KDF_PARAMETERS)
+            .unwrap()
+            .to_vec()
+    }
+
+    fn assert_saves_with_new_seed(data: &[u8]) {
+        let keyfile = fs::read("tests/files/secret").unwrap();
+        let key = TransformedKey::derive(data, "test123", Some(&keyfile)).unwrap();
+        let db = open(data, &key).unwrap();
+
+        let (saved, new_key) = save(&db, "test123", Some(&keyfile)).unwrap();
+
+        assert_ne!(kdf_parameters(&saved), key.kdf);
+        assert_eq!(
+            parse_kdf(&kdf_parameters(&saved)).unwrap().0,
+            parse_kdf(&key.kdf).unwrap().0
+        );
+        assert!(open(&saved, &key).is_err());
+        assert!(TransformedKey::decode(&key.encode(), &saved).is_err());
+        assert_eq!(open(&saved, &new_key).unwrap(), db);
+        assert_eq!(open_with_password(&saved), db);
+
+        // saved again from the new key
+        let db = open(&saved, &new_key).unwrap();
+        let (resaved, _) = save(&db, "test123", Some(&keyfile)).unwrap();
+        assert_ne!(kdf_parameters(&resaved), kdf_parameters(&saved));
+        assert_eq!(open_with_password(&resaved), db);
+    }
+
+    #[test]
+    fn saves_argon2d_with_new_seed() {
+        let data = fs::read("tests/files/test.kdbx").unwrap();
+        assert!(matches!(
+            parse_kdf(&kdf_parameters(&data)).unwrap().0,
+            KdfConfig::Argon2 { .. }
+        ));
+
+        assert_saves_with_new_seed(&data);
+    }
+
+    #[test]
+    fn saves_argon2id_with_new_seed() {
+        let mut db = open_with_password(&fs::read("tests/files/test.kdbx").unwrap());
+        db.config.kdf_config = KdfConfig::Argon2id {
+            iterations: 2,
+            memory: 1024 * 1024,
+            parallelism: 2,
+            version: argon2::Version::Version13,
+        };
+        let data = save(
+            &db,
+            "test123",
+            Some(&fs::read("tests/files/secret").unwrap()),
+        )
+        .unwrap()
+        .0;
+        assert!(matches!(
+            parse_kdf(&kdf_parameters(&data)).unwrap().0,
+            KdfConfig::Argon2id { .. }
+        ));
+
+        assert_saves_with_new_seed(&data);
+    }
+
+    #[test]
+    fn binds_cached_key_to_kdf_parameters() {
+        let (data, key) = derive();
+        let encoded = key.encode();
+        assert!(TransformedKey::is_encoded(&encoded));
+        assert!(TransformedKey::decode(&encoded, &data).is_ok());
+
+        let other = fs::read("tests/files/test.kdbx").unwrap();
+        let db = open(&other, &key).unwrap();
+        let resaved = {
+            let mut data = Vec::new();
+            db.save(&mut data, DatabaseKey::new().with_password("test123"))
+                .unwrap();
+            data
+        };
+
+        assert!(TransformedKey::decode(&encoded, &resaved).is_err());
+    }
+}
@@ -1,25 +1,35 @@
 use std::{
-    fs::File,
+    fs::{self, File},
     io::{self, Cursor, Read},
-    path::Path,
+    path::{Path, PathBuf},
 };
 
+use chrono::{NaiveDateTime, Utc};
 use keepass::{
     Database, DatabaseKey,
     db::{Entry, Group, Node, Value},
     error::{DatabaseOpenError, DatabaseSaveError},
 };
+use log::*;
+use uuid::Uuid;
 
-use crate::pwd::Pwd;
+use crate::{
+    kdbx4::{self, TransformedKey},
+    keyring,
+    otp::{parse_totp, raw_otp},
+    pwd::Pwd,
+};
 
 const MASKED_VALUE: &str = "******";
+pub const EXPIRED_MARKER: &str = " [expired]";
 
-pub fn new_database_key(keyfile: Option<&Path>, password: Pwd) -> io::Result<DatabaseKey> {
-    let password = &password[..];
+pub fn new_database_key(keyfile: Option<&Path>, password: Option<Pwd>) -> io::Result<DatabaseKey> {
     let keyfile = read_file(keyfile)?;
 
     let mut key = DatabaseKey::new();
-    key = key.with_password(password);
+    if let Some(password) = password {
+        key = key.with_password(password.expose());
+    }
 
     if let Some(mut keyfile) = keyfile {
         key = key.with_keyfile(&mut keyfile)?;
@@ -28,20 +38,100 @@ pub fn new_database_key(keyfile: Option<&Path>, password: Pwd) -> io::Result<Dat
     Ok(key)
 }
 
+/// What unlocked a database, needed again to save it
+pub enum Credentials {
+    Password(Pwd),
+    /// The key file alone
+    KeyFile,
+    /// A key derived by the KDF, along with the password when it was typed
+    /// in this run, since saving derives a new one
+    TransformedKey(TransformedKey, Option<Pwd>),
+}
+
+impl Credentials {
+    /// What the keyring keeps for them, never an empty password
+    pub fn to_stored(&self) -> Option<Pwd> {
+        match self {
+            Credentials::Password(password) if password.is_empty() => None,
+            Credentials::Password(password) => Some(password.duplicate()),
+            Credentials::KeyFile => None,
+            Credentials::TransformedKey(key, _) => Some(key.encode().into()),
+        }
+    }
+
+    /// A key read from the keyring can't save: saving derives a new one
+    pub fn needs_password(&self) -> bool {
+        matches!(self, Credentials::TransformedKey(_, None))
+    }
+
+    /// Gives the password to a key read from the keyring, once checked to
+    /// derive that very key
+    pub fn add_password(
+        &mut self,
+        password: Pwd,
+        keyfile: Option<&Path>,
+    ) -> Result<(), DatabaseOpenError> {
+        let Credentials::TransformedKey(key, stored @ None) = self else {
+            return Ok(());
+        };
+        let keyfile = keyfile.map(fs::read).transpose()?;
+        key.verify(password.expose(), keyfile.as_deref())?;
+        *stored = Some(password);
+        Ok(())
+    }
+
+    /// Another copy, for saving more than once
+    pub fn duplicate(&self) -> Credentials {
+        match self {
+            Credentials::Password(password) => Credentials::Password(password.duplicate()),
+            Credentials::KeyFile => Credentials::KeyFile,
+            Credentials::TransformedKey(key, password) => {
+                Credentials::TransformedKey(key.clone(), password.as_ref().map(Pwd::duplicate))
+            }
+        }
+    }
+}
+
 pub fn save_database(
     db: Database,
     dbfile: &Path,
     keyfile: Option<&Path>,
-    password: Pwd,
+    credentials: Credentials,
 ) -> Result<(), DatabaseSaveError> {
-    let key = new_database_key(keyfile, password)?;
-    let mut file = File::create(dbfile)?;
-    db.save(&mut file, key)?;
+    match credentials {
+        Credentials::Password(password) => {
+            let key = new_database_key(keyfile, Some(password))?;
+            let mut file = File::create(dbfile)?;
+            db.save(&mut file, key)?;
+        }
+        Credentials::KeyFile => {
+            let key = new_database_key(keyfile, None)?;
+            let mut file = File::create(dbfile)?;
+            db.save(&mut file, key)?;
+        }
+        Credentials::TransformedKey(_, Some(password)) => {
+            let keyfile_data = keyfile.map(fs::read).transpose()?;
+            let (data, key) = kdbx4::save(&db, password.expose(), keyfile_data.as_deref())?;
+            fs::write(dbfile, data)?;
+
+            // The KDF seed changed, so did the key
+            if let Some(keyring) = keyring::from_db_path(dbfile, keyfile)
+                && let Err(e) = keyring.set_password(&key.encode())
+            {
+                warn!("unable to store the new key in the keyring: {e}");
+            }
+        }
+        Credentials::TransformedKey(_, None) => {
+            return Err(DatabaseSaveError::Io(io::Error::other(
+                "Saving takes the password, the stored key only fits the current KDF seed",
+            )));
+        }
+    }
     Ok(())
 }
 
 pub fn open_database(
-    password: Pwd,
+    password: Option<Pwd>,
     dbfile: &Path,
     keyfile: Option<&Path>,
 ) -> Result<Database, DatabaseOpenError> {
@@ -50,6 +140,90 @@ pub fn open_database(
     Database::open(&mut dbfile, key)
 }
 
+/// Opens the database with the password. With `derive_key`, the KDF output
+/// is kept so that it can be stored instead of the password.
+pub fn unlock_database(
+    password: Pwd,
+    dbfile: &Path,
+    keyfile: Option<&Path>,
+    derive_key: bool,
+) -> Result<(Database, Credentials), DatabaseOpenError> {
+    if derive_key {
+        let data = fs::read(dbfile)?;
+        let keyfile = keyfile.map(fs::read).transpose()?;
+        match TransformedKey::derive(&data, password.expose(), keyfile.as_deref()) {
+            Ok(key) => {
+                let db = kdbx4::open(&data, &key)?;
+                return Ok((db, Credentials::TransformedKey(key, Some(password))));
+            }
+            Err(DatabaseOpenError::UnsupportedVersion) => {
+                warn!("only KDBX 4 databases can be unlocked with a stored key")
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    let db = open_database(Some(password.duplicate()), dbfile, keyfile)?;
+    Ok((db, Credentials::Password(password)))
+}
+
+/// Opens a database locked by the key file alone
+pub fn unlock_database_with_key_file(
+    dbfile: &Path,
+    keyfile: &Path,
+) -> Result<(Database, Credentials), DatabaseOpenError> {
+    let db = open_database(None, dbfile, Some(keyfile))?;
+    Ok((db, Credentials::KeyFile))
+}
+
+/// Opens the database with what the keyring stored, a password or a key
+pub fn unlock_database_stored(
+    stored: Pwd,
+    dbfile: &Path,
+    keyfile: Option<&Path>,
+) -> Result<(Database, Credentials), DatabaseOpenError> {
+    if !TransformedKey::is_encoded(stored.expose()) {
+        return unlock_database(stored, dbfile, keyfile, false);
+    }
+
+    let data = fs::read(dbfile)?;
+    let key = TransformedKey::decode(stored.expose(), &data)?;
+    Ok((
+        kdbx4::open(&data, &key)?,
+        Credentials::TransformedKey(key, None),
+    ))
+}
+
+/// A database opened along with what is needed to save it back
+pub struct OpenedDatabase {
+    pub name: String,
+    pub path: PathBuf,
+    pub key_file: Option<PathBuf>,
+    pub db: Database,
+    pub credentials: Credentials,
+}
+
+impl OpenedDatabase {
+    pub fn save(self) -> Result<(), DatabaseSaveError> {
+        save_database(
+            self.db,
+            &self.path,
+            self.key_file.as_deref(),
+            self.credentials,
+        )
+    }
+
+    /// Saves the database, keeping it open for further changes
+    pub fn save_in_place(&self) -> Result<(), DatabaseSaveError> {
+        save_database(
+            self.db.clone(),
+            &self.path,
+            self.key_file.as_deref(),
+            self.credentials.duplicate(),
+        )
+    }
+}
+
 pub fn show_entry(entry: &Entry, show_sensitive: bool) -> String {
     let mut fields: Vec<String> = Vec::new();
 
@@ -105,13 +279,29 @@ pub fn show_entry(entry: &Entry, show_sensitive: bool) -> String {
         }
     }
 
-    if let Some(code) = entry.get_otp().ok().and_then(|otp| otp.value_now().ok()) {
+    if let Some(code) = raw_otp(entry)
+        .and_then(|raw| parse_totp(raw.expose()).ok())
+        .and_then(|otp| otp.value_now().ok())
+    {
         fields.push(format!("TOTP Code: {}", code.code));
     }
 
     fields.join("\n")
 }
 
+/// Expiry time of the entry, if it is set to expire at all
+pub fn expiry(entry: &Entry) -> Option<NaiveDateTime> {
+    entry
+        .times
+        .expires
+        .then(|| entry.get_expiry_time().copied())
+        .flatten()
+}
+
+pub fn is_expired(entry: &Entry) -> bool {
+    expiry(entry).is_some_and(|time| time <= Utc::now().naive_utc())
+}
+
 fn read_file(file: Option<&Path>) -> io::Result<Option<Cursor<Vec<u8>>>> {
     if let Some(file) = file {
         let mut f = File::open(file)?;
@@ -174,13 +364,75 @@ impl EntryPath for WrappedEntry<'_> {
     }
 
     fn has_totp(&self) -> bool {
-        self.entry
-            .get_raw_otp_value()
-            .map(|otp| !otp.trim().is_empty())
-            .unwrap_or(false)
+        raw_otp(self.entry).is_some()
+    }
+
+    fn is_expired(&self) -> bool {
+        is_expired(self.entry)
     }
 }
 
+/// Entry of one of the opened databases
+pub struct SourcedEntry<'a> {
+    /// Index of the database the entry comes from
+    pub db: usize,
+    pub prefix: Option<&'a str>,
+    pub wrapped: WrappedEntry<'a>,
+}
+
+impl EntryPath for SourcedEntry<'_> {
+    fn entry_path(&self) -> String {
+        match self.prefix {
+            Some(prefix) => format!("{}:{}", prefix, self.wrapped.entry_path()),
+            None => self.wrapped.entry_path(),
+        }
+    }
+
+    fn get_entry(&self) -> &Entry {
+        self.wrapped.entry
+    }
+
+    fn get_title(&self) -> String {
+        match self.prefix {
+            Some(prefix) => format!("{}:{}", prefix, self.wrapped.get_title()),
+            None => self.wrapped.get_title(),
+        }
+    }
+
+    fn has_totp(&self) -> bool {
+        self.wrapped.has_totp()
+    }
+
+    fn is_expired(&self) -> bool {
+        self.wrapped.is_expired()
+    }
+}
+
+/// Entries of all the databases, prefixed with the database's name when
+/// there are several of them
+pub fn get_all_entries(dbs: &[OpenedDatabase]) -> Vec<SourcedEntry<'_>> {
+    dbs.iter()
+        .enumerate()
+        .flat_map(|(idx, opened)| {
+            let prefix = (dbs.len() > 1).then_some(opened.name.as_str());
+            get_entries(&opened.db.root, "")
+                .into_iter()
+                .map(move |wrapped| SourcedEntry {
+                    db: idx,
+                    prefix,
+                    wrapped,
+                })
+        })
+        .collect()
+}
+
+pub fn find_entry_in<'a, 'b>(
+    query: &str,
+    entries: &'b [SourcedEntry<'a>],
+) -> Option<&'b SourcedEntry<'a>> {
+    entries.iter().find(|e| e.entry_path().ends_with(query))
+}
+
 pub fn find_entry<'a>(query: &str, group: &'a Group) -> Option<&'a Entry> {
     get_entries(group, "").iter().find_map(|e| {
         let entry_path = e.entry_path();
@@ -192,11 +444,48 @@ pub fn find_entry<'a>(query: &str, group: &'a Group) -> Option<&'a Entry> {
     })
 }
 
+pub fn find_entry_by_uuid<'a>(group: &'a Group, uuid: &Uuid) -> Option<&'a Entry> {
+    group.children.iter().find_map(|v| match v {
+        Node::Entry(entry) if entry.get_uuid() == uuid => Some(entry),
+        Node::Entry(_) => None,
+        Node::Group(child) => find_entry_by_uuid(child, uuid),
+    })
+}
+
+pub fn find_entry_by_uuid_mut<'a>(group: &'a mut Group, uuid: &Uuid) -> Option<&'a mut Entry> {
+    group.children.iter_mut().find_map(|v| match v {
+        Node::Entry(entry) if entry.get_uuid() == uuid => Some(entry),
+        Node::Entry(_) => None,
+        Node::Group(child) => find_entry_by_uuid_mut(child, uuid),
+    })
+}
+
+pub fn find_group_by_uuid<'a>(group: &'a Group, uuid: &Uuid) -> Option<&'a Group> {
+    if &group.uuid == uuid {
+        return Some(group);
+    }
+    group.children.iter().find_map(|node| match node {
+        Node::Group(child) => find_group_by_uuid(child, uuid),
+        Node::Entry(_) => None,
+    })
+}
+
+pub fn find_group_by_uuid_mut<'a>(group: &'a mut Group, uuid: &Uuid) -> Option<&'a mut Group> {
+    if &group.uuid == uuid {
+        return Some(group);
+    }
+    group.children.iter_mut().find_map(|node| match node {
+        Node::Group(child) => find_group_by_uuid_mut(child, uuid),
+        Node::Entry(_) => None,
+    })
+}
+
 pub trait EntryPath {
     fn entry_path(&self) -> String;
     fn get_entry(&self) -> &Entry;
     fn get_title(&self) -> String;
     fn has_totp(&self) -> bool;
+    fn is_expired(&self) -> bool;
 }
 
 #[cfg(test)]
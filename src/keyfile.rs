@@ -0,0 +1,306 @@
+use std::{fmt, fs::OpenOptions, io::Write, os::unix::fs::OpenOptionsExt, path::Path};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use clap::ValueEnum;
+use sha2::{Digest, Sha256};
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::{Result, pwd::zero_bytes};
+
+const KEY_SIZE: usize = 32;
+
+/// Key file formats which can be generated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// KeePass 2.47+ / KeePassXC XML with a hash check
+    #[default]
+    XmlV2,
+    /// Legacy KeePass XML with base64 data
+    XmlV1,
+    /// Legacy 32 random bytes
+    Binary,
+}
+
+/// How a key file is read
+#[derive(Debug, PartialEq, Eq)]
+pub enum Kind {
+    /// Hex data; the hash check is missing on some older files
+    XmlV2 {
+        hash_checked: bool,
+    },
+    XmlV1,
+    Binary,
+    /// Any other file, its SHA-256 is the key
+    Hashed,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Kind::XmlV2 { hash_checked: true } => write!(f, "XML v2.0, hash verified"),
+            Kind::XmlV2 {
+                hash_checked: false,
+            } => write!(f, "XML v2.0, no hash to verify"),
+            Kind::XmlV1 => write!(f, "XML v1.0"),
+            Kind::Binary => write!(f, "binary, 32 bytes"),
+            Kind::Hashed => write!(f, "any file, SHA-256 of its contents"),
+        }
+    }
+}
+
+struct XmlKey {
+    version: Option<String>,
+    hash: Option<String>,
+    data: String,
+}
+
+impl XmlKey {
+    fn is_v2(&self) -> bool {
+        self.version.as_deref() == Some("2.0")
+    }
+
+    /// Same decoding as `keepass`, falling back to the text as is
+    fn key(&self) -> Vec<u8> {
+        let decoded = if self.is_v2() {
+            hex::decode(self.data.trim().replace([' ', '\n', '\r'], "")).ok()
+        } else {
+            STANDARD.decode(&self.data).ok()
+        };
+
+        decoded.unwrap_or_else(|| self.data.as_bytes().to_vec())
+    }
+}
+
+/// The key file's part of the composite key, read the way `keepass` does:
+/// the data of an XML key file (v2 hex or v1 base64), 32 raw bytes as is,
+/// or the SHA-256 of anything else
+pub fn key_element(data: &[u8]) -> Vec<u8> {
+    if let Some(xml) = parse_xml(data) {
+        xml.key()
+    } else if data.len() == KEY_SIZE {
+        data.to_vec()
+    } else {
+        Sha256::digest(data).to_vec()
+    }
+}
+
+/// Tells the format of the key file, checking the hash of XML v2 ones
+pub fn inspect(data: &[u8]) -> Result<Kind> {
+    let Some(xml) = parse_xml(data) else {
+        return Ok(if data.len() == KEY_SIZE {
+            Kind::Binary
+        } else {
+            Kind::Hashed
+        });
+    };
+    if !xml.is_v2() {
+        return Ok(Kind::XmlV1);
+    }
+
+    let Some(hash) = xml.hash.as_deref() else {
+        return Ok(Kind::XmlV2 {
+            hash_checked: false,
+        });
+    };
+    if !hash.eq_ignore_ascii_case(&check_hash(&xml.key())) {
+        return Err("Key file is corrupted: hash mismatch".into());
+    }
+
+    Ok(Kind::XmlV2 { hash_checked: true })
+}
+
+/// Writes a new random key file readable by the owner only, never
+/// overwriting an existing file
+pub fn generate(path: &Path, format: Format) -> Result<()> {
+    let mut key = [0; KEY_SIZE];
+    getrandom::fill(&mut key).map_err(|e| format!("Unable to generate key: {e}"))?;
+
+    let mut contents = match format {
+        Format::XmlV2 => {
+            let data = hex::encode_upper(key);
+            let groups = data
+                .as_bytes()
+                .chunks(8)
+                .map(|group| std::str::from_utf8(group).expect("hex is ASCII"))
+                .collect::<Vec<_>>();
+            format!(
+                concat!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                    "<KeyFile>\n",
+                    "    <Meta>\n",
+                    "        <Version>2.0</Version>\n",
+                    "    </Meta>\n",
+                    "    <Key>\n",
+                    "        <Data Hash=\"{}\">\n",
+                    "            {}\n",
+                    "            {}\n",
+                    "        </Data>\n",
+                    "    </Key>\n",
+                    "</KeyFile>\n"
+                ),
+                check_hash(&key),
+                groups[..4].join(" "),
+                groups[4..].join(" "),
+            )
+            .into_bytes()
+        }
+        Format::XmlV1 => format!(
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+                "<KeyFile>\n",
+                "    <Meta>\n",
+                "        <Version>1.00</Version>\n",
+                "    </Meta>\n",
+                "    <Key>\n",
+                "        <Data>{}</Data>\n",
+                "    </Key>\n",
+                "</KeyFile>\n"
+            ),
+            STANDARD.encode(key)
+        )
+        .into_bytes(),
+        Format::Binary => key.to_vec(),
+    };
+    zero_bytes(&mut key);
+
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)
+        .and_then(|mut file| file.write_all(&contents));
+    zero_bytes(&mut contents);
+
+    written.map_err(|e| format!("Unable to write `{}`: {e}", path.to_string_lossy()).into())
+}
+
+/// First 4 bytes of the key's SHA-256, as written in XML v2 key files
+fn check_hash(key: &[u8]) -> String {
+    hex::encode_upper(&Sha256::digest(key)[..4])
+}
+
+fn parse_xml(data: &[u8]) -> Option<XmlKey> {
+    let mut path = Vec::new();
+    let mut version = None;
+    let mut hash = None;
+    let mut value = None;
+
+    for event in EventReader::new(data) {
+        match event.ok()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                path.push(name.local_name);
+                if path == ["KeyFile", "Key", "Data"] {
+                    hash = attributes
+                        .into_iter()
+                        .find(|attr| attr.name.local_name == "Hash")
+                        .map(|attr| attr.value);
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                path.pop();
+            }
+            XmlEvent::Characters(s) if path == ["KeyFile", "Meta", "Version"] => version = Some(s),
+            XmlEvent::Characters(s) if path == ["KeyFile", "Key", "Data"] => value = Some(s),
+            _ => {}
+        }
+    }
+
+    Some(XmlKey {
+        version,
+        hash,
+        data: value?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, os::unix::fs::PermissionsExt};
+
+    use keepass::{
+        Database, DatabaseKey,
+        config::{DatabaseConfig, KdfConfig},
+    };
+
+    use super::*;
+
+    fn generated(format: Format) -> Vec<u8> {
+        let path = env::temp_dir().join(format!("kdbx-keyfile-{}-{format:?}", std::process::id()));
+        generate(&path, format).unwrap();
+        let data = fs::read(&path).unwrap();
+
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(generate(&path, format).is_err(), "overwrote key file");
+        fs::remove_file(&path).unwrap();
+
+        data
+    }
+
+    /// Checks `keepass` reads the same key, by opening a database locked
+    /// with the file using the key as a binary key file
+    fn assert_keepass_reads(data: &[u8]) {
+        let element = key_element(data);
+        assert_eq!(element.len(), KEY_SIZE);
+
+        let config = DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 1 },
+            ..Default::default()
+        };
+        let mut db = Vec::new();
+        Database::new(config)
+            .save(
+                &mut db,
+                DatabaseKey::new().with_keyfile(&mut &data[..]).unwrap(),
+            )
+            .unwrap();
+
+        let key = DatabaseKey::new().with_keyfile(&mut &element[..]).unwrap();
+        assert!(Database::parse(&db, key).is_ok());
+    }
+
+    #[test]
+    fn generates_xml_v2_with_hash() {
+        let data = generated(Format::XmlV2);
+
+        assert_eq!(inspect(&data).unwrap(), Kind::XmlV2 { hash_checked: true });
+        assert_keepass_reads(&data);
+    }
+
+    #[test]
+    fn generates_legacy_formats() {
+        let data = generated(Format::XmlV1);
+        assert_eq!(inspect(&data).unwrap(), Kind::XmlV1);
+        assert_keepass_reads(&data);
+
+        let data = generated(Format::Binary);
+        assert_eq!(data.len(), KEY_SIZE);
+        assert_eq!(inspect(&data).unwrap(), Kind::Binary);
+    }
+
+    #[test]
+    fn rejects_xml_v2_with_wrong_hash() {
+        let data = String::from_utf8(generated(Format::XmlV2)).unwrap();
+        let start = data.find("Hash=\"").unwrap() + 6;
+        let wrong = if &data[start..start + 1] == "0" {
+            "1"
+        } else {
+            "0"
+        };
+        let data = format!("{}{wrong}{}", &data[..start], &data[start + 1..]);
+
+        assert!(inspect(data.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn hashes_other_files() {
+        assert_eq!(inspect(b"secret\n").unwrap(), Kind::Hashed);
+        assert_eq!(
+            key_element(b"secret\n"),
+            Sha256::digest(b"secret\n").to_vec()
+        );
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    fmt::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::*;
+
+use crate::pwd::Pwd;
+
+/// Marks a stored value carrying deadlines, i.e.
+/// `\0kdbx1;TTL;IDLE;IDLE_SECS\nPASSWORD` where deadlines are unix timestamps,
+/// `IDLE_SECS` the idle timeout they're re-armed with and 0 means none
+const HEADER: &str = "\0kdbx1;";
+
+/// When a stored password has to be forgotten
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Expiry {
+    /// Seconds since the password was stored
+    pub ttl: Option<u64>,
+    /// Seconds since the password was last used
+    pub idle: Option<u64>,
+}
+
+impl Expiry {
+    pub fn is_set(&self) -> bool {
+        self.ttl.is_some() || self.idle.is_some()
+    }
+}
+
+/// What a keyring entry holds, as read by `Expiring::peek`
+#[derive(Debug, PartialEq, Eq)]
+pub enum Stored {
+    Nothing,
+    /// Past a deadline, deleted on the next use
+    Expired,
+    Password {
+        /// Seconds left before it's forgotten, unless it's used again
+        /// before an idle deadline
+        expires_in: Option<u64>,
+    },
+}
+
+/// Keeps deadlines next to the password on any backend, and lets backends
+/// able to expire keys by themselves (i.e. the kernel keyring) know about them
+pub struct Expiring {
+    inner: Box<dyn super::Keyring>,
+    expiry: Expiry,
+    clock: fn() -> u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Deadlines {
+    ttl: u64,
+    idle: u64,
+    /// Idle timeout the password was stored with, so runs without one
+    /// keep it going
+    idle_secs: u64,
+}
+
+impl Deadlines {
+    fn earliest(&self) -> Option<u64> {
+        [self.ttl, self.idle].into_iter().filter(|d| *d > 0).min()
+    }
+}
+
+impl Expiring {
+    pub fn new(inner: Box<dyn super::Keyring>, expiry: Expiry) -> Self {
+        Expiring {
+            inner,
+            expiry,
+            clock: now,
+        }
+    }
+
+    /// Reads what's stored without re-arming the idle timeout, nor
+    /// deleting an expired password
+    pub fn peek(&self) -> Stored {
+        let Ok(stored) = self.inner.get_password() else {
+            return Stored::Nothing;
+        };
+        let now = (self.clock)();
+
+        match parse(stored.expose()).and_then(|(deadlines, _)| deadlines.earliest()) {
+            Some(deadline) if deadline <= now => Stored::Expired,
+            deadline => Stored::Password {
+                expires_in: deadline.map(|deadline| deadline - now),
+            },
+        }
+    }
+
+    fn store(&self, password: &str, deadlines: Deadlines) -> Result<(), String> {
+        let now = (self.clock)();
+        // room for the header up front, so the password is never left
+        // behind by a reallocation
+        let mut value = String::with_capacity(HEADER.len() + 3 * 21 + 1 + password.len());
+        let _ = write!(
+            value,
+            "{HEADER}{};{};{}\n{password}",
+            deadlines.ttl, deadlines.idle, deadlines.idle_secs
+        );
+        let value = Pwd::from(value);
+        self.inner.set_password(value.expose())?;
+
+        if let Some(deadline) = deadlines.earliest()
+            && let Err(e) = self.inner.set_timeout(deadline.saturating_sub(now).max(1))
+        {
+            warn!("can't set keyring timeout ({e})");
+        }
+
+        Ok(())
+    }
+
+    /// Deadlines for a password used right now, never past the TTL. The
+    /// idle timeout configured wins over the stored one.
+    fn refreshed(&self, ttl: u64, idle_secs: u64) -> Deadlines {
+        let now = (self.clock)();
+        let idle_secs = self.expiry.idle.unwrap_or(idle_secs);
+        let idle = if idle_secs > 0 { now + idle_secs } else { 0 };
+
+        Deadlines {
+            ttl,
+            idle: if ttl > 0 && idle > 0 {
+                idle.min(ttl)
+            } else {
+                idle
+            },
+            idle_secs,
+        }
+    }
+}
+
+impl fmt::Display for Expiring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+impl super::Keyring for Expiring {
+    fn get_password(&self) -> Result<Pwd, String> {
+        let stored = self.inner.get_password()?;
+        let now = (self.clock)();
+
+        let Some((deadlines, password)) = parse(stored.expose()) else {
+            // Stored before any expiry was configured, start counting now
+            if self.expiry.is_set() {
+                let ttl = self.expiry.ttl.map_or(0, |ttl| now + ttl);
+                self.store(stored.expose(), self.refreshed(ttl, 0))?;
+            }
+            return Ok(stored);
+        };
+
+        if deadlines.earliest().is_some_and(|deadline| deadline <= now) {
+            debug!("stored password expired");
+            let _ = self.inner.delete_password();
+            return Err("Stored password expired".to_string());
+        }
+
+        if self.expiry.idle.is_some() || deadlines.idle_secs > 0 {
+            self.store(password, self.refreshed(deadlines.ttl, deadlines.idle_secs))?;
+        }
+
+        Ok(Pwd::copied(password))
+    }
+
+    fn set_password(&self, password: &str) -> Result<(), String> {
+        if !self.expiry.is_set() {
+            return self.inner.set_password(password);
+        }
+
+        let ttl = self.expiry.ttl.map_or(0, |ttl| (self.clock)() + ttl);
+        self.store(password, self.refreshed(ttl, 0))
+    }
+
+    fn delete_password(&self) -> Result<(), String> {
+        self.inner.delete_password()
+    }
+}
+
+fn parse(stored: &str) -> Option<(Deadlines, &str)> {
+    let (header, password) = stored.strip_prefix(HEADER)?.split_once('\n')?;
+    let mut fields = header.split(';').map(|field| field.parse().ok());
+    let (ttl, idle, idle_secs) = (fields.next()??, fields.next()??, fields.next()??);
+
+    Some((
+        Deadlines {
+            ttl,
+            idle,
+            idle_secs,
+        },
+        password,
+    ))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+    use crate::keyring::Keyring;
+
+    thread_local! {
+        static CLOCK: Cell<u64> = const { Cell::new(1_000) };
+    }
+
+    #[derive(Default)]
+    struct Memory {
+        value: RefCell<Option<String>>,
+        timeout: RefCell<Option<u64>>,
+    }
+
+    impl fmt::Display for Memory {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "memory")
+        }
+    }
+
+    impl Keyring for &'static Memory {
+        fn get_password(&self) -> Result<Pwd, String> {
+            self.value
+                .borrow()
+                .clone()
+                .map(Pwd::from)
+                .ok_or_else(|| "No password stored".to_string())
+        }
+
+        fn set_password(&self, password: &str) -> Result<(), String> {
+            *self.value.borrow_mut() = Some(password.to_string());
+            Ok(())
+        }
+
+        fn delete_password(&self) -> Result<(), String> {
+            *self.value.borrow_mut() = None;
+            Ok(())
+        }
+
+        fn set_timeout(&self, secs: u64) -> Result<(), String> {
+            *self.timeout.borrow_mut() = Some(secs);
+            Ok(())
+        }
+    }
+
+    fn keyring(expiry: Expiry) -> (Expiring, &'static Memory) {
+        let memory: &'static Memory = Box::leak(Box::default());
+        let keyring = Expiring {
+            inner: Box::new(memory),
+            expiry,
+            clock: || CLOCK.with(Cell::get),
+        };
+        (keyring, memory)
+    }
+
+    fn advance(secs: u64) {
+        CLOCK.with(|clock| clock.set(clock.get() + secs));
+    }
+
+    #[test]
+    fn forgets_password_after_ttl() {
+        let (keyring, memory) = keyring(Expiry {
+            ttl: Some(60),
+            idle: None,
+        });
+
+        keyring.set_password("test123").unwrap();
+        assert_eq!(*memory.timeout.borrow(), Some(60));
+
+        advance(30);
+        assert_eq!(keyring.get_password().unwrap().expose(), "test123");
+
+        advance(30);
+        assert!(keyring.get_password().is_err());
+        assert!(memory.value.borrow().is_none());
+    }
+
+    #[test]
+    fn idle_timeout_is_refreshed_on_use_until_ttl() {
+        let (keyring, memory) = keyring(Expiry {
+            ttl: Some(100),
+            idle: Some(40),
+        });
+
+        keyring.set_password("test123").unwrap();
+        assert_eq!(*memory.timeout.borrow(), Some(40));
+
+        for _ in 0..2 {
+            advance(30);
+            assert_eq!(keyring.get_password().unwrap().expose(), "test123");
+        }
+        // 60s in, only 40s of the TTL are left
+        assert_eq!(*memory.timeout.borrow(), Some(40));
+
+        advance(30);
+        assert_eq!(keyring.get_password().unwrap().expose(), "test123");
+        assert_eq!(*memory.timeout.borrow(), Some(10));
+
+        advance(10);
+        assert!(keyring.get_password().is_err());
+    }
+
+    #[test]
+    fn keeps_stored_idle_timeout_when_not_configured() {
+        let (keyring, memory) = keyring(Expiry {
+            ttl: None,
+            idle: Some(40),
+        });
+        keyring.set_password("test123").unwrap();
+
+        let keyring = Expiring {
+            expiry: Expiry::default(),
+            ..keyring
+        };
+        advance(30);
+        assert_eq!(keyring.get_password().unwrap().expose(), "test123");
+        assert_eq!(*memory.timeout.borrow(), Some(40));
+
+        advance(41);
+        assert!(keyring.get_password().is_err());
+        assert!(memory.value.borrow().is_none());
+    }
+
+    #[test]
+    fn keeps_plain_password_without_expiry() {
+        let (keyring, memory) = keyring(Expiry::default());
+
+        keyring.set_password("test123").unwrap();
+
+        assert_eq!(memory.value.borrow().as_deref(), Some("test123"));
+        assert_eq!(keyring.get_password().unwrap().expose(), "test123");
+    }
+
+    #[test]
+    fn peeks_without_refreshing() {
+        let (keyring, memory) = keyring(Expiry {
+            ttl: Some(100),
+            idle: Some(40),
+        });
+        assert_eq!(keyring.peek(), Stored::Nothing);
+
+        keyring.set_password("test123").unwrap();
+        let stored = memory.value.borrow().clone();
+        advance(30);
+        assert_eq!(
+            keyring.peek(),
+            Stored::Password {
+                expires_in: Some(10)
+            }
+        );
+        assert_eq!(*memory.value.borrow(), stored);
+
+        advance(10);
+        assert_eq!(keyring.peek(), Stored::Expired);
+        assert!(memory.value.borrow().is_some());
+    }
+}
@@ -0,0 +1,136 @@
+use std::{ffi::CString, fmt, io, ptr};
+
+use libc::{SYS_add_key, SYS_keyctl, SYS_request_key, c_char, c_int, c_uint, syscall};
+use log::*;
+
+use crate::pwd::Pwd;
+
+pub struct Keyring {
+    desc: CString,
+}
+
+impl Keyring {
+    pub fn new(keyname: String, account: String) -> Result<Self, String> {
+        let desc = [keyname, account].join(":").into_bytes();
+        let desc = unsafe { CString::from_vec_unchecked(desc) };
+
+        Ok(Keyring { desc })
+    }
+}
+
+impl super::Keyring for Keyring {
+    fn get_password(&self) -> Result<Pwd, String> {
+        const KEYCTL_READ: c_int = 11;
+
+        info!("keyctl key decryption: {:?}", self.desc);
+
+        let data = unsafe {
+            let key_id = match syscall(
+                SYS_request_key,
+                b"user\0", // type for user-defined keyrings
+                self.desc.as_ptr(),
+                ptr::null::<c_char>(),
+                0,
+            ) {
+                -1 => return Err(format!("{}", io::Error::last_os_error())),
+                id => id,
+            };
+
+            let data_len = match syscall(SYS_keyctl, KEYCTL_READ, key_id, ptr::null::<c_char>(), 0)
+            {
+                -1 => return Err(format!("{}", io::Error::last_os_error())),
+                ln => ln,
+            };
+
+            let mut data = vec![0u8; data_len as usize];
+
+            if -1 == syscall(SYS_keyctl, KEYCTL_READ, key_id, data.as_mut_ptr(), data_len) {
+                return Err(format!("{}", io::Error::last_os_error()));
+            }
+
+            data
+        };
+
+        Pwd::from_utf8(data)
+    }
+
+    fn set_password(&self, password: &str) -> Result<(), String> {
+        const KEY_SPEC_SESSION_KEYRING: c_int = -3;
+
+        info!("keyctl key decryption: {:?}", self.desc);
+
+        unsafe {
+            if -1
+                == syscall(
+                    SYS_add_key,
+                    b"user\0",
+                    self.desc.as_ptr(),
+                    password.as_ptr(),
+                    password.len(),
+                    KEY_SPEC_SESSION_KEYRING,
+                )
+            {
+                return Err(format!("{}", io::Error::last_os_error()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn set_timeout(&self, secs: u64) -> Result<(), String> {
+        const KEYCTL_SET_TIMEOUT: c_int = 15;
+
+        info!("keyctl key timeout: {:?} in {}s", self.desc, secs);
+
+        unsafe {
+            let key_id = match syscall(
+                SYS_request_key,
+                b"user\0", // type for user-defined keyrings
+                self.desc.as_ptr(),
+                ptr::null::<c_char>(),
+                0,
+            ) {
+                -1 => return Err(format!("{}", io::Error::last_os_error())),
+                id => id,
+            };
+
+            let secs = c_uint::try_from(secs).unwrap_or(c_uint::MAX);
+            if -1 == syscall(SYS_keyctl, KEYCTL_SET_TIMEOUT, key_id, secs) {
+                return Err(format!("{}", io::Error::last_os_error()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn delete_password(&self) -> Result<(), String> {
+        const KEYCTL_INVALIDATE: c_int = 21;
+
+        info!("keyctl key decryption: {:?}", self.desc);
+
+        unsafe {
+            let key_id = match syscall(
+                SYS_request_key,
+                b"user\0", // type for user-defined keyrings
+                self.desc.as_ptr(),
+                ptr::null::<c_char>(),
+                0,
+            ) {
+                -1 => return Err(format!("{}", io::Error::last_os_error())),
+                id => id,
+            };
+
+            if -1 == syscall(SYS_keyctl, KEYCTL_INVALIDATE, key_id) {
+                return Err(format!("{}", io::Error::last_os_error()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "description: {})", self.desc.to_string_lossy())
+    }
+}
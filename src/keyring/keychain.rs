@@ -0,0 +1,51 @@
+use std::fmt;
+
+use security_framework::os::macos::keychain::SecKeychain;
+
+use crate::pwd::Pwd;
+
+pub struct Keyring {
+    keyname: String,
+    account: String,
+    keychain: SecKeychain,
+}
+
+impl fmt::Display for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "name: {}, account: {})", self.keyname, self.account)
+    }
+}
+
+impl Keyring {
+    pub fn new(keyname: String, account: String) -> Result<Self, String> {
+        SecKeychain::default()
+            .map(|keychain| Keyring {
+                keyname,
+                account,
+                keychain,
+            })
+            .map_err(|e| format!("{e}"))
+    }
+}
+
+impl super::Keyring for Keyring {
+    fn get_password(&self) -> Result<Pwd, String> {
+        self.keychain
+            .find_generic_password(&self.keyname, &self.account)
+            .map_err(|e| format!("{e}"))
+            .and_then(|(pwd, _)| Pwd::from_utf8(pwd.to_owned()))
+    }
+
+    fn set_password(&self, password: &str) -> Result<(), String> {
+        self.keychain
+            .set_generic_password(&self.keyname, &self.account, password.as_bytes())
+            .map_err(|e| format!("{e}"))
+    }
+
+    fn delete_password(&self) -> Result<(), String> {
+        self.keychain
+            .find_generic_password(&self.keyname, &self.account)
+            .map(|(_, key)| key.delete())
+            .map_err(|e| format!("{e}"))
+    }
+}
@@ -0,0 +1,335 @@
+mod expiring;
+#[cfg(target_os = "linux")]
+mod kernel;
+#[cfg(target_os = "macos")]
+mod keychain;
+mod noop;
+#[cfg(all(target_os = "linux", feature = "secret-service"))]
+mod secret_service;
+
+use std::{
+    fmt, fs,
+    path::{self, Path, PathBuf},
+    sync::OnceLock,
+};
+
+use clap::ValueEnum;
+use log::*;
+use sha2::{Digest, Sha256};
+
+use self::expiring::Expiring;
+pub use self::expiring::{Expiry, Stored};
+use crate::{
+    keyfile,
+    pwd::{Pwd, zero_bytes},
+};
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Storage of database passwords
+pub trait Keyring: fmt::Display {
+    fn get_password(&self) -> Result<Pwd, String>;
+    fn set_password(&self, password: &str) -> Result<(), String>;
+    fn delete_password(&self) -> Result<(), String>;
+
+    /// Makes the backend drop the stored password by itself in `secs`
+    fn set_timeout(&self, _secs: u64) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Settings {
+    pub backend: Backend,
+    pub expiry: Expiry,
+    pub cache: Cache,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// Linux kernel session keyring, cleared on logout
+    Kernel,
+    /// freedesktop Secret Service over D-Bus (GNOME Keyring, KWallet, KeePassXC)
+    SecretService,
+    /// macOS Keychain
+    Keychain,
+    /// Do not store passwords
+    None,
+}
+
+impl Default for Backend {
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            Backend::Keychain
+        } else if cfg!(target_os = "linux") {
+            Backend::Kernel
+        } else {
+            Backend::None
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.pad(value.get_name())
+    }
+}
+
+/// What is stored once a database is unlocked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Cache {
+    /// The master password
+    #[default]
+    Password,
+    /// The key derived by the database's KDF, only valid for its KDF seed.
+    /// Skips the KDF when unlocking and doesn't reveal the password. Saving
+    /// picks a new seed, so it asks for the password again when the database
+    /// was unlocked from the stored key.
+    Key,
+}
+
+impl fmt::Display for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        f.pad(value.get_name())
+    }
+}
+
+/// Picks the backend and expiry used by `from_db_path`, once, before any
+/// database is opened
+pub fn configure(settings: Settings) {
+    if SETTINGS.set(settings).is_err() {
+        warn!("keyring is already configured");
+    }
+}
+
+pub fn settings() -> Settings {
+    SETTINGS.get().copied().unwrap_or_default()
+}
+
+/// Keyring entry of the database unlocked with the key file. An entry made
+/// by older versions, keyed on the path as typed, is moved over.
+pub fn from_db_path(file: impl AsRef<Path>, keyfile: Option<&Path>) -> Option<Box<dyn Keyring>> {
+    let settings = settings();
+    let (service, username) = identity(file.as_ref(), keyfile);
+
+    open(settings.backend, service, username)
+        .inspect(|keyring| {
+            migrate(keyring.as_ref(), || {
+                let (service, username) = legacy_identity(file.as_ref());
+                open(settings.backend, service, username).ok()
+            })
+        })
+        .map(|keyring| Box::new(Expiring::new(keyring, settings.expiry)) as Box<dyn Keyring>)
+        .map(Some)
+        .unwrap_or_else(|e| {
+            warn!("can't init keyring ({e})");
+            None
+        })
+}
+
+/// What's stored for the database, read without changing anything: the
+/// idle timeout isn't re-armed and nothing is moved nor deleted
+pub fn peek_db_path(file: impl AsRef<Path>, keyfile: Option<&Path>) -> Stored {
+    let settings = settings();
+    let peek = |(service, username)| match open(settings.backend, service, username) {
+        Ok(keyring) => Expiring::new(keyring, settings.expiry).peek(),
+        Err(_) => Stored::Nothing,
+    };
+
+    match peek(identity(file.as_ref(), keyfile)) {
+        Stored::Nothing => peek(legacy_identity(file.as_ref())),
+        stored => stored,
+    }
+}
+
+/// Checks whether the backend can be used on this system
+pub fn probe(backend: Backend) -> Result<(), String> {
+    let (service, username) = identity(Path::new(""), None);
+    open(backend, service, username).map(|_| ())
+}
+
+fn open(backend: Backend, service: String, username: String) -> Result<Box<dyn Keyring>, String> {
+    match backend {
+        #[cfg(target_os = "linux")]
+        Backend::Kernel => Ok(Box::new(kernel::Keyring::new(service, username)?)),
+        #[cfg(all(target_os = "linux", feature = "secret-service"))]
+        Backend::SecretService => Ok(Box::new(secret_service::Keyring::new(service, username)?)),
+        #[cfg(target_os = "macos")]
+        Backend::Keychain => Ok(Box::new(keychain::Keyring::new(service, username)?)),
+        Backend::None => Ok(Box::new(noop::Keyring)),
+        #[allow(unreachable_patterns)]
+        backend => Err(format!(
+            "`{backend}` backend is not available on this system"
+        )),
+    }
+}
+
+fn service() -> String {
+    format!("{}.keepass.cli.tool", crate::BIN_NAME)
+}
+
+/// SHA-256 of the canonical path of the database and of the key its key
+/// file holds, so moving the key file around keeps the entry. KDBX has no
+/// identifier which could be read before unlocking, its seeds change on
+/// every save.
+fn identity(file: &Path, keyfile: Option<&Path>) -> (String, String) {
+    let mut hasher = Sha256::new();
+    hasher.update(canonical(file).as_os_str().as_encoded_bytes());
+    if let Some(keyfile) = keyfile {
+        hasher.update([0]);
+        match fs::read(keyfile) {
+            Ok(mut data) => {
+                let mut key = keyfile::key_element(&data);
+                hasher.update(Sha256::digest(&key));
+                zero_bytes(&mut key);
+                zero_bytes(&mut data);
+            }
+            // unlocking fails anyway
+            Err(_) => hasher.update(canonical(keyfile).as_os_str().as_encoded_bytes()),
+        }
+    }
+
+    (service(), hex::encode(hasher.finalize()))
+}
+
+fn canonical(file: &Path) -> PathBuf {
+    fs::canonicalize(file)
+        .or_else(|_| path::absolute(file))
+        .unwrap_or_else(|_| file.to_path_buf())
+}
+
+/// Entry used before, a djb2 hash of the path as typed
+fn legacy_identity(file: &Path) -> (String, String) {
+    use std::ops::BitXor;
+
+    let hash = file
+        .to_string_lossy()
+        .as_bytes()
+        .iter()
+        .fold(1153u64, |acc, &chr| acc.wrapping_mul(33).bitxor(chr as u64));
+
+    (service(), format!("{hash}"))
+}
+
+/// Moves the stored value, deadlines included, when there is none yet. Only
+/// then is the legacy entry looked up.
+fn migrate(keyring: &dyn Keyring, legacy: impl FnOnce() -> Option<Box<dyn Keyring>>) {
+    if keyring.get_password().is_ok() {
+        return;
+    }
+    let Some(legacy) = legacy() else {
+        return;
+    };
+    let Ok(value) = legacy.get_password() else {
+        return;
+    };
+
+    debug!("moving keyring entry from {legacy} to {keyring}");
+    if let Err(e) = keyring.set_password(value.expose()) {
+        warn!("can't move keyring entry ({e})");
+        return;
+    }
+    let _ = legacy.delete_password();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, env, rc::Rc};
+
+    use super::*;
+
+    #[derive(Default, Clone)]
+    struct Memory(Rc<RefCell<Option<String>>>);
+
+    impl fmt::Display for Memory {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "memory")
+        }
+    }
+
+    impl Keyring for Memory {
+        fn get_password(&self) -> Result<Pwd, String> {
+            self.0
+                .borrow()
+                .clone()
+                .map(Pwd::from)
+                .ok_or_else(|| "No password stored".to_string())
+        }
+
+        fn set_password(&self, password: &str) -> Result<(), String> {
+            *self.0.borrow_mut() = Some(password.to_string());
+            Ok(())
+        }
+
+        fn delete_password(&self) -> Result<(), String> {
+            *self.0.borrow_mut() = None;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn identifies_database_by_canonical_path_and_key_file() {
+        let absolute = env::current_dir().unwrap().join("tests/files/test.kdbx");
+        let keyfile = Path::new("tests/files/secret");
+
+        assert_eq!(
+            identity(Path::new("./tests/files/test.kdbx"), None),
+            identity(&absolute, None)
+        );
+        assert_eq!(
+            identity(Path::new("tests/files/../files/test.kdbx"), Some(keyfile)),
+            identity(&absolute, Some(&absolute.with_file_name("secret")))
+        );
+        assert_ne!(
+            identity(&absolute, None),
+            identity(&absolute, Some(keyfile))
+        );
+        assert_eq!(identity(&absolute, None).1.len(), 64);
+    }
+
+    #[test]
+    fn identifies_key_file_by_its_key() {
+        let database = Path::new("tests/files/test.kdbx");
+        let dir = env::temp_dir().join(format!("kdbx-identity-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let moved = dir.join("moved");
+        let other = dir.join("other");
+        fs::copy("tests/files/secret", &moved).unwrap();
+        fs::write(&other, "another key").unwrap();
+
+        let original = identity(database, Some(Path::new("tests/files/secret")));
+        let moved = identity(database, Some(&moved));
+        let other = identity(database, Some(&other));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(original, moved);
+        assert_ne!(original, other);
+    }
+
+    #[test]
+    fn migrates_legacy_entry() {
+        let legacy = Memory::default();
+        let keyring = Memory::default();
+        legacy.set_password("test123").unwrap();
+
+        migrate(&keyring, || Some(Box::new(legacy.clone())));
+
+        assert!(legacy.get_password().is_err());
+        assert_eq!(keyring.get_password().unwrap().expose(), "test123");
+    }
+
+    #[test]
+    fn keeps_current_entry_over_legacy() {
+        let legacy = Memory::default();
+        let keyring = Memory::default();
+        legacy.set_password("old").unwrap();
+        keyring.set_password("test123").unwrap();
+
+        migrate(&keyring, || panic!("legacy entry looked up"));
+
+        assert_eq!(legacy.get_password().unwrap().expose(), "old");
+        assert_eq!(keyring.get_password().unwrap().expose(), "test123");
+    }
+}
@@ -0,0 +1,26 @@
+use std::fmt;
+
+use crate::pwd::Pwd;
+
+/// Keeps nothing, so the password is asked every time
+pub struct Keyring;
+
+impl fmt::Display for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "passwords are not stored")
+    }
+}
+
+impl super::Keyring for Keyring {
+    fn get_password(&self) -> Result<Pwd, String> {
+        Err("No password stored".to_string())
+    }
+
+    fn set_password(&self, _: &str) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn delete_password(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
@@ -0,0 +1,473 @@
+use std::{collections::HashMap, fmt};
+
+use aes::{
+    Aes128,
+    cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit, block_padding::Pkcs7},
+};
+use hkdf::Hkdf;
+use log::*;
+use num_bigint::BigUint;
+use sha2::Sha256;
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value},
+};
+
+use crate::pwd::{Pwd, zero_bytes};
+
+const BUS_NAME: &str = "org.freedesktop.secrets";
+const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+
+const SERVICE_IFACE: &str = "org.freedesktop.Secret.Service";
+const COLLECTION_IFACE: &str = "org.freedesktop.Secret.Collection";
+const ITEM_IFACE: &str = "org.freedesktop.Secret.Item";
+const PROMPT_IFACE: &str = "org.freedesktop.Secret.Prompt";
+
+const ALGORITHM: &str = "dh-ietf1024-sha256-aes128-cbc-pkcs7";
+/// Second Oakley Group of RFC 2409, with 2 as generator
+const DH_PRIME: &str = "\
+    FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74\
+    020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F1437\
+    4FE1356D6D51C245E485B576625E7EC6F44C42E9A637ED6B0BFF5CB6F406B7ED\
+    EE386BFB5A899FA5AE9F24117C4B1FE649286651ECE65381FFFFFFFFFFFFFFFF";
+const DH_SIZE: usize = 128;
+
+/// `(session, parameters, value, content type)`
+type Secret = (OwnedObjectPath, Vec<u8>, Vec<u8>, String);
+
+/// Password stored as an item of the default collection, found by the
+/// `service` and `username` attributes. Secrets go over the session bus
+/// encrypted with a key agreed on when opening the session, so they never
+/// show up in a bus monitor.
+pub struct Keyring {
+    conn: Connection,
+    session: OwnedObjectPath,
+    key: SessionKey,
+    keyname: String,
+    account: String,
+}
+
+impl fmt::Display for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "service: {}, username: {}", self.keyname, self.account)
+    }
+}
+
+impl Keyring {
+    pub fn new(keyname: String, account: String) -> Result<Self, String> {
+        let conn = Connection::session().map_err(|e| format!("{e}"))?;
+        Keyring::with_connection(conn, keyname, account)
+    }
+
+    fn with_connection(conn: Connection, keyname: String, account: String) -> Result<Self, String> {
+        let exchange = Exchange::new()?;
+        let (output, session): (OwnedValue, OwnedObjectPath) =
+            proxy(&conn, SERVICE_PATH, SERVICE_IFACE)?
+                .call(
+                    "OpenSession",
+                    &(ALGORITHM, Value::from(exchange.public.clone())),
+                )
+                .map_err(|e| format!("Unable to open an encrypted session: {e}"))?;
+        debug!("secret service session: {}", session.as_str());
+
+        let public = Vec::<u8>::try_from(output).map_err(|e| format!("{e}"))?;
+        Ok(Keyring {
+            conn,
+            session,
+            key: exchange.key(&public)?,
+            keyname,
+            account,
+        })
+    }
+
+    fn attributes(&self) -> HashMap<&str, &str> {
+        HashMap::from([
+            ("service", self.keyname.as_str()),
+            ("username", self.account.as_str()),
+        ])
+    }
+
+    fn search(&self) -> Result<Vec<OwnedObjectPath>, String> {
+        let (unlocked, locked): (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) =
+            proxy(&self.conn, SERVICE_PATH, SERVICE_IFACE)?
+                .call("SearchItems", &(self.attributes(),))
+                .map_err(|e| format!("{e}"))?;
+
+        if locked.is_empty() {
+            return Ok(unlocked);
+        }
+
+        info!("unlocking {} secret service item(s)", locked.len());
+        let (mut items, prompt): (Vec<OwnedObjectPath>, OwnedObjectPath) =
+            proxy(&self.conn, SERVICE_PATH, SERVICE_IFACE)?
+                .call("Unlock", &(locked,))
+                .map_err(|e| format!("{e}"))?;
+        if let Some(result) = self.prompt(&prompt)? {
+            items.extend(Vec::<OwnedObjectPath>::try_from(result).map_err(|e| format!("{e}"))?);
+        }
+
+        Ok(unlocked.into_iter().chain(items).collect())
+    }
+
+    /// Asks the service to show its prompt (e.g. to unlock a collection)
+    /// and waits for the user to complete it
+    fn prompt(&self, path: &ObjectPath) -> Result<Option<OwnedValue>, String> {
+        if path.as_str() == "/" {
+            return Ok(None);
+        }
+
+        let prompt = proxy(&self.conn, path.as_str(), PROMPT_IFACE)?;
+        let mut completed = prompt
+            .receive_signal("Completed")
+            .map_err(|e| format!("{e}"))?;
+        prompt
+            .call::<_, _, ()>("Prompt", &("",))
+            .map_err(|e| format!("{e}"))?;
+
+        let message = completed
+            .next()
+            .ok_or_else(|| "Secret service prompt vanished".to_string())?;
+        let (dismissed, result): (bool, OwnedValue) =
+            message.body().deserialize().map_err(|e| format!("{e}"))?;
+        if dismissed {
+            return Err("Secret service prompt dismissed".to_string());
+        }
+
+        Ok(Some(result))
+    }
+}
+
+impl super::Keyring for Keyring {
+    fn get_password(&self) -> Result<Pwd, String> {
+        let item = self
+            .search()?
+            .into_iter()
+            .next()
+            .ok_or_else(|| "No password stored".to_string())?;
+
+        let (_, iv, value, _): Secret = proxy(&self.conn, item.as_str(), ITEM_IFACE)?
+            .call("GetSecret", &(&self.session,))
+            .map_err(|e| format!("{e}"))?;
+
+        Pwd::from_utf8(self.key.decrypt(&iv, &value)?)
+    }
+
+    fn set_password(&self, password: &str) -> Result<(), String> {
+        let label = format!("{} database password", crate::BIN_NAME);
+        let properties = HashMap::from([
+            ("org.freedesktop.Secret.Item.Label", Value::from(label)),
+            (
+                "org.freedesktop.Secret.Item.Attributes",
+                Value::from(self.attributes()),
+            ),
+        ]);
+        let (iv, value) = self.key.encrypt(password.as_bytes())?;
+        let secret: Secret = (self.session.clone(), iv, value, "text/plain".to_string());
+
+        let (_, prompt): (OwnedObjectPath, OwnedObjectPath) =
+            proxy(&self.conn, DEFAULT_COLLECTION, COLLECTION_IFACE)?
+                .call("CreateItem", &(properties, secret, true))
+                .map_err(|e| format!("{e}"))?;
+        self.prompt(&prompt)?;
+
+        Ok(())
+    }
+
+    fn delete_password(&self) -> Result<(), String> {
+        let items = self.search()?;
+        if items.is_empty() {
+            return Err("No password stored".to_string());
+        }
+
+        for item in items {
+            let prompt: OwnedObjectPath = proxy(&self.conn, item.as_str(), ITEM_IFACE)?
+                .call("Delete", &())
+                .map_err(|e| format!("{e}"))?;
+            self.prompt(&prompt)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Our half of the Diffie-Hellman exchange opening a session
+struct Exchange {
+    private: BigUint,
+    public: Vec<u8>,
+}
+
+impl Exchange {
+    fn new() -> Result<Self, String> {
+        let mut bytes = [0; DH_SIZE];
+        getrandom::fill(&mut bytes).map_err(|e| format!("Unable to generate key: {e}"))?;
+        let private = BigUint::from_bytes_be(&bytes);
+        zero_bytes(&mut bytes);
+
+        let public = BigUint::from(2u8).modpow(&private, &prime()).to_bytes_be();
+        Ok(Exchange { private, public })
+    }
+
+    /// Derives the session key from the other side's public key
+    fn key(self, public: &[u8]) -> Result<SessionKey, String> {
+        let prime = prime();
+        let public = BigUint::from_bytes_be(public);
+        if public <= BigUint::from(1u8) || public >= &prime - 1u8 {
+            return Err("Invalid secret service session key".to_string());
+        }
+
+        let mut shared = public.modpow(&self.private, &prime).to_bytes_be();
+        let mut ikm = [0; DH_SIZE];
+        ikm[DH_SIZE - shared.len()..].copy_from_slice(&shared);
+        zero_bytes(&mut shared);
+
+        let mut key = SessionKey([0; 16]);
+        Hkdf::<Sha256>::new(None, &ikm)
+            .expand(&[], &mut key.0)
+            .expect("valid key length");
+        zero_bytes(&mut ikm);
+
+        Ok(key)
+    }
+}
+
+fn prime() -> BigUint {
+    BigUint::parse_bytes(DH_PRIME.as_bytes(), 16).expect("valid prime")
+}
+
+/// AES-128 key secrets are encrypted with, along their IV
+struct SessionKey([u8; 16]);
+
+impl SessionKey {
+    /// The IV and the encrypted value
+    fn encrypt(&self, value: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+        let mut iv = vec![0; 16];
+        getrandom::fill(&mut iv).map_err(|e| format!("Unable to generate IV: {e}"))?;
+
+        let mut buf = vec![0; value.len() + 16];
+        buf[..value.len()].copy_from_slice(value);
+        let len = cbc::Encryptor::<Aes128>::new_from_slices(&self.0, &iv)
+            .map_err(|e| format!("{e}"))?
+            .encrypt_padded_mut::<Pkcs7>(&mut buf, value.len())
+            .map_err(|e| format!("{e}"))?
+            .len();
+        buf.truncate(len);
+
+        Ok((iv, buf))
+    }
+
+    fn decrypt(&self, iv: &[u8], value: &[u8]) -> Result<Vec<u8>, String> {
+        let mut buf = value.to_vec();
+        let len = cbc::Decryptor::<Aes128>::new_from_slices(&self.0, iv)
+            .map_err(|e| format!("{e}"))?
+            .decrypt_padded_mut::<Pkcs7>(&mut buf)
+            .map(|plain| plain.len());
+        match len {
+            Ok(len) => {
+                zero_bytes(&mut buf[len..]);
+                buf.truncate(len);
+                Ok(buf)
+            }
+            Err(_) => {
+                zero_bytes(&mut buf);
+                Err("Unable to decrypt the secret".to_string())
+            }
+        }
+    }
+}
+
+impl Drop for SessionKey {
+    fn drop(&mut self) {
+        zero_bytes(&mut self.0);
+    }
+}
+
+fn proxy<'a>(conn: &Connection, path: &'a str, iface: &'a str) -> Result<Proxy<'a>, String> {
+    Proxy::new(conn, BUS_NAME, path, iface).map_err(|e| format!("{e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        os::unix::net::UnixStream,
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use zbus::{ObjectServer, fdo, interface, zvariant::Dict};
+
+    use super::*;
+    use crate::keyring::Keyring as _;
+
+    type Store = Arc<Mutex<Vec<(u32, HashMap<String, String>, Vec<u8>)>>>;
+    type Key = Arc<Mutex<Option<SessionKey>>>;
+
+    /// Bare minimum of a Secret Service: one always unlocked collection and
+    /// a single session
+    struct Service(Store, Key);
+    struct Collection(Store, Key);
+    struct Item(Store, Key, u32);
+
+    fn item_path(id: u32) -> OwnedObjectPath {
+        ObjectPath::try_from(format!("/org/freedesktop/secrets/collection/login/{id}"))
+            .unwrap()
+            .into()
+    }
+
+    fn no_prompt() -> OwnedObjectPath {
+        ObjectPath::try_from("/").unwrap().into()
+    }
+
+    #[interface(name = "org.freedesktop.Secret.Service")]
+    impl Service {
+        fn open_session(
+            &self,
+            algorithm: &str,
+            input: Value<'_>,
+        ) -> fdo::Result<(OwnedValue, OwnedObjectPath)> {
+            if algorithm != ALGORITHM {
+                return Err(fdo::Error::NotSupported(algorithm.to_string()));
+            }
+            let public = Vec::<u8>::try_from(input)
+                .map_err(|_| fdo::Error::InvalidArgs("public key".to_string()))?;
+            let exchange = Exchange::new().unwrap();
+            let output = Value::from(exchange.public.clone()).try_into().unwrap();
+            *self.1.lock().unwrap() = Some(exchange.key(&public).map_err(fdo::Error::Failed)?);
+
+            let session = ObjectPath::try_from("/org/freedesktop/secrets/session/1").unwrap();
+            Ok((output, session.into()))
+        }
+
+        fn search_items(
+            &self,
+            attributes: HashMap<String, String>,
+        ) -> (Vec<OwnedObjectPath>, Vec<OwnedObjectPath>) {
+            let items = self
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(_, attrs, _)| attributes.iter().all(|(k, v)| attrs.get(k) == Some(v)))
+                .map(|(id, _, _)| item_path(*id))
+                .collect();
+            (items, Vec::new())
+        }
+    }
+
+    #[interface(name = "org.freedesktop.Secret.Collection")]
+    impl Collection {
+        async fn create_item(
+            &self,
+            properties: HashMap<String, OwnedValue>,
+            secret: Secret,
+            replace: bool,
+            #[zbus(object_server)] server: &ObjectServer,
+        ) -> fdo::Result<(OwnedObjectPath, OwnedObjectPath)> {
+            let attributes: HashMap<String, String> = properties
+                .get("org.freedesktop.Secret.Item.Attributes")
+                .and_then(|v| Dict::try_from(v.try_clone().ok()?).ok())
+                .and_then(|d| d.try_into().ok())
+                .ok_or_else(|| fdo::Error::InvalidArgs("attributes".to_string()))?;
+
+            let (_, iv, value, _) = secret;
+            let value = self
+                .1
+                .lock()
+                .unwrap()
+                .as_ref()
+                .unwrap()
+                .decrypt(&iv, &value);
+            let value = value.map_err(fdo::Error::Failed)?;
+
+            let id = {
+                let mut store = self.0.lock().unwrap();
+                if replace {
+                    store.retain(|(_, attrs, _)| *attrs != attributes);
+                }
+                let id = store.iter().map(|(id, _, _)| id + 1).max().unwrap_or(1);
+                store.push((id, attributes, value));
+                id
+            };
+            let item = Item(self.0.clone(), self.1.clone(), id);
+            server.at(item_path(id), item).await?;
+
+            Ok((item_path(id), no_prompt()))
+        }
+    }
+
+    #[interface(name = "org.freedesktop.Secret.Item")]
+    impl Item {
+        fn get_secret(&self, session: OwnedObjectPath) -> fdo::Result<Secret> {
+            let store = self.0.lock().unwrap();
+            let (_, _, value) = store
+                .iter()
+                .find(|(id, _, _)| *id == self.2)
+                .ok_or_else(|| fdo::Error::UnknownObject("deleted".to_string()))?;
+            let key = self.1.lock().unwrap();
+            let (iv, value) = key.as_ref().unwrap().encrypt(value).unwrap();
+
+            Ok((session, iv, value, "text/plain".to_string()))
+        }
+
+        fn delete(&self) -> OwnedObjectPath {
+            self.0.lock().unwrap().retain(|(id, _, _)| *id != self.2);
+            no_prompt()
+        }
+    }
+
+    fn connect() -> (Connection, Connection) {
+        use zbus::blocking::connection::Builder;
+
+        let (server, client) = UnixStream::pair().unwrap();
+        let store = Store::default();
+        let key = Key::default();
+
+        let server = thread::spawn(move || {
+            Builder::async_io_unix_stream(server)
+                .server(zbus::Guid::generate())
+                .unwrap()
+                .p2p()
+                .serve_at(SERVICE_PATH, Service(store.clone(), key.clone()))
+                .unwrap()
+                .serve_at(DEFAULT_COLLECTION, Collection(store, key))
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = Builder::async_io_unix_stream(client).p2p().build().unwrap();
+
+        (server.join().unwrap(), client)
+    }
+
+    #[test]
+    fn agrees_on_session_key() {
+        let (ours, theirs) = (Exchange::new().unwrap(), Exchange::new().unwrap());
+        let public = ours.public.clone();
+        let key = ours.key(&theirs.public).unwrap();
+        let other = theirs.key(&public).unwrap();
+        assert_eq!(key.0, other.0);
+
+        let (iv, value) = key.encrypt(b"test123").unwrap();
+        assert_eq!(value.len(), 16);
+        assert_ne!(value, b"test123");
+        assert_eq!(other.decrypt(&iv, &value).unwrap(), b"test123");
+
+        assert!(Exchange::new().unwrap().key(&[1]).is_err());
+    }
+
+    #[test]
+    fn stores_password_in_default_collection() {
+        let (_server, conn) = connect();
+        let keyring =
+            Keyring::with_connection(conn, "kdbx.test".to_string(), "42".to_string()).unwrap();
+
+        assert!(keyring.get_password().is_err());
+
+        keyring.set_password("secret").unwrap();
+        keyring.set_password("test123").unwrap();
+        assert_eq!(keyring.get_password().unwrap().expose(), "test123");
+
+        keyring.delete_password().unwrap();
+        assert!(keyring.get_password().is_err());
+    }
+}
@@ -2,15 +2,27 @@
 mod utils;
 mod clipboard;
 mod commands;
+mod config;
+mod hibp;
+mod import;
+mod kdbx4;
 mod keepass;
+mod keyfile;
 mod keyring;
 mod logger;
+mod otp;
+mod password_source;
+mod pinentry;
 mod pwd;
+mod qr;
+mod repl;
+mod secret;
 mod stdin;
+mod tui;
 
 use std::{env, error, process, result, sync::atomic, thread, time};
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 use log::*;
 use once_cell::sync::Lazy;
 
@@ -27,10 +39,40 @@ type Result<T> = result::Result<T, Box<dyn error::Error>>;
 
 fn main() {
     logger::init(BIN_NAME);
+    secret::disable_core_dumps();
 
     set_ctrlc_handler();
 
-    let cli = Cli::parse();
+    let cli = match config::Config::load().and_then(|config| config.get_matches(Cli::command())) {
+        Ok(mut matches) => Cli::from_arg_matches_mut(&mut matches).unwrap_or_else(|e| e.exit()),
+        Err(err) => {
+            werr!("{}", err);
+            process::exit(1);
+        }
+    };
+    if let Some(profile) = cli.profile.as_deref() {
+        debug!("using profile `{profile}`");
+    }
+    keyring::configure(keyring::Settings {
+        backend: cli.keyring_backend,
+        expiry: keyring::Expiry {
+            ttl: cli.keyring_ttl.map(|ttl| ttl.num_seconds().unsigned_abs()),
+            idle: cli
+                .keyring_idle
+                .map(|idle| idle.num_seconds().unsigned_abs()),
+        },
+        cache: cli.keyring_cache,
+    });
+    if let Some(source) = cli.password_source() {
+        password_source::configure(source);
+    }
+    clipboard::configure(clipboard::Settings {
+        backend: cli.clipboard,
+        command: cli.clipboard_command.clone(),
+    });
+    if let Some(program) = cli.pinentry.clone() {
+        pinentry::configure(program);
+    }
 
     if let Err(err) = match cli.command {
         Commands::Pwd(args) => commands::pwd::run(args),
@@ -38,7 +80,15 @@ fn main() {
         Commands::Show(args) => commands::show::run(args),
         Commands::Init(args) => commands::init::run(args),
         Commands::Add(args) => commands::add::run(args),
+        Commands::Import(args) => commands::import::run(args),
         Commands::List(args) => commands::list::run(args),
+        Commands::Audit(args) => commands::audit::run(args),
+        Commands::Expiring(args) => commands::expiring::run(args),
+        Commands::Expire(args) => commands::expire::run(args),
+        Commands::Keyring(args) => commands::keyring::run(args),
+        Commands::Keyfile(args) => commands::keyfile::run(args),
+        Commands::Tui(args) => commands::tui::run(args),
+        Commands::Open(args) => commands::open::run(args),
         Commands::Completion(args) => commands::completion::run(args),
     } {
         werr!("{}", err);
@@ -49,10 +99,101 @@ fn main() {
 #[derive(Parser)]
 #[command(version, about = BIN_DESCRIPTION)]
 struct Cli {
+    /// Use flags of a profile from the config file
+    #[arg(long, global = true, env = "KDBX_PROFILE")]
+    profile: Option<String>,
+
+    /// Where database passwords are stored
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "KDBX_KEYRING_BACKEND",
+        default_value_t = keyring::Backend::default()
+    )]
+    keyring_backend: keyring::Backend,
+
+    /// Forget the stored password this long after storing it, e.g. 8h
+    #[arg(long, global = true, value_name = "DURATION", env = "KDBX_KEYRING_TTL", value_parser = utils::parse_duration)]
+    keyring_ttl: Option<chrono::TimeDelta>,
+
+    /// Forget the stored password when unused for this long, e.g. 30m
+    #[arg(long, global = true, value_name = "DURATION", env = "KDBX_KEYRING_IDLE", value_parser = utils::parse_duration)]
+    keyring_idle: Option<chrono::TimeDelta>,
+
+    /// What to store in the keyring
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "KDBX_KEYRING_CACHE",
+        default_value_t = keyring::Cache::default()
+    )]
+    keyring_cache: keyring::Cache,
+
+    /// Read the master password from this open file descriptor
+    #[arg(
+        long,
+        global = true,
+        value_name = "N",
+        env = "KDBX_PASSWORD_FD",
+        group = "password_source"
+    )]
+    password_fd: Option<i32>,
+
+    /// Read the master password from this file
+    #[arg(long, global = true, value_name = "PATH", env = "KDBX_PASSWORD_FILE", group = "password_source", value_hint = clap::ValueHint::FilePath)]
+    password_file: Option<std::path::PathBuf>,
+
+    /// Read the master password from the output of this shell command
+    #[arg(
+        long,
+        global = true,
+        value_name = "COMMAND",
+        env = "KDBX_PASSWORD_COMMAND",
+        group = "password_source"
+    )]
+    password_command: Option<String>,
+
+    /// Ask for passwords with this pinentry program, e.g. pinentry-gnome3
+    #[arg(long, global = true, value_name = "PATH", env = "KDBX_PINENTRY", value_hint = clap::ValueHint::ExecutablePath)]
+    pinentry: Option<std::path::PathBuf>,
+
+    /// Where copied values go
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "KDBX_CLIPBOARD",
+        default_value_t = clipboard::Backend::default()
+    )]
+    clipboard: clipboard::Backend,
+
+    /// Copy by piping values to this shell command, e.g. `wl-copy -p`.
+    /// Clearing pipes an empty value
+    #[arg(
+        long,
+        global = true,
+        value_name = "COMMAND",
+        env = "KDBX_CLIPBOARD_COMMAND"
+    )]
+    clipboard_command: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+impl Cli {
+    fn password_source(&self) -> Option<password_source::Source> {
+        use password_source::Source;
+
+        self.password_fd
+            .map(Source::Fd)
+            .or_else(|| self.password_file.clone().map(Source::File))
+            .or_else(|| self.password_command.clone().map(Source::Command))
+    }
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Copy password and clear clipboard after specified amount of time
@@ -63,10 +204,26 @@ enum Commands {
     Show(commands::show::Args),
     /// Add new entry
     Add(commands::add::Args),
+    /// Import TOTP accounts from authenticator exports
+    Import(commands::import::Args),
     /// Init new database
     Init(commands::init::Args),
     /// List all entries
     List(commands::list::Args),
+    /// Report weak, reused, stale and empty passwords
+    Audit(commands::audit::Args),
+    /// List expired entries and those expiring soon
+    Expiring(commands::expiring::Args),
+    /// Set or clear entry's expiry
+    Expire(commands::expire::Args),
+    /// Manage the keyring storing database passwords
+    Keyring(commands::keyring::Args),
+    /// Generate and verify key files
+    Keyfile(commands::keyfile::Args),
+    /// Browse and edit the database in a full-screen terminal UI
+    Tui(commands::tui::Args),
+    /// Unlock the database once and run commands on it in a shell
+    Open(commands::open::Args),
     /// Outputs the completion file for given shell
     Completion(commands::completion::Args),
 }
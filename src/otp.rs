@@ -0,0 +1,468 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use keepass::{
+    db::{Entry, TOTP},
+    error::TOTPError,
+};
+use sha1::Sha1;
+use url::{Url, form_urlencoded};
+
+use crate::{Result, pwd::Pwd, secret::Secret};
+
+const STEAM_ALPHABET: &[u8] = b"23456789BCDFGHJKMNPQRTVWXY";
+const STEAM_DIGITS: u32 = 5;
+const STEAM_PERIOD: u64 = 30;
+
+const LEGACY_SEED_FIELD: &str = "TOTP Seed";
+const LEGACY_SETTINGS_FIELD: &str = "TOTP Settings";
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Encoder {
+    Default,
+    Steam,
+}
+
+pub struct Otp {
+    pub totp: TOTP,
+    pub encoder: Encoder,
+}
+
+pub struct OtpCode {
+    pub code: String,
+    /// Seconds left before the code rotates
+    pub valid_for: u64,
+}
+
+impl Otp {
+    pub fn value_at(&self, time: u64) -> Result<OtpCode> {
+        let period = self.totp.period;
+        let code = match self.encoder {
+            Encoder::Default => self.totp.value_at(time).code.clone(),
+            Encoder::Steam => steam_code(self.secret()?.expose(), time / period),
+        };
+
+        Ok(OtpCode {
+            code,
+            valid_for: period - (time % period),
+        })
+    }
+
+    pub fn value_now(&self) -> Result<OtpCode> {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        self.value_at(time)
+    }
+
+    /// The decoded secret, in locked memory
+    pub fn secret(&self) -> Result<Secret> {
+        let encoded = Pwd::from(self.totp.get_secret());
+        let mut decoded = base32::decode(
+            base32::Alphabet::Rfc4648 { padding: true },
+            encoded.expose(),
+        )
+        .ok_or("Unable to read TOTP: Base32 decoding error")?;
+
+        Ok(Secret::new(&mut decoded))
+    }
+}
+
+/// Returns the entry's OTP setup as an `otpauth://` URI (or KeeOTP string),
+/// falling back to KeePassXC's legacy `TOTP Seed`/`TOTP Settings` fields
+pub fn raw_otp(entry: &Entry) -> Option<Pwd> {
+    if let Some(otp) = entry.get_raw_otp_value().map(str::trim)
+        && !otp.is_empty()
+    {
+        return Some(Pwd::copied(otp));
+    }
+
+    let seed = entry.get(LEGACY_SEED_FIELD).map(str::trim)?;
+    if seed.is_empty() {
+        return None;
+    }
+    if seed.starts_with("otpauth://") {
+        return Some(Pwd::copied(seed));
+    }
+
+    Some(legacy_to_uri(
+        entry.get_title().unwrap_or_default(),
+        entry.get_username().unwrap_or_default(),
+        seed,
+        entry.get(LEGACY_SETTINGS_FIELD).unwrap_or_default(),
+    ))
+}
+
+/// Same as `raw_otp`, but a bare secret or KeeOTP string is turned into
+/// an `otpauth://` URI
+pub fn otpauth(entry: &Entry) -> Option<Pwd> {
+    let raw_value = raw_otp(entry)?;
+    let raw = raw_value.expose();
+
+    if raw.starts_with("otpauth://") {
+        Some(raw_value)
+    } else if raw.starts_with("key=") {
+        Some(keeotp_to_uri(raw))
+    } else {
+        Some(otpauth_uri(
+            entry.get_title().unwrap_or_default(),
+            entry.get_username().unwrap_or_default(),
+            raw,
+        ))
+    }
+}
+
+pub fn otpauth_uri(title: &str, username: &str, secret: &str) -> Pwd {
+    let query = Pwd::from(
+        form_urlencoded::Serializer::new(String::new())
+            .append_pair("secret", secret)
+            .append_pair("period", "30")
+            .append_pair("digits", "6")
+            .append_pair("issuer", title)
+            .finish(),
+    );
+
+    format!(
+        "otpauth://totp/{}:{}?{}",
+        encode_label(title),
+        encode_label(username),
+        query.expose()
+    )
+    .into()
+}
+
+/// Reads a time-based OTP setup, HOTP ones are refused as their counter
+/// can't be kept in step
+pub fn parse_totp(raw_value: &str) -> Result<Otp> {
+    if hotp_counter(raw_value).is_some() {
+        return Err("Unable to compute HOTP codes, only time-based ones"
+            .to_string()
+            .into());
+    }
+    parse_otp(raw_value)
+}
+
+/// The counter of an `otpauth://hotp/` URI, 0 when missing
+pub fn hotp_counter(raw_value: &str) -> Option<u64> {
+    let url = Url::parse(raw_value).ok()?;
+    if url.scheme() != "otpauth" || url.host_str() != Some("hotp") {
+        return None;
+    }
+    let counter = url
+        .query_pairs()
+        .find(|(key, _)| key == "counter")
+        .and_then(|(_, value)| value.parse().ok());
+    Some(counter.unwrap_or(0))
+}
+
+/// Reads an OTP setup, HOTP ones included, for its parameters
+pub fn parse_otp(raw_value: &str) -> Result<Otp> {
+    let keeotp;
+    let raw_value = if raw_value.starts_with("key=") {
+        keeotp = keeotp_to_uri(raw_value);
+        keeotp.expose()
+    } else {
+        raw_value
+    };
+
+    // `TOTP` keeps its own copy of the secret in a plain `String`
+    let mut totp = match raw_value.parse::<TOTP>() {
+        Ok(otp) => otp,
+        Err(TOTPError::Base32) => {
+            let Some(normalized) = normalize_totp_secret(raw_value) else {
+                return Err("Unable to read TOTP: Base32 decoding error"
+                    .to_string()
+                    .into());
+            };
+
+            normalized
+                .expose()
+                .parse::<TOTP>()
+                .map_err(|e| format!("Unable to read TOTP: {e}"))?
+        }
+        Err(err) => return Err(format!("Unable to read TOTP: {err}").into()),
+    };
+
+    if totp.period == 0 {
+        return Err("Unable to read TOTP: the period can't be 0"
+            .to_string()
+            .into());
+    }
+
+    let encoder = detect_encoder(raw_value);
+    if encoder == Encoder::Steam {
+        totp.digits = STEAM_DIGITS;
+    }
+
+    Ok(Otp { totp, encoder })
+}
+
+fn detect_encoder(raw_value: &str) -> Encoder {
+    let Ok(url) = Url::parse(raw_value) else {
+        return Encoder::Default;
+    };
+
+    let steam_host = url.host_str() == Some("steam");
+    let steam_param = url
+        .query_pairs()
+        .any(|(k, v)| k == "encoder" && v.eq_ignore_ascii_case("steam"));
+
+    if steam_host || steam_param {
+        Encoder::Steam
+    } else {
+        Encoder::Default
+    }
+}
+
+fn steam_code(secret: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let mut full = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    let mut code = String::with_capacity(STEAM_DIGITS as usize);
+    for _ in 0..STEAM_DIGITS {
+        code.push(STEAM_ALPHABET[full as usize % STEAM_ALPHABET.len()] as char);
+        full /= STEAM_ALPHABET.len() as u32;
+    }
+
+    code
+}
+
+/// Settings are stored as `period;digits[;encoder]`, where digits may be `S`
+/// for Steam Guard codes
+fn legacy_to_uri(title: &str, username: &str, seed: &str, settings: &str) -> Pwd {
+    let mut parts = settings.split(';').map(str::trim);
+    let period = parts
+        .next()
+        .filter(|p| !p.is_empty())
+        .unwrap_or("30")
+        .to_string();
+    let digits = parts.next().unwrap_or("6");
+    let encoder = parts.next().unwrap_or_default();
+
+    let steam = digits.eq_ignore_ascii_case("S") || encoder.eq_ignore_ascii_case("S");
+
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    serializer.append_pair("secret", seed);
+    if steam {
+        serializer.append_pair("period", &STEAM_PERIOD.to_string());
+        serializer.append_pair("digits", &STEAM_DIGITS.to_string());
+        serializer.append_pair("encoder", "steam");
+    } else {
+        serializer.append_pair("period", &period);
+        serializer.append_pair("digits", digits);
+    }
+    serializer.append_pair("issuer", title);
+    let query = Pwd::from(serializer.finish());
+
+    format!(
+        "otpauth://totp/{}:{}?{}",
+        encode_label(title),
+        encode_label(username),
+        query.expose()
+    )
+    .into()
+}
+
+/// KeeOTP plugin format, i.e. `key=SECRET&size=6&step=30&otpHashMode=Sha256`
+fn keeotp_to_uri(raw_value: &str) -> Pwd {
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+
+    for (key, value) in form_urlencoded::parse(raw_value.as_bytes()) {
+        match key.as_ref() {
+            "key" => serializer.append_pair("secret", &value),
+            "size" => serializer.append_pair("digits", &value),
+            "step" => serializer.append_pair("period", &value),
+            "otpHashMode" => serializer.append_pair("algorithm", &value.to_ascii_uppercase()),
+            _ => &mut serializer,
+        };
+    }
+
+    let query = Pwd::from(serializer.finish());
+    format!("otpauth://totp/KeeOTP?{}", query.expose()).into()
+}
+
+pub fn encode_label(value: &str) -> String {
+    form_urlencoded::byte_serialize(value.as_bytes())
+        .collect::<String>()
+        .replace('+', "%20")
+}
+
+fn normalize_totp_secret(raw_value: &str) -> Option<Pwd> {
+    let mut url = Url::parse(raw_value).ok()?;
+    let mut pairs: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+    let mut changed = false;
+
+    for (key, value) in pairs.iter_mut() {
+        if key != "secret" {
+            continue;
+        }
+
+        let normalized = normalize_base32_secret(value);
+        if normalized != *value {
+            *value = normalized;
+            changed = true;
+        }
+    }
+
+    if !changed {
+        return None;
+    }
+
+    let mut serializer = form_urlencoded::Serializer::new(String::new());
+    for (key, value) in pairs {
+        serializer.append_pair(&key, &value);
+    }
+
+    let query = Pwd::from(serializer.finish());
+    url.set_query(Some(query.expose()));
+
+    Some(String::from(url).into())
+}
+
+pub fn normalize_base32_secret(secret: &str) -> String {
+    let mut normalized: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+
+    if !normalized.len().is_multiple_of(8) {
+        normalized.push_str(&"=".repeat(8 - (normalized.len() % 8)));
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use keepass::db::Value;
+
+    use super::*;
+
+    fn entry_with(fields: &[(&str, &str)]) -> Entry {
+        let mut entry = Entry::new();
+        for (key, value) in fields {
+            entry
+                .fields
+                .insert(key.to_string(), Value::Unprotected(value.to_string()));
+        }
+        entry
+    }
+
+    #[test]
+    fn parses_secret_with_spaces() {
+        let entry = entry_with(&[(
+            "otp",
+            "otpauth://totp/example:demo?secret=JBSW%20Y3DP%20EHPK%203PXP&issuer=example&digits=6",
+        )]);
+
+        let otp = parse_totp(raw_otp(&entry).unwrap().expose()).expect("parsed totp");
+
+        assert_eq!(otp.totp.get_secret(), "JBSWY3DPEHPK3PXP");
+        assert_eq!(otp.value_at(0).unwrap().code.len(), 6);
+        assert_eq!(otp.secret().unwrap().expose(), b"Hello!\xDE\xAD\xBE\xEF");
+    }
+
+    #[test]
+    fn escapes_uri_of_bare_secret() {
+        let entry = entry_with(&[
+            ("Title", "A&B: shop?"),
+            ("UserName", "me@x"),
+            ("otp", "JBSWY3DPEHPK3PXP"),
+        ]);
+        let uri = otpauth(&entry).unwrap();
+
+        assert_eq!(
+            uri.expose(),
+            "otpauth://totp/A%26B%3A%20shop%3F:me%40x?secret=JBSWY3DPEHPK3PXP&period=30&digits=6&\
+             issuer=A%26B%3A+shop%3F"
+        );
+        let otp = parse_totp(uri.expose()).expect("parsed totp");
+        assert_eq!(otp.totp.issuer.as_deref(), Some("A&B: shop?"));
+        assert_eq!(otp.totp.get_secret(), "JBSWY3DPEHPK3PXP");
+    }
+
+    #[test]
+    fn parses_steam_encoder() {
+        let otp = parse_totp(
+            "otpauth://totp/Steam:demo?secret=JBSWY3DPEHPK3PXP&period=30&digits=5&issuer=Steam&\
+             encoder=steam",
+        )
+        .expect("parsed totp");
+
+        assert_eq!(otp.encoder, Encoder::Steam);
+        assert_eq!(otp.value_at(0).unwrap().code, "VH8YJ");
+        assert_eq!(
+            otp.value_at(59).unwrap().code,
+            otp.value_at(30).unwrap().code
+        );
+        assert!(
+            otp.value_at(1_700_000_000)
+                .unwrap()
+                .code
+                .bytes()
+                .all(|b| STEAM_ALPHABET.contains(&b))
+        );
+    }
+
+    #[test]
+    fn reads_legacy_fields() {
+        let entry = entry_with(&[
+            ("Title", "My Site"),
+            ("UserName", "demo"),
+            ("TOTP Seed", "JBSW Y3DP EHPK 3PXP"),
+            ("TOTP Settings", "60;8"),
+        ]);
+
+        let otp = parse_totp(raw_otp(&entry).unwrap().expose()).expect("parsed totp");
+
+        assert_eq!(otp.encoder, Encoder::Default);
+        assert_eq!(otp.totp.period, 60);
+        assert_eq!(otp.value_at(0).unwrap().code.len(), 8);
+    }
+
+    #[test]
+    fn reads_legacy_steam_fields() {
+        let entry = entry_with(&[
+            ("Title", "Steam"),
+            ("TOTP Seed", "JBSWY3DPEHPK3PXP"),
+            ("TOTP Settings", "30;S"),
+        ]);
+
+        let otp = parse_totp(raw_otp(&entry).unwrap().expose()).expect("parsed totp");
+
+        assert_eq!(otp.encoder, Encoder::Steam);
+        assert_eq!(otp.value_at(0).unwrap().code, "VH8YJ");
+    }
+
+    #[test]
+    fn parses_keeotp_format() {
+        let otp = parse_totp("key=JBSWY3DPEHPK3PXP&size=8&step=60&otpHashMode=Sha256")
+            .expect("parsed totp");
+
+        assert_eq!(otp.totp.digits, 8);
+        assert_eq!(otp.totp.period, 60);
+        assert_eq!(otp.totp.algorithm, keepass::db::TOTPAlgorithm::Sha256);
+    }
+
+    #[test]
+    fn refuses_hotp_and_zero_period() {
+        let hotp = "otpauth://hotp/ACME:bob?secret=JBSWY3DPEHPK3PXP&counter=7";
+        assert_eq!(hotp_counter(hotp), Some(7));
+        assert!(parse_otp(hotp).is_ok());
+        assert_eq!(
+            parse_totp(hotp).err().unwrap().to_string(),
+            "Unable to compute HOTP codes, only time-based ones"
+        );
+
+        assert!(parse_totp("otpauth://totp/ACME:bob?secret=JBSWY3DPEHPK3PXP&period=0").is_err());
+        assert!(parse_totp("key=JBSWY3DPEHPK3PXP&step=0").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::{
+    fmt,
+    fs::{self, File},
+    io::{self, Read},
+    os::fd::{FromRawFd, RawFd},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+
+use log::*;
+
+use crate::{pwd::Pwd, stdin::trim_newlines};
+
+static SOURCE: OnceLock<Source> = OnceLock::new();
+static PASSWORD: OnceLock<Result<Pwd, String>> = OnceLock::new();
+
+/// Where the master password is read from instead of stdin
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// An open file descriptor, e.g. `3<secret`
+    Fd(RawFd),
+    File(PathBuf),
+    /// A shell command printing the password
+    Command(String),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Fd(fd) => write!(f, "file descriptor {fd}"),
+            Source::File(path) => write!(f, "`{}`", path.to_string_lossy()),
+            Source::Command(command) => write!(f, "`{command}`"),
+        }
+    }
+}
+
+pub fn configure(source: Source) {
+    if SOURCE.set(source).is_err() {
+        warn!("password source is already configured");
+    }
+}
+
+pub fn is_configured() -> bool {
+    SOURCE.get().is_some()
+}
+
+/// The password from the configured source, read once and shared by all
+/// databases. Trailing newlines are dropped.
+pub fn read() -> Option<Result<Pwd, String>> {
+    let source = SOURCE.get()?;
+
+    let password = PASSWORD.get_or_init(|| {
+        debug!("reading password from {source}");
+        read_source(source)
+            .map(|mut password| {
+                trim_newlines(&mut password);
+                password.into()
+            })
+            .map_err(|e| format!("Unable to read password from {source}: {e}"))
+    });
+
+    Some(password.as_ref().map(Pwd::duplicate).map_err(Clone::clone))
+}
+
+fn read_source(source: &Source) -> io::Result<String> {
+    match source {
+        Source::Fd(fd) => {
+            if unsafe { libc::fcntl(*fd, libc::F_GETFD) } == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut password = String::new();
+            // the descriptor is handed over to us and closed once read
+            unsafe { File::from_raw_fd(*fd) }.read_to_string(&mut password)?;
+            Ok(password)
+        }
+        Source::File(path) => fs::read_to_string(path),
+        Source::Command(command) => {
+            let output = Command::new("sh")
+                .args(["-c", command])
+                .stdin(Stdio::null())
+                .stderr(Stdio::inherit())
+                .output()?;
+            if !output.status.success() {
+                return Err(io::Error::other(format!("exited with {}", output.status)));
+            }
+            String::from_utf8(output.stdout).map_err(|_| io::Error::other("not UTF-8"))
+        }
+    }
+}
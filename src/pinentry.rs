@@ -0,0 +1,204 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::OnceLock,
+};
+
+use log::*;
+
+use crate::pwd::{Pwd, zero_bytes};
+
+static PROGRAM: OnceLock<PathBuf> = OnceLock::new();
+
+/// Assuan error code of a prompt closed by the user
+const CANCELLED: &str = "83886179";
+
+pub fn configure(program: PathBuf) {
+    if PROGRAM.set(program).is_err() {
+        warn!("pinentry is already configured");
+    }
+}
+
+/// Path of the pinentry program asking for passwords, if any
+pub fn program() -> Option<&'static Path> {
+    PROGRAM.get().map(PathBuf::as_path)
+}
+
+/// A running pinentry program, talking the Assuan protocol over its
+/// stdin and stdout
+pub struct Pinentry {
+    child: Child,
+    input: Option<ChildStdin>,
+    output: BufReader<ChildStdout>,
+}
+
+impl Drop for Pinentry {
+    fn drop(&mut self) {
+        let _ = self.command("BYE");
+        // closing its input ends pinentry even if it ignores BYE
+        self.input = None;
+        let _ = self.child.wait();
+    }
+}
+
+impl Pinentry {
+    pub fn spawn(program: &Path) -> io::Result<Self> {
+        debug!("spawning pinentry `{}`", program.to_string_lossy());
+        let mut child = Command::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let input = child.stdin.take();
+        let output = BufReader::new(child.stdout.take().expect("stdout is piped"));
+
+        let mut pinentry = Pinentry {
+            child,
+            input,
+            output,
+        };
+        pinentry.response()?;
+        pinentry.command("SETTITLE kdbx")?;
+
+        Ok(pinentry)
+    }
+
+    pub fn set_description(&mut self, text: &str) -> io::Result<()> {
+        self.command(&format!("SETDESC {}", escape(text))).map(drop)
+    }
+
+    /// Asks for a password, showing the error of the previous attempt.
+    /// `None` when the user cancels.
+    pub fn get_pin(&mut self, prompt: &str, error: Option<&str>) -> io::Result<Option<Pwd>> {
+        self.command(&format!("SETPROMPT {}", escape(prompt)))?;
+        if let Some(error) = error {
+            self.command(&format!("SETERROR {}", escape(error)))?;
+        }
+
+        match self.command("GETPIN") {
+            Ok(data) => {
+                let pin = String::from_utf8(data).map_err(|e| {
+                    let mut data = e.into_bytes();
+                    zero_bytes(&mut data);
+                    io::Error::other("password is not UTF-8")
+                })?;
+                Ok(Some(pin.into()))
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends a command and returns the data of the response
+    fn command(&mut self, command: &str) -> io::Result<Vec<u8>> {
+        let input = self.input.as_mut().ok_or(io::ErrorKind::BrokenPipe)?;
+        writeln!(input, "{command}")?;
+        input.flush()?;
+        self.response()
+    }
+
+    fn response(&mut self) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut line = String::new();
+        loop {
+            zero_bytes(unsafe { line.as_bytes_mut() });
+            line.clear();
+            if self.output.read_line(&mut line)? == 0 {
+                zero_bytes(&mut data);
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line == "OK" || line.starts_with("OK ") {
+                return Ok(data);
+            } else if let Some(error) = line.strip_prefix("ERR ") {
+                zero_bytes(&mut data);
+                return Err(if error.split(' ').next() == Some(CANCELLED) {
+                    io::ErrorKind::Interrupted.into()
+                } else {
+                    io::Error::other(format!("pinentry: {error}"))
+                });
+            } else if let Some(chunk) = line.strip_prefix("D ") {
+                unescape(chunk, &mut data);
+            }
+            // status and comment lines are ignored
+        }
+    }
+}
+
+/// Percent-encodes what can't be sent as is in an Assuan line
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '%' | '\n' | '\r' => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(chunk: &str, data: &mut Vec<u8>) {
+    let mut bytes = chunk.bytes();
+    while let Some(byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [bytes.next().unwrap_or(b'0'), bytes.next().unwrap_or(b'0')];
+            let decoded = std::str::from_utf8(&hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            data.push(decoded.unwrap_or(b'?'));
+        } else {
+            data.push(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    #[test]
+    fn escapes_lines() {
+        assert_eq!(escape("100%\nsure"), "100%25%0Asure");
+
+        let mut data = Vec::new();
+        unescape("a%25b%0A", &mut data);
+        assert_eq!(data, b"a%b\n");
+    }
+
+    #[test]
+    fn talks_to_scripted_pinentry() {
+        let path = env::temp_dir().join(format!("kdbx-pinentry-{}", std::process::id()));
+        fs::write(
+            &path,
+            concat!(
+                "#!/bin/sh\n",
+                "echo 'OK Pleased to meet you'\n",
+                "while read -r cmd rest; do\n",
+                "  case \"$cmd\" in\n",
+                "    SETERROR) cancel=1; echo OK;;\n",
+                "    GETPIN) if [ -n \"$cancel\" ]; then echo 'ERR 83886179 Operation \
+                 cancelled';\n",
+                "            else echo 'D 50%25 off'; echo OK; fi;;\n",
+                "    *) echo OK;;\n",
+                "  esac\n",
+                "done\n"
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o700)).unwrap();
+
+        let mut pinentry = Pinentry::spawn(&path).unwrap();
+        pinentry.set_description("Unlock").unwrap();
+        let pin = pinentry.get_pin("Password:", None).unwrap();
+        let cancelled = pinentry.get_pin("Password:", Some("Wrong")).unwrap();
+        drop(pinentry);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(pin.as_ref().map(Pwd::expose), Some("50% off"));
+        assert!(cancelled.is_none());
+    }
+}
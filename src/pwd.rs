@@ -1,42 +1,67 @@
-use std::{convert::AsRef, ops::Deref, ptr, sync::atomic};
+use std::{ptr, sync::atomic};
 
 use log::*;
 
-#[derive(PartialEq, Clone, Default)]
-pub struct Pwd(String);
+use crate::secret::Secret;
 
-impl From<String> for Pwd {
-    fn from(pwd: String) -> Self {
-        Pwd(pwd)
-    }
-}
-
-impl Deref for Pwd {
-    type Target = str;
+/// A password kept in locked memory. Not `Clone` and not `Deref`: it's read
+/// with `expose` and copied with `duplicate` only.
+#[derive(Default)]
+pub struct Pwd(Secret);
 
-    fn deref(&self) -> &Self::Target {
-        self.0.as_ref()
+impl From<String> for Pwd {
+    fn from(mut pwd: String) -> Self {
+        // zeroes are valid UTF-8
+        Pwd(Secret::new(unsafe { pwd.as_bytes_mut() }))
     }
 }
 
-impl AsRef<str> for Pwd {
-    fn as_ref(&self) -> &str {
-        self
+impl PartialEq for Pwd {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.expose() == other.0.expose()
     }
 }
 
 impl Drop for Pwd {
     fn drop(&mut self) {
         info!("zeroing password memory");
-        zero_memory(&mut self.0)
     }
 }
 
-fn zero_memory(pwd: &mut str) {
-    unsafe {
-        for byte in pwd.as_bytes_mut() {
-            ptr::write_volatile(byte, 0x00);
+impl Pwd {
+    /// Moves the bytes into a password, zeroing them whether UTF-8 or not
+    pub fn from_utf8(mut bytes: Vec<u8>) -> Result<Pwd, String> {
+        if std::str::from_utf8(&bytes).is_err() {
+            zero_bytes(&mut bytes);
+            return Err("Stored password is not UTF-8".to_string());
         }
+        Ok(Pwd(Secret::new(&mut bytes)))
+    }
+
+    /// The password, readable while borrowed
+    pub fn expose(&self) -> &str {
+        // built from a `String` only
+        unsafe { std::str::from_utf8_unchecked(self.0.expose()) }
+    }
+
+    /// A locked copy of a password borrowed from elsewhere
+    pub fn copied(password: &str) -> Pwd {
+        Pwd(Secret::new(&mut password.as_bytes().to_vec()))
+    }
+
+    /// Another locked copy
+    pub fn duplicate(&self) -> Pwd {
+        Pwd::copied(self.expose())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+pub fn zero_bytes(bytes: &mut [u8]) {
+    for byte in bytes {
+        unsafe { ptr::write_volatile(byte, 0x00) };
     }
 
     atomic::fence(atomic::Ordering::SeqCst);
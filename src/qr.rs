@@ -0,0 +1,114 @@
+use std::{fs::OpenOptions, io::Write, os::unix::fs::OpenOptionsExt, path::Path};
+
+use image::{ImageFormat, Luma};
+use log::*;
+use qrcode::{QrCode, render::unicode::Dense1x2};
+
+use crate::{Result, pwd::Pwd};
+
+/// Decodes every QR code found in a PNG/JPEG image, locally
+pub fn decode_image(path: &Path) -> Result<Vec<String>> {
+    let img = image::open(path)
+        .map_err(|e| format!("Unable to read image `{}`: {e}", path.to_string_lossy()))?
+        .to_luma8();
+
+    let mut prepared = rqrr::PreparedImage::prepare(img);
+    let grids = prepared.detect_grids();
+    debug!("found {} QR grid(s)", grids.len());
+
+    let contents = grids
+        .iter()
+        .filter_map(|grid| match grid.decode() {
+            Ok((_, content)) => Some(content),
+            Err(e) => {
+                warn!("could not decode QR grid: {e}");
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if contents.is_empty() {
+        return Err(format!("No QR code found in `{}`", path.to_string_lossy()).into());
+    }
+
+    Ok(contents)
+}
+
+/// Reads a single `otpauth://` URI out of a QR code image
+pub fn decode_otpauth(path: &Path) -> Result<Pwd> {
+    let uri = decode_image(path)?
+        .into_iter()
+        .map(Pwd::from)
+        .find(|content| content.expose().starts_with("otpauth://"))
+        .ok_or_else(|| "QR code does not contain an otpauth:// URI".to_string())?;
+
+    crate::otp::parse_totp(uri.expose())?;
+
+    Ok(uri)
+}
+
+/// Renders data as a QR code made of half blocks, painted black on white so
+/// it scans regardless of the terminal's color scheme
+pub fn render_terminal(data: &str) -> Result<String> {
+    let code = QrCode::new(data.as_bytes()).map_err(|e| format!("Unable to encode QR: {e}"))?;
+    let art = code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Dark)
+        .light_color(Dense1x2::Light)
+        .quiet_zone(true)
+        .build();
+
+    Ok(art
+        .lines()
+        .map(|line| format!("\x1b[30;47m{line}\x1b[0m"))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Writes data as a PNG QR code readable by the owner only
+pub fn write_png(data: &str, path: &Path) -> Result<()> {
+    let code = QrCode::new(data.as_bytes()).map_err(|e| format!("Unable to encode QR: {e}"))?;
+    let img = code.render::<Luma<u8>>().min_dimensions(256, 256).build();
+
+    let mut buf = std::io::Cursor::new(Vec::new());
+    img.write_to(&mut buf, ImageFormat::Png)
+        .map_err(|e| format!("Unable to encode PNG: {e}"))?;
+
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?
+        .write_all(buf.get_ref())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_otpauth_from_png() {
+        let uri = decode_otpauth(Path::new("tests/files/totp.png")).expect("decoded qr");
+
+        assert_eq!(
+            uri.expose(),
+            "otpauth://totp/Example:alice@example.com?secret=JBSWY3DPEHPK3PXP&issuer=Example&\
+             period=30&digits=6"
+        );
+    }
+
+    #[test]
+    fn png_round_trip() {
+        let path = std::env::temp_dir().join(format!("kdbx-qr-{}.png", std::process::id()));
+        let uri = "otpauth://totp/Example:alice?secret=JBSWY3DPEHPK3PXP&issuer=Example";
+
+        write_png(uri, &path).expect("written png");
+        let decoded = decode_image(&path);
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(decoded.expect("decoded png"), vec![uri.to_string()]);
+    }
+}
@@ -0,0 +1,328 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex, atomic},
+    time::Duration,
+};
+
+use ratatui::crossterm::{
+    cursor::MoveToColumn,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    queue,
+    style::Print,
+    terminal::{self, Clear, ClearType},
+};
+
+use super::Session;
+use crate::{CANCEL, CANCEL_RQ_FREQ};
+
+/// How reading a line ended
+pub enum Input {
+    Line(String),
+    /// Ctrl-C, the line is dropped
+    Cancelled,
+    /// Ctrl-D on an empty line
+    End,
+}
+
+/// Reads command lines, completing paths of the session and keeping the
+/// history in memory only. Secrets are asked for apart from the command
+/// line, so they never end up in it.
+pub struct Editor {
+    session: Arc<Mutex<Session>>,
+    history: Vec<String>,
+}
+
+/// The line being edited, `cursor` counting chars
+#[derive(Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+/// Leaves raw mode however reading ends
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl Editor {
+    pub fn new(session: Arc<Mutex<Session>>) -> Self {
+        Editor {
+            session,
+            history: Vec::new(),
+        }
+    }
+
+    /// Lines starting with a space are left out, like in shells
+    pub fn remember(&mut self, line: &str) {
+        if !line.trim().is_empty() && !line.starts_with(' ') {
+            self.history.push(line.to_string());
+        }
+    }
+
+    /// Reads a line after the session's prompt, showing its notices above
+    /// the line as they come
+    pub fn read_line(&mut self) -> io::Result<Input> {
+        let _raw = RawMode::enable()?;
+        let mut out = io::stdout();
+        let tick = Duration::from_millis(1_000 / CANCEL_RQ_FREQ);
+
+        let mut prompt = self.prompt();
+        let mut line = Line::default();
+        // position in the history and the line typed before browsing it
+        let mut browsing: Option<(usize, Vec<char>)> = None;
+        render(&mut out, &prompt, &line)?;
+
+        loop {
+            if CANCEL.load(atomic::Ordering::SeqCst) {
+                return end(&mut out, Input::Cancelled);
+            }
+            if !event::poll(tick)? {
+                if let Some(notice) = self.notice() {
+                    queue!(
+                        out,
+                        MoveToColumn(0),
+                        Print(notice),
+                        Clear(ClearType::UntilNewLine)
+                    )?;
+                    queue!(out, Print("\r\n"))?;
+                    prompt = self.prompt();
+                    render(&mut out, &prompt, &line)?;
+                }
+                continue;
+            }
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+            match key.code {
+                KeyCode::Enter => return end(&mut out, Input::Line(line.text())),
+                KeyCode::Char('c') if ctrl => {
+                    queue!(out, Print("^C"))?;
+                    return end(&mut out, Input::Cancelled);
+                }
+                KeyCode::Char('d') if ctrl && line.chars.is_empty() => {
+                    return end(&mut out, Input::End);
+                }
+                KeyCode::Char('a') if ctrl => line.cursor = 0,
+                KeyCode::Char('e') if ctrl => line.cursor = line.chars.len(),
+                KeyCode::Char('u') if ctrl => {
+                    line.chars.drain(..line.cursor);
+                    line.cursor = 0;
+                }
+                KeyCode::Char(_) if ctrl => {}
+                KeyCode::Char(c) => line.insert(&[c]),
+                KeyCode::Backspace if line.cursor > 0 => {
+                    line.cursor -= 1;
+                    line.chars.remove(line.cursor);
+                }
+                KeyCode::Delete if line.cursor < line.chars.len() => {
+                    line.chars.remove(line.cursor);
+                }
+                KeyCode::Left => line.cursor = line.cursor.saturating_sub(1),
+                KeyCode::Right => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                KeyCode::Home => line.cursor = 0,
+                KeyCode::End => line.cursor = line.chars.len(),
+                KeyCode::Up => {
+                    let (idx, typed) = match browsing.take() {
+                        Some(browsed) => browsed,
+                        None => (self.history.len(), line.chars.clone()),
+                    };
+                    let idx = idx.saturating_sub(1);
+                    if let Some(previous) = self.history.get(idx) {
+                        line.set(previous.chars().collect());
+                    }
+                    browsing = Some((idx, typed));
+                }
+                KeyCode::Down => {
+                    browsing = match browsing.take() {
+                        Some((idx, typed)) => match self.history.get(idx + 1) {
+                            Some(next) => {
+                                line.set(next.chars().collect());
+                                Some((idx + 1, typed))
+                            }
+                            None => {
+                                line.set(typed);
+                                None
+                            }
+                        },
+                        None => None,
+                    };
+                }
+                KeyCode::Tab => self.complete(&mut out, &mut line)?,
+                _ => {}
+            }
+
+            render(&mut out, &prompt, &line)?;
+        }
+    }
+
+    fn prompt(&self) -> String {
+        self.session
+            .lock()
+            .map(|session| session.prompt())
+            .unwrap_or_default()
+    }
+
+    fn notice(&self) -> Option<String> {
+        self.session.lock().ok()?.take_notice()
+    }
+
+    /// Completes a single candidate, extends the word to what all of them
+    /// share or else lists them under the line
+    fn complete(&self, out: &mut impl Write, line: &mut Line) -> io::Result<()> {
+        let before = line.chars[..line.cursor].iter().collect::<String>();
+        let (start, word) = current_word(&before);
+        let first = before[..start].trim().is_empty();
+
+        let candidates = match self.session.lock() {
+            Ok(session) => session.complete(&word, first),
+            Err(_) => return Ok(()),
+        };
+        let candidates = candidates
+            .iter()
+            .map(|path| path.replace(' ', "\\ "))
+            .collect::<Vec<_>>();
+
+        let start = before[..start].chars().count();
+        let typed = line.cursor - start;
+        let completion = match candidates.as_slice() {
+            [] => return Ok(()),
+            [single] if single.ends_with('/') => single.clone(),
+            [single] => format!("{single} "),
+            _ => common_prefix(&candidates),
+        };
+
+        if completion.chars().count() > typed {
+            line.chars.drain(start..line.cursor);
+            line.cursor = start;
+            line.insert(&completion.chars().collect::<Vec<_>>());
+        } else {
+            queue!(
+                out,
+                Print("\r\n"),
+                Print(candidates.join("  ")),
+                Print("\r\n")
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn insert(&mut self, chars: &[char]) {
+        self.chars
+            .splice(self.cursor..self.cursor, chars.iter().copied());
+        self.cursor += chars.len();
+    }
+
+    fn set(&mut self, chars: Vec<char>) {
+        self.cursor = chars.len();
+        self.chars = chars;
+    }
+}
+
+/// Redraws the prompt and the line, which is expected to fit in the
+/// terminal's width
+fn render(out: &mut impl Write, prompt: &str, line: &Line) -> io::Result<()> {
+    let column = prompt.chars().count() + line.cursor;
+    queue!(
+        out,
+        MoveToColumn(0),
+        Print(prompt),
+        Print(line.text()),
+        Clear(ClearType::UntilNewLine),
+        MoveToColumn(u16::try_from(column).unwrap_or(u16::MAX)),
+    )?;
+    out.flush()
+}
+
+fn end(out: &mut impl Write, input: Input) -> io::Result<Input> {
+    queue!(out, Print("\r\n"))?;
+    out.flush()?;
+    Ok(input)
+}
+
+/// Start and unescaped text of the word ending at the cursor
+fn current_word(line: &str) -> (usize, String) {
+    let mut start = 0;
+    let mut word = String::new();
+    let mut escaped = false;
+
+    for (idx, c) in line.char_indices() {
+        match c {
+            _ if escaped => {
+                word.push(c);
+                escaped = false;
+            }
+            '\\' => escaped = true,
+            ' ' => {
+                start = idx + 1;
+                word.clear();
+            }
+            c => word.push(c),
+        }
+    }
+
+    (start, word)
+}
+
+fn common_prefix(candidates: &[String]) -> String {
+    let Some((first, rest)) = candidates.split_first() else {
+        return String::new();
+    };
+
+    let mut prefix = first.as_str();
+    for candidate in rest {
+        let len = prefix
+            .char_indices()
+            .zip(candidate.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(prefix.len().min(candidate.len()), |((idx, _), _)| idx);
+        prefix = &prefix[..len];
+    }
+
+    prefix.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_escaped_word() {
+        assert_eq!(
+            current_word("show Work/Mail\\ b"),
+            (5, "Work/Mail b".into())
+        );
+        assert_eq!(current_word("ls "), (3, String::new()));
+        assert_eq!(current_word("sh"), (0, "sh".into()));
+    }
+
+    #[test]
+    fn shares_common_prefix() {
+        let candidates = ["Work/Mail\\ box", "Work/Mail\\ bag", "Work/"].map(String::from);
+        assert_eq!(common_prefix(&candidates[..2]), "Work/Mail\\ b");
+        assert_eq!(common_prefix(&candidates), "Work/");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
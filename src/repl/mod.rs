@@ -0,0 +1,767 @@
+mod editor;
+
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use keepass::db::{Entry, Group, Node, Value};
+use uuid::Uuid;
+
+pub use self::editor::{Editor, Input};
+use crate::{
+    Result,
+    clipboard::{set_clipboard, unmarked_warning},
+    keepass::{
+        EXPIRED_MARKER, EntryPath, OpenedDatabase, find_entry_by_uuid, find_entry_by_uuid_mut,
+        get_entries, is_expired, show_entry, unlock_database, unlock_database_with_key_file,
+    },
+    otp::{parse_totp, raw_otp},
+    pwd::Pwd,
+};
+
+const COMMANDS: [&str; 15] = [
+    "ls", "cd", "show", "pwd", "totp", "add", "edit", "rm", "mv", "save", "lock", "help", "exit",
+    "quit", "exit!",
+];
+
+const HELP: &str = "\
+ls [GROUP]              List groups and entries
+cd [GROUP]              Change the current group, `..` is the parent, `/` the root
+show ENTRY [-s]         Display entry's info, -s shows sensitive fields
+pwd ENTRY               Copy entry's password
+totp ENTRY              Copy entry's TOTP code
+add TITLE               Add an entry to the current group, asking for its fields
+edit ENTRY FIELD        Change a field, e.g. `edit github password`
+rm ENTRY|GROUP          Remove an entry or an empty group
+mv ENTRY|GROUP GROUP    Move an entry or a group into another group
+save                    Write the changes to the database
+lock                    Lock the database until the password is given again
+exit, quit              Leave, `exit!` discards unsaved changes
+
+Passwords and field values are asked for separately and never kept in the history.";
+
+/// Asks the user for the values commands need
+pub trait Ask {
+    fn text(&mut self, prompt: &str) -> String;
+    fn secret(&mut self, prompt: &str) -> Pwd;
+}
+
+pub enum Flow {
+    Continue(String),
+    Exit,
+}
+
+/// The unlocked database with the current group and unsaved changes
+pub struct Session {
+    name: String,
+    path: PathBuf,
+    key_file: Option<PathBuf>,
+    /// Dropped while locked
+    opened: Option<OpenedDatabase>,
+    /// Unsaved changes kept while locked, given back once the password is
+    /// checked again
+    kept: Option<OpenedDatabase>,
+    /// Group names below the root
+    cwd: Vec<String>,
+    dirty: bool,
+    last_activity: Instant,
+    lock_after: Option<Duration>,
+    clipboard_timeout: Option<Duration>,
+    clear_clipboard_at: Option<Instant>,
+    notice: Option<String>,
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.clear_clipboard();
+    }
+}
+
+/// What a path points at
+enum Target {
+    Entry(Uuid),
+    Group(Vec<String>),
+}
+
+impl Session {
+    /// `lock_after` and `clipboard_timeout` of `None` never lock or clear
+    pub fn new(
+        opened: OpenedDatabase,
+        lock_after: Option<Duration>,
+        clipboard_timeout: Option<Duration>,
+    ) -> Self {
+        Session {
+            name: opened.name.clone(),
+            path: opened.path.clone(),
+            key_file: opened.key_file.clone(),
+            opened: Some(opened),
+            kept: None,
+            cwd: Vec::new(),
+            dirty: false,
+            last_activity: Instant::now(),
+            lock_after,
+            clipboard_timeout,
+            clear_clipboard_at: None,
+            notice: None,
+        }
+    }
+
+    /// Doesn't look like the command line while locked, so the password
+    /// isn't typed there
+    pub fn prompt(&self) -> String {
+        if self.is_locked() {
+            return format!("{} (locked, Enter to unlock)> ", self.name);
+        }
+        format!(
+            "{}:/{}{}> ",
+            self.name,
+            self.cwd.join("/"),
+            if self.dirty { "*" } else { "" }
+        )
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.opened.is_none()
+    }
+
+    /// Clears the copied value once its timeout has passed
+    pub fn clear_clipboard_if_due(&mut self, now: Instant) -> bool {
+        if self.clear_clipboard_at.is_none_or(|at| now < at) {
+            return false;
+        }
+        self.clear_clipboard();
+        true
+    }
+
+    /// Clears the copied value if it's still waiting to be cleared
+    pub fn clear_clipboard(&mut self) {
+        if self.clear_clipboard_at.take().is_some() {
+            let _ = set_clipboard(None);
+        }
+    }
+
+    /// Locks once idle for too long, unsaved changes included
+    pub fn lock_if_idle(&mut self, now: Instant) -> bool {
+        let idle = self
+            .lock_after
+            .is_some_and(|after| now.duration_since(self.last_activity) >= after);
+        if !idle || self.is_locked() {
+            return false;
+        }
+
+        self.lock();
+        true
+    }
+
+    /// What happened while waiting for a command
+    pub fn take_notice(&mut self) -> Option<String> {
+        self.notice.take()
+    }
+
+    /// The stored password isn't used: unlocking takes the password again.
+    /// Unsaved changes kept while locked replace the database read back.
+    pub fn unlock(&mut self, password: Pwd) -> Result<()> {
+        let (db, credentials) = match self.key_file.as_deref() {
+            Some(keyfile) if password.is_empty() => {
+                unlock_database_with_key_file(&self.path, keyfile)?
+            }
+            keyfile => unlock_database(password, &self.path, keyfile, false)
+                .map_err(|_| "Invalid password or key")?,
+        };
+
+        let db = match self.kept.take() {
+            Some(kept) => kept.db,
+            None => db,
+        };
+        self.opened = Some(OpenedDatabase {
+            name: self.name.clone(),
+            path: self.path.clone(),
+            key_file: self.key_file.clone(),
+            db,
+            credentials,
+        });
+        self.last_activity = Instant::now();
+        if self.group(&self.cwd).is_none() {
+            self.cwd.clear();
+        }
+
+        Ok(())
+    }
+
+    pub fn execute(&mut self, line: &str, ask: &mut dyn Ask) -> Result<Flow> {
+        self.last_activity = Instant::now();
+
+        let words = split_words(line);
+        let Some((command, args)) = words.split_first() else {
+            return Ok(Flow::Continue(String::new()));
+        };
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let output = match (command.as_str(), args.as_slice()) {
+            ("exit" | "quit", []) if self.dirty => {
+                return Err("Unsaved changes, `save` them or `exit!` to discard them".into());
+            }
+            ("exit" | "quit" | "exit!", []) => return Ok(Flow::Exit),
+            ("help", []) => HELP.to_string(),
+            ("lock", []) => {
+                self.lock();
+                String::new()
+            }
+            _ if self.is_locked() => return Err("Locked".into()),
+            ("ls", []) => self.list(&self.cwd.clone())?,
+            ("ls", [path]) => self.list(&resolve(&self.cwd, path))?,
+            ("cd", []) => {
+                self.cwd.clear();
+                String::new()
+            }
+            ("cd", [path]) => {
+                let path = resolve(&self.cwd, path);
+                self.group(&path).ok_or("No such group")?;
+                self.cwd = path;
+                String::new()
+            }
+            ("show", [entry]) => show_entry(self.entry(entry)?, false),
+            ("show", [entry, "-s" | "--show-sensitive"]) => show_entry(self.entry(entry)?, true),
+            ("pwd", [entry]) => {
+                let password = self.entry(entry)?.get_password().unwrap_or_default();
+                let password = Pwd::from(password.to_string());
+                self.copy(password)?
+            }
+            ("totp", [entry]) => {
+                let raw = raw_otp(self.entry(entry)?).ok_or("Entry has no TOTP secret")?;
+                let code = parse_totp(raw.expose())?.value_now()?;
+                self.copy(code.code.into())?
+            }
+            ("add", [title]) => self.add(title, ask)?,
+            ("edit", [entry, field]) => self.edit(entry, field, ask)?,
+            ("rm", [path]) => self.remove(path)?,
+            ("mv", [path, group]) => self.move_to(path, group)?,
+            ("save", []) => {
+                self.save(ask)?;
+                "Saved".to_string()
+            }
+            (command, _) if COMMANDS.contains(&command) => {
+                return Err(format!("Wrong arguments for `{command}`, see `help`").into());
+            }
+            (command, _) => return Err(format!("Unknown command `{command}`, see `help`").into()),
+        };
+
+        Ok(Flow::Continue(output))
+    }
+
+    /// A key read from the keyring can't save, the password is asked for
+    pub fn save(&mut self, ask: &mut dyn Ask) -> Result<()> {
+        if let Some(opened) = self.opened.as_mut() {
+            if opened.credentials.needs_password() {
+                let password = ask.secret("Password to save: ");
+                opened
+                    .credentials
+                    .add_password(password, opened.key_file.as_deref())
+                    .map_err(|_| "Invalid password or key")?;
+            }
+            opened.save_in_place()?;
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn lock(&mut self) {
+        let Some(opened) = self.opened.take() else {
+            return;
+        };
+        if self.dirty {
+            self.kept = Some(opened);
+            self.notice = Some("Locked, unsaved changes are kept until unlocked".to_string());
+        } else {
+            self.notice = Some("Locked".to_string());
+        }
+    }
+
+    fn list(&self, path: &[String]) -> Result<String> {
+        let group = self.group(path).ok_or("No such group")?;
+        let lines = group
+            .children
+            .iter()
+            .map(|node| match node {
+                Node::Group(child) => format!("{}/", child.name),
+                Node::Entry(entry) if is_expired(entry) => {
+                    format!("{}{EXPIRED_MARKER}", entry.get_title().unwrap_or_default())
+                }
+                Node::Entry(entry) => entry.get_title().unwrap_or_default().to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        Ok(lines.join("\n"))
+    }
+
+    fn copy(&mut self, value: Pwd) -> Result<String> {
+        set_clipboard(Some(value)).map_err(|_| "Clipboard unavailable")?;
+
+        self.clear_clipboard_at = self.clipboard_timeout.map(|t| Instant::now() + t);
+        let copied = match self.clipboard_timeout {
+            Some(timeout) => format!(
+                "Copied to the clipboard, clearing in {} seconds",
+                timeout.as_secs()
+            ),
+            None => "Copied to the clipboard".to_string(),
+        };
+        Ok(match unmarked_warning() {
+            Some(warning) => format!("{copied}\n{warning}"),
+            None => copied,
+        })
+    }
+
+    fn add(&mut self, title: &str, ask: &mut dyn Ask) -> Result<String> {
+        let username = ask.text("Username: ");
+        let password = ask.secret("Password: ");
+        let url = ask.text("URL: ");
+
+        let mut entry = Entry::new();
+        for (name, value) in [("Title", title), ("UserName", &username), ("URL", &url)] {
+            entry
+                .fields
+                .insert(name.to_string(), Value::Unprotected(value.to_string()));
+        }
+        entry.fields.insert(
+            "Password".to_string(),
+            Value::Protected(password.expose().as_bytes().into()),
+        );
+
+        let cwd = self.cwd.clone();
+        self.group_mut(&cwd)
+            .ok_or("No such group")?
+            .children
+            .push(Node::Entry(entry));
+        self.dirty = true;
+
+        Ok(String::new())
+    }
+
+    fn edit(&mut self, query: &str, field: &str, ask: &mut dyn Ask) -> Result<String> {
+        let uuid = *self.entry(query)?.get_uuid();
+        let name = field_name(field);
+        let protected = match self.entry(query)?.fields.get(&name) {
+            Some(value) => matches!(value, Value::Protected(_)),
+            None => name == "Password",
+        };
+
+        let value = if protected {
+            let value = ask.secret(&format!("{name}: "));
+            Value::Protected(value.expose().as_bytes().into())
+        } else {
+            Value::Unprotected(ask.text(&format!("{name}: ")))
+        };
+
+        let entry = self
+            .opened
+            .as_mut()
+            .and_then(|opened| find_entry_by_uuid_mut(&mut opened.db.root, &uuid))
+            .ok_or("Not found")?;
+        entry.fields.insert(name, value);
+        entry.update_history();
+        self.dirty = true;
+
+        Ok(String::new())
+    }
+
+    fn remove(&mut self, path: &str) -> Result<String> {
+        match self.target(path)? {
+            Target::Entry(uuid) => {
+                self.take_entry(&uuid).ok_or("Not found")?;
+            }
+            Target::Group(path) => {
+                let Some((name, parent)) = path.split_last() else {
+                    return Err("The root group can't be removed".into());
+                };
+                if self
+                    .group(&path)
+                    .is_some_and(|group| !group.children.is_empty())
+                {
+                    return Err("Group is not empty".into());
+                }
+                self.group_mut(parent)
+                    .ok_or("No such group")?
+                    .children
+                    .retain(|node| !matches!(node, Node::Group(group) if &group.name == name));
+                if self.cwd.starts_with(&path) {
+                    self.cwd = parent.to_vec();
+                }
+            }
+        }
+        self.dirty = true;
+
+        Ok(String::new())
+    }
+
+    fn move_to(&mut self, path: &str, group: &str) -> Result<String> {
+        let destination = resolve(&self.cwd, group);
+        self.group(&destination).ok_or("No such group")?;
+
+        let node = match self.target(path)? {
+            Target::Entry(uuid) => Node::Entry(self.take_entry(&uuid).ok_or("Not found")?),
+            Target::Group(path) => {
+                let Some((name, parent)) = path.split_last() else {
+                    return Err("The root group can't be moved".into());
+                };
+                if destination.starts_with(&path) {
+                    return Err("A group can't be moved into itself".into());
+                }
+                let parent = self.group_mut(parent).ok_or("No such group")?;
+                let idx = parent
+                    .children
+                    .iter()
+                    .position(|node| matches!(node, Node::Group(group) if &group.name == name))
+                    .ok_or("No such group")?;
+                let node = parent.children.remove(idx);
+                // follow the group when inside it
+                if let Some(rest) = self.cwd.strip_prefix(path.as_slice()) {
+                    self.cwd = [destination.as_slice(), std::slice::from_ref(name), rest].concat();
+                }
+                node
+            }
+        };
+
+        // the destination's path is the same once the node is out, as
+        // moving into oneself is refused
+        self.group_mut(&destination)
+            .ok_or("No such group")?
+            .children
+            .push(node);
+        self.dirty = true;
+
+        Ok(String::new())
+    }
+
+    /// An entry by its path from the current group, or else by the end of
+    /// its path like the other commands find them
+    fn entry(&self, query: &str) -> Result<&Entry> {
+        let root = &self.opened.as_ref().ok_or("Locked")?.db.root;
+        if let Target::Entry(uuid) = self.target(query)? {
+            return find_entry_by_uuid(root, &uuid).ok_or_else(|| "Not found".into());
+        }
+        Err("Not found".into())
+    }
+
+    fn target(&self, query: &str) -> Result<Target> {
+        let root = &self.opened.as_ref().ok_or("Locked")?.db.root;
+        let path = resolve(&self.cwd, query);
+
+        if let Some((title, parent)) = path.split_last()
+            && let Some(entry) = self.group(parent).and_then(|group| {
+                group.children.iter().find_map(|node| match node {
+                    Node::Entry(entry) if entry.get_title() == Some(title.as_str()) => Some(entry),
+                    _ => None,
+                })
+            })
+        {
+            return Ok(Target::Entry(entry.uuid));
+        }
+        if self.group(&path).is_some() {
+            return Ok(Target::Group(path));
+        }
+
+        get_entries(root, "")
+            .into_iter()
+            .find(|wrapped| wrapped.entry_path().ends_with(query))
+            .map(|wrapped| Target::Entry(wrapped.entry.uuid))
+            .ok_or_else(|| "Not found".into())
+    }
+
+    fn take_entry(&mut self, uuid: &Uuid) -> Option<Entry> {
+        fn take(group: &mut Group, uuid: &Uuid) -> Option<Entry> {
+            if let Some(idx) = group
+                .children
+                .iter()
+                .position(|node| matches!(node, Node::Entry(entry) if &entry.uuid == uuid))
+            {
+                let Node::Entry(entry) = group.children.remove(idx) else {
+                    unreachable!("found an entry");
+                };
+                return Some(entry);
+            }
+            group.children.iter_mut().find_map(|node| match node {
+                Node::Group(child) => take(child, uuid),
+                Node::Entry(_) => None,
+            })
+        }
+
+        take(&mut self.opened.as_mut()?.db.root, uuid)
+    }
+
+    fn group(&self, path: &[String]) -> Option<&Group> {
+        let mut group = &self.opened.as_ref()?.db.root;
+        for name in path {
+            group = group.children.iter().find_map(|node| match node {
+                Node::Group(child) if &child.name == name => Some(child),
+                _ => None,
+            })?;
+        }
+        Some(group)
+    }
+
+    fn group_mut(&mut self, path: &[String]) -> Option<&mut Group> {
+        let mut group = &mut self.opened.as_mut()?.db.root;
+        for name in path {
+            group = group.children.iter_mut().find_map(|node| match node {
+                Node::Group(child) if &child.name == name => Some(child),
+                _ => None,
+            })?;
+        }
+        Some(group)
+    }
+
+    /// Paths starting with `word`: commands for the first word, else groups
+    /// (ending with `/`) and entries from the current group or the root
+    pub fn complete(&self, word: &str, first: bool) -> Vec<String> {
+        if first {
+            return COMMANDS
+                .iter()
+                .filter(|command| command.starts_with(word))
+                .map(|command| command.to_string())
+                .collect();
+        }
+
+        let (base, prefix) = if word.starts_with('/') {
+            (Vec::new(), "/")
+        } else {
+            (self.cwd.clone(), "")
+        };
+        let Some(group) = self.group(&base) else {
+            return Vec::new();
+        };
+
+        // paths of `get_entries` start with the group's own name
+        let own = format!("/{}/", group.name);
+        let mut paths = Vec::new();
+        collect_groups(group, prefix, &mut paths);
+        paths.extend(get_entries(group, "").iter().map(|wrapped| {
+            let path = wrapped.entry_path();
+            format!("{prefix}{}", path.strip_prefix(&own).unwrap_or(&path))
+        }));
+
+        paths.retain(|path| path.starts_with(word));
+        paths
+    }
+}
+
+/// Maps the usual lowercase names to KeePass field names
+fn field_name(field: &str) -> String {
+    match field.to_lowercase().as_str() {
+        "title" => "Title",
+        "username" | "user" => "UserName",
+        "password" | "pass" => "Password",
+        "url" => "URL",
+        "notes" => "Notes",
+        _ => field,
+    }
+    .to_string()
+}
+
+fn collect_groups(group: &Group, prefix: &str, paths: &mut Vec<String>) {
+    for node in &group.children {
+        if let Node::Group(child) = node {
+            let path = format!("{prefix}{}/", child.name);
+            collect_groups(child, &path, paths);
+            paths.push(path);
+        }
+    }
+}
+
+/// Group names of a path from the current group, `..` going up and `/`
+/// starting from the root
+fn resolve(cwd: &[String], path: &str) -> Vec<String> {
+    let mut resolved = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        cwd.to_vec()
+    };
+    for name in path.split('/') {
+        match name {
+            "" | "." => {}
+            ".." => {
+                resolved.pop();
+            }
+            name => resolved.push(name.to_string()),
+        }
+    }
+    resolved
+}
+
+/// Splits on spaces, keeping quoted and backslash-escaped ones
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', _) => {
+                word.extend(chars.next());
+                in_word = true;
+            }
+            ('"' | '\'', None) => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (c, Some(q)) if c == q => quote = None,
+            (c, None) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (c, _) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, env, fs};
+
+    use keepass::{
+        Database,
+        config::{DatabaseConfig, KdfConfig},
+    };
+
+    use super::*;
+    use crate::keepass::{Credentials, open_database};
+
+    struct Script(VecDeque<&'static str>);
+
+    impl Ask for Script {
+        fn text(&mut self, _: &str) -> String {
+            self.0.pop_front().unwrap().to_string()
+        }
+
+        fn secret(&mut self, _: &str) -> Pwd {
+            self.0.pop_front().unwrap().to_string().into()
+        }
+    }
+
+    fn session(name: &str) -> Session {
+        let config = DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 1 },
+            ..Default::default()
+        };
+        let mut db = Database::new(config);
+        let mut work = Group::new("Work");
+        let mut entry = Entry::new();
+        entry.fields.insert(
+            "Title".to_string(),
+            Value::Unprotected("Mail box".to_string()),
+        );
+        work.children.push(Node::Entry(entry));
+        db.root.children.push(Node::Group(work));
+
+        let path = env::temp_dir().join(format!("kdbx-repl-{}-{name}.kdbx", std::process::id()));
+        let opened = OpenedDatabase {
+            name: name.to_string(),
+            path,
+            key_file: None,
+            db,
+            credentials: Credentials::Password("test".to_string().into()),
+        };
+
+        Session::new(opened, Some(Duration::from_secs(60)), None)
+    }
+
+    fn run(session: &mut Session, line: &str, answers: &[&'static str]) -> Result<String> {
+        let mut script = Script(answers.iter().copied().collect());
+        match session.execute(line, &mut script)? {
+            Flow::Continue(output) => Ok(output),
+            Flow::Exit => Ok("<exit>".to_string()),
+        }
+    }
+
+    #[test]
+    fn splits_words() {
+        assert_eq!(
+            split_words(r#"mv "Mail box" Work\ Old '' "#),
+            ["mv", "Mail box", "Work Old", ""]
+        );
+        assert_eq!(resolve(&["Work".to_string()], "../Home/./x"), ["Home", "x"]);
+        assert!(resolve(&["Work".to_string()], "/").is_empty());
+    }
+
+    #[test]
+    fn navigates_and_completes() {
+        let mut session = session("navigate");
+
+        assert_eq!(run(&mut session, "ls", &[]).unwrap(), "Work/");
+        run(&mut session, "cd Work", &[]).unwrap();
+        assert_eq!(session.prompt(), "navigate:/Work> ");
+        assert_eq!(run(&mut session, "ls", &[]).unwrap(), "Mail box");
+        assert!(run(&mut session, "cd Nope", &[]).is_err());
+
+        assert_eq!(session.complete("Ma", false), ["Mail box"]);
+        assert_eq!(session.complete("/W", false), ["/Work/", "/Work/Mail box"]);
+        assert_eq!(session.complete("sh", true), ["show"]);
+
+        assert_eq!(
+            run(&mut session, "show \"Mail box\"", &[]).unwrap(),
+            "Title: Mail box"
+        );
+    }
+
+    #[test]
+    fn tracks_changes_until_saved() {
+        let mut session = session("changes");
+
+        run(&mut session, "add GitHub", &["octocat", "hunter2", ""]).unwrap();
+        run(&mut session, "mv GitHub Work", &[]).unwrap();
+        assert!(run(&mut session, "mv Work Work", &[]).is_err());
+        run(&mut session, "edit Work/GitHub password", &["changed"]).unwrap();
+        run(&mut session, "rm \"Work/Mail box\"", &[]).unwrap();
+        assert_eq!(session.prompt(), "changes:/*> ");
+        assert!(run(&mut session, "exit", &[]).is_err());
+
+        run(&mut session, "save", &[]).unwrap();
+        assert_eq!(run(&mut session, "exit", &[]).unwrap(), "<exit>");
+
+        let db = open_database(Some("test".to_string().into()), &session.path, None).unwrap();
+        fs::remove_file(&session.path).unwrap();
+        let work = db.root.groups()[0];
+        let entries = work.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get_username(), Some("octocat"));
+        assert_eq!(entries[0].get_password(), Some("changed"));
+    }
+
+    #[test]
+    fn locks_when_idle_keeping_changes() {
+        let mut session = session("idle");
+        run(&mut session, "save", &[]).unwrap();
+        run(&mut session, "add GitHub", &["", "", ""]).unwrap();
+
+        assert!(!session.lock_if_idle(Instant::now()));
+        assert!(session.lock_if_idle(Instant::now() + Duration::from_secs(61)));
+        assert!(session.is_locked());
+        assert_eq!(session.prompt(), "idle (locked, Enter to unlock)> ");
+        assert!(session.take_notice().is_some());
+        assert!(run(&mut session, "ls", &[]).is_err());
+        assert!(session.complete("", false).is_empty());
+
+        run(&mut session, "save", &[]).unwrap_err();
+        assert!(session.unlock("wrong".to_string().into()).is_err());
+        assert!(session.is_locked());
+        session.unlock("test".to_string().into()).unwrap();
+        fs::remove_file(&session.path).unwrap();
+        assert_eq!(session.prompt(), "idle:/*> ");
+        assert_eq!(run(&mut session, "ls", &[]).unwrap(), "Work/\nGitHub");
+
+        run(&mut session, "lock", &[]).unwrap();
+        assert!(session.is_locked());
+    }
+}
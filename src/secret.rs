@@ -0,0 +1,175 @@
+use std::{ptr::NonNull, sync::Once};
+
+use log::*;
+
+use crate::pwd::zero_bytes;
+
+static MLOCK_WARNING: Once = Once::new();
+
+/// Bytes kept in their own pages, locked in RAM so they are never swapped,
+/// left out of core dumps and fenced by inaccessible guard pages. Zeroed
+/// and unmapped on drop. Deliberately not `Clone`: every copy of a secret
+/// is explicit.
+pub struct Secret {
+    /// Start of the mapping, the leading guard page
+    map: Option<NonNull<u8>>,
+    map_len: usize,
+    len: usize,
+}
+
+// The bytes are never changed once written
+unsafe impl Send for Secret {}
+unsafe impl Sync for Secret {}
+
+impl Default for Secret {
+    fn default() -> Self {
+        Secret::new(&mut [])
+    }
+}
+
+impl Secret {
+    /// Moves the bytes into locked memory, zeroing the source
+    pub fn new(data: &mut [u8]) -> Self {
+        if data.is_empty() {
+            return Secret {
+                map: None,
+                map_len: 0,
+                len: 0,
+            };
+        }
+
+        let page = page_size();
+        let data_len = data.len().div_ceil(page) * page;
+        let map_len = data_len + 2 * page;
+
+        let map = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                map_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if map == libc::MAP_FAILED {
+            zero_bytes(data);
+            panic!(
+                "unable to map memory for a secret: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+        let map = map.cast::<u8>();
+
+        unsafe {
+            let region = map.add(page);
+            if libc::mlock(region.cast(), data_len) != 0 {
+                let err = std::io::Error::last_os_error();
+                MLOCK_WARNING.call_once(|| warn!("unable to lock secrets in memory: {err}"));
+            }
+            #[cfg(target_os = "linux")]
+            libc::madvise(region.cast(), data_len, libc::MADV_DONTDUMP);
+
+            libc::mprotect(map.cast(), page, libc::PROT_NONE);
+            libc::mprotect(region.add(data_len).cast(), page, libc::PROT_NONE);
+        }
+
+        let secret = Secret {
+            map: NonNull::new(map),
+            map_len,
+            len: data.len(),
+        };
+        // right before the trailing guard page, so overruns fault
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), secret.start(), data.len());
+        }
+        zero_bytes(data);
+
+        secret
+    }
+
+    /// The bytes, readable while borrowed
+    pub fn expose(&self) -> &[u8] {
+        match self.map {
+            Some(_) => unsafe { std::slice::from_raw_parts(self.start(), self.len) },
+            None => &[],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn start(&self) -> *mut u8 {
+        let map = self.map.expect("mapped").as_ptr();
+        unsafe { map.add(self.map_len - page_size() - self.len) }
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        let Some(map) = self.map else {
+            return;
+        };
+
+        unsafe {
+            zero_bytes(std::slice::from_raw_parts_mut(self.start(), self.len));
+            let page = page_size();
+            libc::munlock(map.as_ptr().add(page).cast(), self.map_len - 2 * page);
+            libc::munmap(map.as_ptr().cast(), self.map_len);
+        }
+    }
+}
+
+/// Keeps secrets out of core dumps and away from debuggers of the same user
+pub fn disable_core_dumps() {
+    unsafe {
+        let limit = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        if libc::setrlimit(libc::RLIMIT_CORE, &limit) != 0 {
+            warn!(
+                "unable to disable core dumps: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+
+        #[cfg(target_os = "linux")]
+        if libc::prctl(libc::PR_SET_DUMPABLE, 0) != 0 {
+            warn!(
+                "unable to make the process not dumpable: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_bytes_into_locked_pages() {
+        let mut data = b"test123".to_vec();
+        let secret = Secret::new(&mut data);
+
+        assert_eq!(secret.expose(), b"test123");
+        assert_eq!(data, [0; 7]);
+        assert_eq!(secret.start() as usize % page_size(), page_size() - 7);
+    }
+
+    #[test]
+    fn spans_pages() {
+        let mut data = vec![7; page_size() + 1];
+        let secret = Secret::new(&mut data);
+
+        assert_eq!(secret.expose().len(), page_size() + 1);
+        assert!(secret.expose().iter().all(|&b| b == 7));
+        assert!(Secret::new(&mut []).is_empty());
+    }
+}
@@ -3,7 +3,9 @@ use std::{
     mem::MaybeUninit,
 };
 
-use libc::{ECHO, ECHONL, STDIN_FILENO, TCSANOW, isatty, tcgetattr, tcsetattr};
+use libc::{
+    ECHO, ECHONL, ICANON, STDIN_FILENO, TCSANOW, VMIN, VTIME, isatty, tcgetattr, tcsetattr,
+};
 use log::*;
 
 use crate::pwd::Pwd;
@@ -36,6 +38,16 @@ impl Stdin {
         text
     }
 
+    /// Waits up to `timeout` tenths of a second for a single key press.
+    /// The TTY stays in non-canonical mode until `reset_tty` is called.
+    pub fn read_key(&self, timeout: u8) -> Option<u8> {
+        read_key(self.0?, timeout)
+    }
+
+    pub fn is_tty(&self) -> bool {
+        self.0.is_some()
+    }
+
     pub fn reset_tty(&self) {
         info!("resetting TTY params");
         reset_impl(self.0);
@@ -99,13 +111,27 @@ fn read_text(tty: Option<libc::termios>) -> ::std::io::Result<String> {
     Ok(text)
 }
 
+fn read_key(mut termios: libc::termios, timeout: u8) -> Option<u8> {
+    termios.c_lflag &= !(ICANON | ECHO);
+    termios.c_cc[VMIN] = 0;
+    termios.c_cc[VTIME] = timeout;
+
+    let mut key = 0u8;
+    let read = unsafe {
+        tcsetattr(STDIN_FILENO, TCSANOW, &termios);
+        libc::read(STDIN_FILENO, (&mut key as *mut u8).cast(), 1)
+    };
+
+    (read == 1).then_some(key)
+}
+
 fn reset_impl(termios: Option<libc::termios>) {
     if let Some(termios) = termios {
         unsafe { tcsetattr(STDIN_FILENO, TCSANOW, &termios) };
     }
 }
 
-fn trim_newlines(text: &mut String) {
+pub fn trim_newlines(text: &mut String) {
     while text.ends_with(['\n', '\r'].as_ref()) {
         text.pop();
     }
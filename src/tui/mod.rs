@@ -0,0 +1,1002 @@
+mod ui;
+
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use keepass::db::{Entry, Group, Node, Value};
+use log::*;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use uuid::Uuid;
+
+pub use self::ui::draw;
+use crate::{
+    clipboard::{set_clipboard, unmarked_warning},
+    keepass::{
+        OpenedDatabase, find_entry_by_uuid, find_entry_by_uuid_mut, find_group_by_uuid,
+        find_group_by_uuid_mut, unlock_database, unlock_database_with_key_file,
+    },
+    otp::{parse_totp, raw_otp},
+    pwd::{Pwd, zero_bytes},
+};
+
+const STANDARD_FIELDS: [&str; 5] = ["Title", "UserName", "Password", "URL", "Notes"];
+const MASKED_VALUE: &str = "******";
+/// Row of the details showing the current TOTP code
+const TOTP_ROW: &str = "TOTP";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Groups,
+    Entries,
+    Fields,
+}
+
+/// What the input line at the bottom is asking for
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Prompt {
+    Search,
+    EditField(String),
+    NewFieldName,
+    /// Name of the field and whether it's protected
+    NewFieldValue(String, bool),
+    NewEntry,
+    NewGroup,
+    RenameGroup,
+    Unlock,
+    /// Saving with a key read from the keyring takes the password again
+    SavePassword,
+}
+
+impl Prompt {
+    fn label(&self) -> String {
+        match self {
+            Prompt::Search => "Search: ".to_string(),
+            Prompt::EditField(name) | Prompt::NewFieldValue(name, _) => format!("{name}: "),
+            Prompt::NewFieldName => "Field name (ending with ! to protect it): ".to_string(),
+            Prompt::NewEntry => "Title: ".to_string(),
+            Prompt::NewGroup | Prompt::RenameGroup => "Group name: ".to_string(),
+            Prompt::Unlock => "Password: ".to_string(),
+            Prompt::SavePassword => "Password to save: ".to_string(),
+        }
+    }
+}
+
+struct GroupRow {
+    uuid: Uuid,
+    name: String,
+    depth: usize,
+}
+
+struct EntryRow {
+    uuid: Uuid,
+    title: String,
+    /// Group path, shown for search results
+    path: String,
+}
+
+struct FieldRow {
+    name: String,
+    /// Masked unless revealed
+    value: String,
+}
+
+/// State of the vault browser, changed by key presses and the clock
+pub struct App {
+    name: String,
+    path: PathBuf,
+    key_file: Option<PathBuf>,
+    /// Dropped while locked
+    opened: Option<OpenedDatabase>,
+    /// Unsaved changes kept while locked, given back once the password is
+    /// checked again
+    kept: Option<OpenedDatabase>,
+    /// Changed since the last save, which failed or wasn't confirmed
+    dirty: bool,
+
+    focus: Focus,
+    prompt: Option<Prompt>,
+    input: String,
+    masked: bool,
+    query: String,
+
+    groups: Vec<GroupRow>,
+    group_idx: usize,
+    entries: Vec<EntryRow>,
+    entry_idx: usize,
+    field_idx: usize,
+    revealed: HashSet<String>,
+
+    status: String,
+    quit: bool,
+    last_activity: Instant,
+    lock_after: Option<Duration>,
+    clipboard_timeout: Option<Duration>,
+    clear_clipboard_at: Option<Instant>,
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        self.clear_input();
+        if self.clear_clipboard_at.is_some() {
+            let _ = set_clipboard(None);
+        }
+    }
+}
+
+impl App {
+    /// `lock_after` and `clipboard_timeout` of `None` never lock or clear
+    pub fn new(
+        opened: OpenedDatabase,
+        lock_after: Option<Duration>,
+        clipboard_timeout: Option<Duration>,
+    ) -> Self {
+        let mut app = App {
+            name: opened.name.clone(),
+            path: opened.path.clone(),
+            key_file: opened.key_file.clone(),
+            opened: Some(opened),
+            kept: None,
+            dirty: false,
+            focus: Focus::Groups,
+            prompt: None,
+            input: String::new(),
+            masked: false,
+            query: String::new(),
+            groups: Vec::new(),
+            group_idx: 0,
+            entries: Vec::new(),
+            entry_idx: 0,
+            field_idx: 0,
+            revealed: HashSet::new(),
+            status: String::new(),
+            quit: false,
+            last_activity: Instant::now(),
+            lock_after,
+            clipboard_timeout,
+            clear_clipboard_at: None,
+        };
+        app.refresh();
+        app
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.opened.is_none()
+    }
+
+    /// Locks after inactivity and clears the clipboard once it's due
+    pub fn tick(&mut self, now: Instant) {
+        if let Some(at) = self.clear_clipboard_at
+            && now >= at
+        {
+            let _ = set_clipboard(None);
+            self.clear_clipboard_at = None;
+            self.status = "Clipboard cleared".to_string();
+        }
+
+        if !self.is_locked()
+            && let Some(after) = self.lock_after
+            && now.duration_since(self.last_activity) >= after
+        {
+            debug!("locking after {}s of inactivity", after.as_secs());
+            self.lock();
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        self.last_activity = Instant::now();
+
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        if self.prompt.is_some() {
+            self.handle_prompt_key(key);
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => self.quit(),
+            KeyCode::Char('s') => self.save(),
+            KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => self.focus_next(),
+            KeyCode::BackTab | KeyCode::Left | KeyCode::Char('h') => self.focus_previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Char('/') => {
+                let query = self.query.clone();
+                self.start_prompt(Prompt::Search, query, false);
+            }
+            KeyCode::Esc if !self.query.is_empty() => {
+                self.query.clear();
+                self.refresh();
+            }
+            KeyCode::Char('L') => self.lock(),
+            KeyCode::Char('u') => self.copy_field("UserName"),
+            KeyCode::Char('p') => self.copy_field("Password"),
+            KeyCode::Char('t') => self.copy_field(TOTP_ROW),
+            KeyCode::Char('a') => self.start_creation(),
+            KeyCode::Char('e') => self.start_edition(),
+            KeyCode::Enter | KeyCode::Char('c') if self.focus == Focus::Fields => {
+                if let Some(name) = self.selected_field_name() {
+                    self.copy_field(&name);
+                }
+            }
+            KeyCode::Enter if self.focus != Focus::Fields => self.focus_next(),
+            KeyCode::Char('r') if self.focus == Focus::Fields => {
+                if let Some(name) = self.selected_field_name()
+                    && !self.revealed.remove(&name)
+                {
+                    self.revealed.insert(name);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_prompt_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Enter => self.submit_prompt(),
+            KeyCode::Esc if self.prompt == Some(Prompt::Unlock) => self.quit(),
+            KeyCode::Esc => {
+                if self.prompt == Some(Prompt::Search) {
+                    self.query.clear();
+                    self.refresh();
+                }
+                self.prompt = None;
+                self.clear_input();
+            }
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Char(c) => self.input.push(c),
+            _ => {}
+        }
+
+        // search as you type
+        if self.prompt == Some(Prompt::Search) {
+            self.query = self.input.clone();
+            self.refresh();
+        }
+    }
+
+    fn start_prompt(&mut self, prompt: Prompt, input: String, masked: bool) {
+        self.clear_input();
+        self.status.clear();
+        self.prompt = Some(prompt);
+        self.input = input;
+        self.masked = masked;
+    }
+
+    fn start_creation(&mut self) {
+        match self.focus {
+            Focus::Groups => self.start_prompt(Prompt::NewGroup, String::new(), false),
+            Focus::Entries => self.start_prompt(Prompt::NewEntry, String::new(), false),
+            Focus::Fields if self.selected_entry().is_some() => {
+                self.start_prompt(Prompt::NewFieldName, String::new(), false)
+            }
+            Focus::Fields => {}
+        }
+    }
+
+    fn start_edition(&mut self) {
+        match self.focus {
+            Focus::Groups => {
+                if let Some(row) = self.groups.get(self.group_idx) {
+                    let name = row.name.clone();
+                    self.start_prompt(Prompt::RenameGroup, name, false);
+                }
+            }
+            Focus::Entries => self.start_field_edition("Title"),
+            Focus::Fields => {
+                if let Some(name) = self.selected_field_name()
+                    && name != TOTP_ROW
+                {
+                    self.start_field_edition(&name);
+                }
+            }
+        }
+    }
+
+    fn start_field_edition(&mut self, name: &str) {
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        let protected = is_protected(entry, name);
+        let value = entry.get(name).unwrap_or_default().to_string();
+        self.start_prompt(Prompt::EditField(name.to_string()), value, protected);
+    }
+
+    fn submit_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        let mut input = std::mem::take(&mut self.input);
+
+        match prompt {
+            Prompt::Search => self.focus = Focus::Entries,
+            Prompt::Unlock => {
+                // zeroed by the move into locked memory
+                self.unlock(Pwd::from(input));
+                return;
+            }
+            Prompt::SavePassword => {
+                let Some(opened) = self.opened.as_mut() else {
+                    return;
+                };
+                let checked = opened
+                    .credentials
+                    .add_password(Pwd::from(input), opened.key_file.as_deref());
+                match checked {
+                    Ok(()) => self.save(),
+                    Err(_) => self.status = "Invalid password or key, not saved".to_string(),
+                }
+                return;
+            }
+            Prompt::EditField(name) => {
+                let value = field_value(&name, self.selected_entry(), &input);
+                self.update_entry(|entry| {
+                    entry.fields.insert(name, value);
+                });
+            }
+            Prompt::NewFieldName => {
+                // `name!` is a protected field, its value typed masked
+                let (name, protected) = match input.strip_suffix('!') {
+                    Some(name) => (name.to_string(), true),
+                    None => (input, false),
+                };
+                if name.is_empty() {
+                    return;
+                }
+                if self
+                    .selected_entry()
+                    .is_some_and(|entry| entry.fields.contains_key(&name))
+                {
+                    self.status = format!("Field `{name}` exists");
+                } else {
+                    let prompt = Prompt::NewFieldValue(name, protected);
+                    self.start_prompt(prompt, String::new(), protected);
+                }
+                return;
+            }
+            Prompt::NewFieldValue(name, protected) => {
+                let value = if protected {
+                    Value::Protected(input.as_bytes().into())
+                } else {
+                    Value::Unprotected(input.clone())
+                };
+                self.update_entry(|entry| {
+                    entry.fields.insert(name, value);
+                });
+            }
+            Prompt::NewEntry => self.create_entry(&input),
+            Prompt::NewGroup if input.is_empty() => {}
+            Prompt::NewGroup => {
+                let group = Group::new(&input);
+                let uuid = group.uuid;
+                if self.update_group(|parent| parent.children.push(Node::Group(group))) {
+                    self.select_group(uuid);
+                }
+            }
+            Prompt::RenameGroup if input.is_empty() => {}
+            Prompt::RenameGroup => {
+                let name = input.clone();
+                self.update_group(|group| group.name = name);
+            }
+        }
+
+        zero_bytes(unsafe { input.as_bytes_mut() });
+    }
+
+    fn create_entry(&mut self, title: &str) {
+        let mut entry = Entry::new();
+        for name in STANDARD_FIELDS {
+            let value = if name == "Title" { title } else { "" };
+            entry
+                .fields
+                .insert(name.to_string(), field_value(name, None, value));
+        }
+        let uuid = entry.uuid;
+
+        if self.update_group(|group| group.children.push(Node::Entry(entry))) {
+            if let Some(idx) = self.entries.iter().position(|row| row.uuid == uuid) {
+                self.entry_idx = idx;
+            }
+            self.focus = Focus::Fields;
+            self.field_idx = 0;
+        }
+    }
+
+    /// Changes the selected group and saves. False if none is selected.
+    fn update_group(&mut self, change: impl FnOnce(&mut Group)) -> bool {
+        let Some(uuid) = self.groups.get(self.group_idx).map(|row| row.uuid) else {
+            return false;
+        };
+        let Some(group) = self
+            .opened
+            .as_mut()
+            .and_then(|opened| find_group_by_uuid_mut(&mut opened.db.root, &uuid))
+        else {
+            return false;
+        };
+
+        change(group);
+        self.dirty = true;
+        self.save();
+        true
+    }
+
+    /// Changes the selected entry, keeping its history, and saves
+    fn update_entry(&mut self, change: impl FnOnce(&mut Entry)) {
+        let Some(uuid) = self.entries.get(self.entry_idx).map(|row| row.uuid) else {
+            return;
+        };
+        let Some(entry) = self
+            .opened
+            .as_mut()
+            .and_then(|opened| find_entry_by_uuid_mut(&mut opened.db.root, &uuid))
+        else {
+            return;
+        };
+
+        change(entry);
+        entry.update_history();
+        self.dirty = true;
+        self.save();
+    }
+
+    fn save(&mut self) {
+        let Some(opened) = self.opened.as_ref() else {
+            return;
+        };
+        if opened.credentials.needs_password() {
+            self.refresh();
+            self.start_prompt(Prompt::SavePassword, String::new(), true);
+            return;
+        }
+        self.status = match opened.save_in_place() {
+            Ok(()) => {
+                self.dirty = false;
+                "Saved".to_string()
+            }
+            Err(e) => format!("Unable to save: {e}, `s` tries again"),
+        };
+        self.refresh();
+    }
+
+    /// Ctrl-C leaves anyway, discarding unsaved changes
+    fn quit(&mut self) {
+        if !self.dirty {
+            self.quit = true;
+        } else if self.is_locked() {
+            self.status = "Unsaved changes, unlock to save them or Ctrl-C to discard them".into();
+        } else {
+            self.status = "Unsaved changes, `s` saves them or Ctrl-C discards them".into();
+        }
+    }
+
+    /// Drops the database and everything read from it, but unsaved changes
+    fn lock(&mut self) {
+        let opened = self.opened.take();
+        if self.dirty {
+            self.kept = opened;
+        }
+        self.groups.clear();
+        self.entries.clear();
+        self.revealed.clear();
+        self.query.clear();
+        self.focus = Focus::Groups;
+        self.start_prompt(Prompt::Unlock, String::new(), true);
+        self.status = if self.dirty {
+            "Locked, unsaved changes are kept until unlocked".to_string()
+        } else {
+            "Locked".to_string()
+        };
+    }
+
+    /// The stored password isn't used: unlocking takes the password again.
+    /// Unsaved changes kept while locked replace the database read back.
+    fn unlock(&mut self, password: Pwd) {
+        let opened = match self.key_file.as_deref() {
+            Some(keyfile) if password.is_empty() => {
+                unlock_database_with_key_file(&self.path, keyfile).map_err(|e| e.to_string())
+            }
+            keyfile => unlock_database(password, &self.path, keyfile, false)
+                .map_err(|_| "Invalid password or key".to_string()),
+        };
+
+        match opened {
+            Ok((db, credentials)) => {
+                let db = match self.kept.take() {
+                    Some(kept) => kept.db,
+                    None => db,
+                };
+                self.opened = Some(OpenedDatabase {
+                    name: self.name.clone(),
+                    path: self.path.clone(),
+                    key_file: self.key_file.clone(),
+                    db,
+                    credentials,
+                });
+                self.status = "Unlocked".to_string();
+                self.refresh();
+            }
+            Err(e) => {
+                self.start_prompt(Prompt::Unlock, String::new(), true);
+                self.status = e;
+            }
+        }
+    }
+
+    fn copy_field(&mut self, name: &str) {
+        let Some(entry) = self.selected_entry() else {
+            return;
+        };
+        let value: Option<Pwd> = if name == TOTP_ROW {
+            totp_code(entry).map(|(code, _)| code.into())
+        } else {
+            entry.get(name).map(|value| value.to_string().into())
+        };
+        let Some(value) = value.filter(|value| !value.is_empty()) else {
+            self.status = format!("No {name} to copy");
+            return;
+        };
+
+        self.status = match set_clipboard(Some(value)) {
+            Ok(()) => {
+                self.clear_clipboard_at = self.clipboard_timeout.map(|t| Instant::now() + t);
+                let copied = match self.clipboard_timeout {
+                    Some(t) => format!("Copied {name}, clearing in {}s", t.as_secs()),
+                    None => format!("Copied {name}"),
+                };
+                match unmarked_warning() {
+                    Some(warning) => format!("{copied}. {warning}"),
+                    None => copied,
+                }
+            }
+            Err(_) => "Clipboard unavailable".to_string(),
+        };
+    }
+
+    fn focus_next(&mut self) {
+        self.focus = match self.focus {
+            Focus::Groups => Focus::Entries,
+            Focus::Entries | Focus::Fields => Focus::Fields,
+        };
+    }
+
+    fn focus_previous(&mut self) {
+        self.focus = match self.focus {
+            Focus::Groups | Focus::Entries => Focus::Groups,
+            Focus::Fields => Focus::Entries,
+        };
+    }
+
+    fn move_selection(&mut self, by: isize) {
+        let fields = self.fields().len();
+        let (idx, len) = match self.focus {
+            Focus::Groups => (&mut self.group_idx, self.groups.len()),
+            Focus::Entries => (&mut self.entry_idx, self.entries.len()),
+            Focus::Fields => (&mut self.field_idx, fields),
+        };
+        *idx = idx.saturating_add_signed(by).min(len.saturating_sub(1));
+
+        match self.focus {
+            Focus::Groups => {
+                self.entry_idx = 0;
+                self.field_idx = 0;
+                self.refresh();
+            }
+            Focus::Entries => self.field_idx = 0,
+            Focus::Fields => {}
+        }
+        if self.focus != Focus::Fields {
+            self.revealed.clear();
+        }
+    }
+
+    fn select_group(&mut self, uuid: Uuid) {
+        if let Some(idx) = self.groups.iter().position(|row| row.uuid == uuid) {
+            self.group_idx = idx;
+            self.entry_idx = 0;
+            self.refresh();
+        }
+    }
+
+    /// Rebuilds the rows from the database, keeping the selection in range
+    fn refresh(&mut self) {
+        let Some(opened) = self.opened.as_ref() else {
+            return;
+        };
+
+        self.groups.clear();
+        flatten_groups(&opened.db.root, 0, &mut self.groups);
+        self.group_idx = self.group_idx.min(self.groups.len().saturating_sub(1));
+
+        self.entries.clear();
+        if !self.query.is_empty() {
+            self.entries = search(&opened.db.root, &self.query.to_lowercase());
+        } else if let Some(group) = self
+            .groups
+            .get(self.group_idx)
+            .and_then(|row| find_group_by_uuid(&opened.db.root, &row.uuid))
+        {
+            self.entries = group
+                .children
+                .iter()
+                .filter_map(|node| match node {
+                    Node::Entry(entry) => Some(entry_row("", entry)),
+                    Node::Group(_) => None,
+                })
+                .collect();
+        }
+        self.entry_idx = self.entry_idx.min(self.entries.len().saturating_sub(1));
+        self.field_idx = self.field_idx.min(self.fields().len().saturating_sub(1));
+    }
+
+    fn selected_entry(&self) -> Option<&Entry> {
+        let row = self.entries.get(self.entry_idx)?;
+        find_entry_by_uuid(&self.opened.as_ref()?.db.root, &row.uuid)
+    }
+
+    fn selected_field_name(&self) -> Option<String> {
+        self.fields()
+            .into_iter()
+            .nth(self.field_idx)
+            .map(|row| row.name)
+    }
+
+    /// Rows of the selected entry: the standard fields first, then the
+    /// others by name and the current TOTP code
+    fn fields(&self) -> Vec<FieldRow> {
+        let Some(entry) = self.selected_entry() else {
+            return Vec::new();
+        };
+
+        let mut names = entry
+            .fields
+            .keys()
+            .filter(|name| !STANDARD_FIELDS.contains(&name.as_str()))
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+
+        let mut rows = STANDARD_FIELDS
+            .into_iter()
+            .chain(names)
+            .map(|name| FieldRow {
+                name: name.to_string(),
+                value: if is_protected(entry, name) && !self.revealed.contains(name) {
+                    MASKED_VALUE.to_string()
+                } else {
+                    entry.get(name).unwrap_or_default().replace('\n', " ")
+                },
+            })
+            .collect::<Vec<_>>();
+
+        if let Some((code, valid_for)) = totp_code(entry) {
+            rows.push(FieldRow {
+                name: TOTP_ROW.to_string(),
+                value: format!("{code}  {valid_for:>2}s"),
+            });
+        }
+
+        rows
+    }
+
+    fn clear_input(&mut self) {
+        // zeroes are valid UTF-8
+        zero_bytes(unsafe { self.input.as_bytes_mut() });
+        self.input.clear();
+    }
+}
+
+fn is_protected(entry: &Entry, name: &str) -> bool {
+    match entry.fields.get(name) {
+        Some(value) => matches!(value, Value::Protected(_)),
+        None => name == "Password",
+    }
+}
+
+/// The new value of a field, protected like the one it replaces
+fn field_value(name: &str, entry: Option<&Entry>, value: &str) -> Value {
+    let protected = match entry {
+        Some(entry) => is_protected(entry, name),
+        None => name == "Password",
+    };
+    if protected {
+        Value::Protected(value.as_bytes().into())
+    } else {
+        Value::Unprotected(value.to_string())
+    }
+}
+
+fn totp_code(entry: &Entry) -> Option<(String, u64)> {
+    let otp = raw_otp(entry).and_then(|raw| parse_totp(raw.expose()).ok())?;
+    let code = otp.value_now().ok()?;
+    Some((code.code, code.valid_for))
+}
+
+fn entry_row(path: &str, entry: &Entry) -> EntryRow {
+    EntryRow {
+        uuid: entry.uuid,
+        title: entry.get_title().unwrap_or_default().to_string(),
+        path: path.to_string(),
+    }
+}
+
+fn flatten_groups(group: &Group, depth: usize, rows: &mut Vec<GroupRow>) {
+    rows.push(GroupRow {
+        uuid: group.uuid,
+        name: group.name.clone(),
+        depth,
+    });
+    for node in &group.children {
+        if let Node::Group(child) = node {
+            flatten_groups(child, depth + 1, rows);
+        }
+    }
+}
+
+/// Entries of all groups with the query in their title, username, URL or
+/// group path
+fn search(root: &Group, query: &str) -> Vec<EntryRow> {
+    let mut rows = Vec::new();
+    let mut groups = vec![(root, format!("/{}", root.name))];
+    while let Some((group, path)) = groups.pop() {
+        for node in &group.children {
+            match node {
+                Node::Group(child) => groups.push((child, format!("{path}/{}", child.name))),
+                Node::Entry(entry) => {
+                    let matches = [entry.get_title(), entry.get_username(), entry.get_url()]
+                        .into_iter()
+                        .flatten()
+                        .chain([path.as_str()])
+                        .any(|text| text.to_lowercase().contains(query));
+                    if matches {
+                        rows.push(entry_row(&path, entry));
+                    }
+                }
+            }
+        }
+    }
+    rows.sort_by(|a, b| (&a.path, &a.title).cmp(&(&b.path, &b.title)));
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use keepass::{
+        Database, DatabaseKey,
+        config::{DatabaseConfig, KdfConfig},
+    };
+
+    use super::*;
+    use crate::{
+        kdbx4::TransformedKey,
+        keepass::{Credentials, open_database},
+    };
+
+    fn app(name: &str, lock_after: Option<Duration>) -> App {
+        let config = DatabaseConfig {
+            kdf_config: KdfConfig::Aes { rounds: 1 },
+            ..Default::default()
+        };
+        let mut db = Database::new(config);
+        let mut work = Group::new("Work");
+        let mut entry = Entry::new();
+        entry
+            .fields
+            .insert("Title".to_string(), Value::Unprotected("Mail".to_string()));
+        entry.fields.insert(
+            "UserName".to_string(),
+            Value::Unprotected("bob".to_string()),
+        );
+        entry.fields.insert(
+            "Password".to_string(),
+            Value::Protected(b"secret".as_slice().into()),
+        );
+        work.children.push(Node::Entry(entry));
+        db.root.children.push(Node::Group(work));
+
+        let path = env::temp_dir().join(format!("kdbx-tui-{}-{name}.kdbx", std::process::id()));
+        let opened = OpenedDatabase {
+            name: name.to_string(),
+            path,
+            key_file: None,
+            db,
+            credentials: Credentials::Password("test".to_string().into()),
+        };
+        opened.save_in_place().unwrap();
+
+        App::new(opened, lock_after, None)
+    }
+
+    fn press(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\t' => KeyCode::Tab,
+                '\x1b' => KeyCode::Esc,
+                c => KeyCode::Char(c),
+            };
+            app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        }
+    }
+
+    fn titles(app: &App) -> Vec<&str> {
+        app.entries.iter().map(|row| row.title.as_str()).collect()
+    }
+
+    #[test]
+    fn browses_groups_and_searches() {
+        let mut app = app("browse", None);
+        fs::remove_file(&app.path).unwrap();
+
+        let groups = app
+            .groups
+            .iter()
+            .map(|row| (row.name.as_str(), row.depth))
+            .collect::<Vec<_>>();
+        assert_eq!(groups, [("Root", 0), ("Work", 1)]);
+        assert!(titles(&app).is_empty());
+
+        press(&mut app, "j");
+        assert_eq!(titles(&app), ["Mail"]);
+
+        press(&mut app, "k/BOB");
+        assert_eq!(titles(&app), ["Mail"]);
+        assert_eq!(app.entries[0].path, "/Root/Work");
+        press(&mut app, "\x1b");
+        assert!(app.query.is_empty());
+        assert!(titles(&app).is_empty());
+    }
+
+    #[test]
+    fn reveals_and_edits_fields() {
+        let mut app = app("edit", None);
+
+        press(&mut app, "j\t\tjj");
+        assert_eq!(app.selected_field_name().as_deref(), Some("Password"));
+        assert_eq!(app.fields()[2].value, MASKED_VALUE);
+        press(&mut app, "r");
+        assert_eq!(app.fields()[2].value, "secret");
+
+        press(&mut app, "e");
+        assert!(app.masked);
+        app.input.clear();
+        press(&mut app, "changed\nhhk\taNew\n");
+        assert_eq!(app.status, "Saved");
+
+        let db = open_database(Some("test".to_string().into()), &app.path, None).unwrap();
+        fs::remove_file(&app.path).unwrap();
+        let work = find_group_by_uuid(&db.root, &app.groups[1].uuid).unwrap();
+        let entries = work.entries();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].get_password(), Some("changed"));
+        let groups = db.root.groups();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].entries()[0].get_title(), Some("Mail"));
+        assert_eq!(db.root.entries()[0].get_title(), Some("New"));
+    }
+
+    #[test]
+    fn adds_protected_fields() {
+        let mut app = app("fields", None);
+
+        press(&mut app, "j\t\taPIN!\n");
+        assert_eq!(app.prompt, Some(Prompt::NewFieldValue("PIN".into(), true)));
+        assert!(app.masked);
+        press(&mut app, "1234\naNote\n");
+        assert!(!app.masked);
+        press(&mut app, "hello\n");
+        assert_eq!(app.status, "Saved");
+
+        let db = open_database(Some("test".to_string().into()), &app.path, None).unwrap();
+        fs::remove_file(&app.path).unwrap();
+        let work = find_group_by_uuid(&db.root, &app.groups[1].uuid).unwrap();
+        let entry = work.entries()[0];
+        assert!(matches!(entry.fields.get("PIN"), Some(Value::Protected(_))));
+        assert_eq!(entry.get("PIN"), Some("1234"));
+        assert!(matches!(
+            entry.fields.get("Note"),
+            Some(Value::Unprotected(_))
+        ));
+    }
+
+    #[test]
+    fn asks_password_to_save_with_stored_key() {
+        let mut app = app("key", None);
+        let data = fs::read(&app.path).unwrap();
+        let key = TransformedKey::derive(&data, "test", None).unwrap();
+        app.opened.as_mut().unwrap().credentials = Credentials::TransformedKey(key, None);
+
+        press(&mut app, "j\taNew\n");
+        assert_eq!(app.prompt, Some(Prompt::SavePassword));
+        assert!(app.masked);
+        press(&mut app, "wrong\n");
+        assert_eq!(app.status, "Invalid password or key, not saved");
+
+        press(&mut app, "e");
+        app.input.clear();
+        press(&mut app, "Newer\ntest\n");
+        assert_eq!(app.status, "Saved");
+        let db = open_database(Some("test".to_string().into()), &app.path, None).unwrap();
+        fs::remove_file(&app.path).unwrap();
+        assert_eq!(db.root.groups()[0].entries()[1].get_title(), Some("Newer"));
+    }
+
+    #[test]
+    fn locks_after_inactivity() {
+        let mut app = app("lock", Some(Duration::from_secs(60)));
+        fs::remove_file(&app.path).unwrap();
+        let copy = app.opened.as_ref().unwrap().db.clone();
+        app.tick(Instant::now());
+        assert!(!app.is_locked());
+
+        app.tick(Instant::now() + Duration::from_secs(61));
+        assert!(app.is_locked());
+        assert!(app.groups.is_empty());
+
+        // unlocking reads the file again
+        let mut file = fs::File::create(&app.path).unwrap();
+        copy.save(&mut file, DatabaseKey::new().with_password("test"))
+            .unwrap();
+        press(&mut app, "wrong\n");
+        assert!(app.is_locked());
+        assert_eq!(app.status, "Invalid password or key");
+        press(&mut app, "test\n");
+        fs::remove_file(&app.path).unwrap();
+
+        assert!(!app.is_locked());
+        assert_eq!(app.groups.len(), 2);
+    }
+
+    #[test]
+    fn keeps_unsaved_changes_until_saved() {
+        let mut app = app("unsaved", None);
+        let path = app.path.clone();
+        app.opened.as_mut().unwrap().path = path.join("missing");
+
+        press(
+            &mut app, "j	aNew
+",
+        );
+        assert!(app.status.starts_with("Unable to save: "));
+        assert!(app.dirty);
+        press(&mut app, "q");
+        assert!(!app.should_quit());
+        assert_eq!(
+            app.status,
+            "Unsaved changes, `s` saves them or Ctrl-C discards them"
+        );
+
+        press(&mut app, "L");
+        assert_eq!(
+            app.status,
+            "Locked, unsaved changes are kept until unlocked"
+        );
+        press(&mut app, "\x1b");
+        assert!(!app.should_quit());
+        press(&mut app, "test\n");
+        assert_eq!(titles(&app), ["Mail", "New"]);
+
+        app.opened.as_mut().unwrap().path = path;
+        press(&mut app, "s");
+        assert_eq!(app.status, "Saved");
+        assert!(!app.dirty);
+        let db = open_database(Some("test".to_string().into()), &app.path, None).unwrap();
+        fs::remove_file(&app.path).unwrap();
+        assert_eq!(db.root.groups()[0].entries()[1].get_title(), Some("New"));
+        press(&mut app, "q");
+        assert!(app.should_quit());
+    }
+}
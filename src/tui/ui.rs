@@ -0,0 +1,210 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph},
+};
+
+use super::{App, Focus};
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let [main, status, help] = Layout::vertical([
+        Constraint::Min(3),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+
+    if app.is_locked() {
+        let text = if app.dirty {
+            format!("`{}` is locked, unsaved changes are kept", app.name)
+        } else {
+            format!("`{}` is locked", app.name)
+        };
+        let locked = Paragraph::new(text)
+            .centered()
+            .block(Block::bordered().title(" kdbx "));
+        frame.render_widget(locked, main);
+    } else {
+        let [groups, entries, fields] = Layout::horizontal([
+            Constraint::Percentage(25),
+            Constraint::Percentage(35),
+            Constraint::Percentage(40),
+        ])
+        .areas(main);
+
+        draw_groups(frame, app, groups);
+        draw_entries(frame, app, entries);
+        draw_fields(frame, app, fields);
+    }
+
+    draw_status(frame, app, status);
+    frame.render_widget(
+        Paragraph::new(help_text(app)).style(Style::new().add_modifier(Modifier::DIM)),
+        help,
+    );
+}
+
+fn draw_groups(frame: &mut Frame, app: &App, area: Rect) {
+    let items = app
+        .groups
+        .iter()
+        .map(|row| ListItem::new(format!("{}{}", "  ".repeat(row.depth), row.name)));
+
+    draw_list(
+        frame,
+        app,
+        area,
+        " Groups ".to_string(),
+        Focus::Groups,
+        items,
+        app.group_idx,
+    );
+}
+
+fn draw_entries(frame: &mut Frame, app: &App, area: Rect) {
+    let searching = !app.query.is_empty();
+    let items = app.entries.iter().map(|row| {
+        if searching {
+            ListItem::new(Line::from(vec![
+                Span::raw(row.title.clone()),
+                Span::styled(
+                    format!("  {}", row.path),
+                    Style::new().add_modifier(Modifier::DIM),
+                ),
+            ]))
+        } else {
+            ListItem::new(row.title.clone())
+        }
+    });
+    let title = if searching {
+        format!(" Search: {} ", app.query)
+    } else {
+        " Entries ".to_string()
+    };
+
+    draw_list(
+        frame,
+        app,
+        area,
+        title,
+        Focus::Entries,
+        items,
+        app.entry_idx,
+    );
+}
+
+fn draw_fields(frame: &mut Frame, app: &App, area: Rect) {
+    let rows = app.fields();
+    let width = rows
+        .iter()
+        .map(|row| row.name.chars().count())
+        .max()
+        .unwrap_or_default();
+    let items = rows.into_iter().map(|row| {
+        ListItem::new(Line::from(vec![
+            Span::styled(
+                format!("{:<width$}  ", row.name),
+                Style::new().add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(row.value),
+        ]))
+    });
+
+    draw_list(
+        frame,
+        app,
+        area,
+        " Entry ".to_string(),
+        Focus::Fields,
+        items,
+        app.field_idx,
+    );
+}
+
+fn draw_list<'a>(
+    frame: &mut Frame,
+    app: &App,
+    area: Rect,
+    title: String,
+    focus: Focus,
+    items: impl Iterator<Item = ListItem<'a>>,
+    selected: usize,
+) {
+    let border = if app.focus == focus {
+        Style::new().fg(Color::Cyan)
+    } else {
+        Style::new()
+    };
+    let list = List::new(items)
+        .block(Block::bordered().title(title).border_style(border))
+        .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+
+    let mut state = ListState::default().with_selected(Some(selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_status(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(prompt) = app.prompt.as_ref() else {
+        let mut line = Line::raw(app.status.as_str());
+        if app.dirty {
+            line.spans.insert(
+                0,
+                Span::styled("[unsaved] ", Style::new().fg(Color::Yellow)),
+            );
+        }
+        frame.render_widget(Paragraph::new(line), area);
+        return;
+    };
+
+    let label = prompt.label();
+    let input = if app.masked {
+        "*".repeat(app.input.chars().count())
+    } else {
+        app.input.clone()
+    };
+    let cursor = (label.chars().count() + input.chars().count()) as u16;
+
+    let line = if app.status.is_empty() {
+        Line::from(vec![Span::raw(label), Span::raw(input)])
+    } else {
+        // e.g. a wrong password, shown while asking again
+        Line::from(vec![
+            Span::raw(label),
+            Span::raw(input),
+            Span::styled(
+                format!("  ({})", app.status),
+                Style::new().add_modifier(Modifier::DIM),
+            ),
+        ])
+    };
+    frame.render_widget(Paragraph::new(line), area);
+    frame.set_cursor_position(Position::new(
+        area.x + cursor.min(area.width.saturating_sub(1)),
+        area.y,
+    ));
+}
+
+fn help_text(app: &App) -> &'static str {
+    if app.prompt.is_some() {
+        return if app.is_locked() {
+            "enter unlock  esc quit"
+        } else {
+            "enter confirm  esc cancel"
+        };
+    }
+
+    match app.focus {
+        Focus::Groups => {
+            "j/k move  tab entries  a new group  e rename  / search  s save  L lock  q quit"
+        }
+        Focus::Entries => {
+            "j/k move  tab details  a new entry  e title  u/p/t copy user/pass/totp  / search  q \
+             quit"
+        }
+        Focus::Fields => {
+            "j/k move  c copy  r reveal  e edit  a new field  u/p/t copy user/pass/totp  q quit"
+        }
+    }
+}
@@ -1,14 +1,24 @@
-use std::{borrow::Cow, error, fmt, io, path::Path};
+use std::{
+    borrow::Cow,
+    error, fmt, io,
+    path::{Path, PathBuf},
+};
 
-use keepass::{Database, error::DatabaseOpenError as KeepassOpenError};
+use chrono::TimeDelta;
+use keepass::{Database, db::Entry, error::DatabaseOpenError as KeepassOpenError};
 use log::*;
 use regex::Regex;
 use skim::{prelude::*, tui::options::PreviewLayout};
 
 use crate::{
     STDIN,
-    keepass::{EntryPath, open_database, show_entry},
-    keyring::Keyring,
+    kdbx4::TransformedKey,
+    keepass::{
+        Credentials, EXPIRED_MARKER, EntryPath, OpenedDatabase, expiry, is_expired, show_entry,
+        unlock_database, unlock_database_stored, unlock_database_with_key_file,
+    },
+    keyring, password_source,
+    pinentry::{self, Pinentry},
     pwd::Pwd,
 };
 
@@ -42,6 +52,7 @@ macro_rules! werr {
 #[derive(Debug)]
 pub enum DatabaseOpenError {
     NoInteraction,
+    PasswordSource(String),
     KeepassOpenError(KeepassOpenError),
 }
 
@@ -49,6 +60,7 @@ impl fmt::Display for DatabaseOpenError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             DatabaseOpenError::NoInteraction => write!(f, "No interaction"),
+            DatabaseOpenError::PasswordSource(ref msg) => write!(f, "{msg}"),
             DatabaseOpenError::KeepassOpenError(..) => write!(f, "Invalid password or key"),
         }
     }
@@ -57,7 +69,7 @@ impl fmt::Display for DatabaseOpenError {
 impl error::Error for DatabaseOpenError {
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
-            DatabaseOpenError::NoInteraction => None,
+            DatabaseOpenError::NoInteraction | DatabaseOpenError::PasswordSource(..) => None,
             DatabaseOpenError::KeepassOpenError(ref e) => Some(e),
         }
     }
@@ -75,16 +87,113 @@ pub fn open_database_interactively(
     use_keyring: bool,
     remove_key: bool,
     no_interaction: bool,
-) -> Result<(Database, Pwd), DatabaseOpenError> {
+) -> Result<(Database, Credentials), DatabaseOpenError> {
+    unlock(
+        dbfile,
+        keyfile,
+        use_keyring,
+        remove_key,
+        no_interaction,
+        "Password: ",
+        None,
+    )
+}
+
+/// Pairs databases with their key files: `-k` ones go with `-d` ones in the
+/// same order, and `--pair DATABASE KEY_FILE` ones come after them
+pub fn pair_key_files(
+    dbfiles: &[PathBuf],
+    keyfiles: &[PathBuf],
+    pairs: &[PathBuf],
+) -> crate::Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    if !keyfiles.is_empty() && keyfiles.len() != dbfiles.len() {
+        return Err(format!(
+            "{} key files for {} databases, pair them with `--pair DATABASE KEY_FILE`",
+            keyfiles.len(),
+            dbfiles.len()
+        )
+        .into());
+    }
+    if dbfiles.is_empty() && pairs.is_empty() {
+        return Err("No database given, use `-d DATABASE`".into());
+    }
+
+    let mut keyfiles = keyfiles.iter();
+    let given = dbfiles
+        .iter()
+        .map(|dbfile| (dbfile.clone(), keyfiles.next().cloned()));
+    // clap hands over complete pairs only
+    let paired = pairs
+        .chunks_exact(2)
+        .map(|pair| (pair[0].clone(), Some(pair[1].clone())));
+
+    Ok(given.chain(paired).collect())
+}
+
+/// Opens every database with its own key file and keyring entry. A piped
+/// password is used for all of them.
+pub fn open_databases_interactively(
+    dbfiles: &[(PathBuf, Option<PathBuf>)],
+    use_keyring: bool,
+    remove_key: bool,
+    no_interaction: bool,
+) -> Result<Vec<OpenedDatabase>, DatabaseOpenError> {
+    let piped = (dbfiles.len() > 1 && !password_source::is_configured() && !is_tty(io::stdin()))
+        .then(|| STDIN.read_password());
+
+    dbfiles
+        .iter()
+        .map(|(dbfile, keyfile)| {
+            let name = dbfile
+                .file_stem()
+                .unwrap_or(dbfile.as_os_str())
+                .to_string_lossy()
+                .into_owned();
+            let prompt = if dbfiles.len() > 1 {
+                format!("Password for `{name}`: ")
+            } else {
+                "Password: ".to_string()
+            };
+
+            let (db, credentials) = unlock(
+                dbfile,
+                keyfile.as_deref(),
+                use_keyring,
+                remove_key,
+                no_interaction,
+                &prompt,
+                piped.as_ref().map(Pwd::duplicate),
+            )?;
+
+            Ok(OpenedDatabase {
+                name,
+                path: dbfile.clone(),
+                key_file: keyfile.clone(),
+                db,
+                credentials,
+            })
+        })
+        .collect()
+}
+
+fn unlock(
+    dbfile: &Path,
+    keyfile: Option<&Path>,
+    use_keyring: bool,
+    remove_key: bool,
+    no_interaction: bool,
+    prompt: &str,
+    piped: Option<Pwd>,
+) -> Result<(Database, Credentials), DatabaseOpenError> {
     if remove_key
-        && let Some(keyring) = Keyring::from_db_path(dbfile)
+        && let Some(keyring) = keyring::from_db_path(dbfile, keyfile)
         && let Err(msg) = keyring.delete_password()
     {
         werr!("No key removed for `{}`. {}", dbfile.to_string_lossy(), msg);
     }
 
     let keyring = if use_keyring {
-        Keyring::from_db_path(dbfile).map(|k| {
+        keyring::from_db_path(dbfile, keyfile).map(|k| {
             debug!("keyring: {k}");
             k
         })
@@ -92,48 +201,208 @@ pub fn open_database_interactively(
         None
     };
 
-    if let Some(Ok(password)) = keyring.as_ref().map(|k| k.get_password()) {
-        if let Ok(db) = open_database(password.clone(), dbfile, keyfile) {
-            return Ok((db, password));
+    let derive_key = keyring.is_some() && keyring::settings().cache == keyring::Cache::Key;
+
+    if let Some(Ok(stored)) = keyring.as_ref().map(|k| k.get_password()) {
+        let opened = if derive_key && !TransformedKey::is_encoded(stored.expose()) {
+            // Stored before caching keys, swap it for the key
+            unlock_database(stored, dbfile, keyfile, true).inspect(|(_, credentials)| {
+                if let (Some(k), Some(stored)) = (keyring.as_ref(), credentials.to_stored()) {
+                    let _ = k.set_password(stored.expose());
+                }
+            })
+        } else {
+            unlock_database_stored(stored, dbfile, keyfile)
+        };
+        if let Ok(opened) = opened {
+            return Ok(opened);
         }
 
         warn!("removing wrong password in the keyring");
         let _ = keyring.as_ref().map(|k| k.delete_password());
     }
 
-    // Try read password from pipe
+    // A password given by --password-fd, --password-file or --password-command
+    if let Some(password) = password_source::read() {
+        let password = password.map_err(DatabaseOpenError::PasswordSource)?;
+        if password.is_empty()
+            && let Some(opened) = unlock_with_key_file(dbfile, keyfile)
+        {
+            return Ok(opened);
+        }
+        return Ok(unlock_database(password, dbfile, keyfile, false)?);
+    }
+
+    // A graphical prompt, working without a terminal
+    if let Some(program) = pinentry::program() {
+        // Don't prompt for databases locked by a key file alone
+        if let Some(opened) = unlock_with_key_file(dbfile, keyfile) {
+            return Ok(opened);
+        }
+        if no_interaction {
+            return Err(DatabaseOpenError::NoInteraction);
+        }
+
+        let pinentry_error = |e: io::Error| {
+            DatabaseOpenError::PasswordSource(format!("Unable to run pinentry: {e}"))
+        };
+        let mut pinentry = Pinentry::spawn(program).map_err(pinentry_error)?;
+        pinentry
+            .set_description(&format!(
+                "Enter the password of `{}`",
+                dbfile.to_string_lossy()
+            ))
+            .map_err(pinentry_error)?;
+
+        return unlock_attempts(dbfile, keyfile, keyring, derive_key, |left| {
+            let error =
+                left.map(|left| format!("Invalid password or key. {left} attempt(s) left."));
+            pinentry
+                .get_pin(prompt.trim_end(), error.as_deref())
+                .map_err(pinentry_error)?
+                .ok_or_else(|| DatabaseOpenError::PasswordSource("Cancelled".to_string()))
+        });
+    }
+
+    // Try read password from pipe, an empty one may mean the key file alone
     if !is_tty(io::stdin()) {
-        let password = STDIN.read_password();
-        return Ok((open_database(password.clone(), dbfile, keyfile)?, password));
+        let password = piped.unwrap_or_else(|| STDIN.read_password());
+        if password.is_empty()
+            && let Some(opened) = unlock_with_key_file(dbfile, keyfile)
+        {
+            return Ok(opened);
+        }
+        return Ok(unlock_database(password, dbfile, keyfile, false)?);
     }
 
+    // Don't prompt for databases locked by a key file alone
+    if let Some(opened) = unlock_with_key_file(dbfile, keyfile) {
+        return Ok(opened);
+    }
     if no_interaction {
         return Err(DatabaseOpenError::NoInteraction);
     }
 
-    // Allow multiple attempts to enter the password from TTY
+    unlock_attempts(dbfile, keyfile, keyring, derive_key, |left| {
+        if let Some(left) = left {
+            wout!("{} attempt(s) left.", left);
+        }
+        put!("{}", prompt);
+        Ok(STDIN.read_password())
+    })
+}
+
+/// Allows multiple attempts to enter the password, storing the one which
+/// opens the database (or its key). `read` is given the attempts left
+/// after a wrong one.
+fn unlock_attempts(
+    dbfile: &Path,
+    keyfile: Option<&Path>,
+    keyring: Option<Box<dyn keyring::Keyring>>,
+    derive_key: bool,
+    mut read: impl FnMut(Option<u8>) -> Result<Pwd, DatabaseOpenError>,
+) -> Result<(Database, Credentials), DatabaseOpenError> {
     let mut att: u8 = 3;
+    let mut left = None;
     loop {
-        put!("Password: ");
+        let password = read(left)?;
+        let opened = unlock_database(password, dbfile, keyfile, derive_key);
+
+        // If opened successfully store the password, or the key
+        if let (Some(k), Ok((_, credentials))) = (keyring.as_ref(), &opened)
+            && let Some(stored) = credentials.to_stored()
+        {
+            let _ = k.set_password(stored.expose());
+        }
 
-        let password = STDIN.read_password();
-        let db = open_database(password.clone(), dbfile, keyfile);
+        att -= 1;
 
-        // If opened successfully store the password
-        if db.is_ok() {
-            let _ = keyring.as_ref().map(|k| k.set_password(&password));
+        if opened.is_ok() || att == 0 {
+            break Ok(opened?);
         }
 
+        left = Some(att);
+    }
+}
+
+/// Asks for the password again when the credentials only hold a key read
+/// from the keyring, which can't save. It's checked against that key.
+pub fn ask_password_to_save(
+    dbfile: &Path,
+    keyfile: Option<&Path>,
+    credentials: &mut Credentials,
+) -> Result<(), DatabaseOpenError> {
+    if !credentials.needs_password() {
+        return Ok(());
+    }
+
+    if let Some(password) = password_source::read() {
+        let password = password.map_err(DatabaseOpenError::PasswordSource)?;
+        return Ok(credentials.add_password(password, keyfile)?);
+    }
+
+    if let Some(program) = pinentry::program() {
+        let pinentry_error = |e: io::Error| {
+            DatabaseOpenError::PasswordSource(format!("Unable to run pinentry: {e}"))
+        };
+        let mut pinentry = Pinentry::spawn(program).map_err(pinentry_error)?;
+        pinentry
+            .set_description(&format!(
+                "Enter the password of `{}` to save it",
+                dbfile.to_string_lossy()
+            ))
+            .map_err(pinentry_error)?;
+
+        return save_attempts(keyfile, credentials, |left| {
+            let error =
+                left.map(|left| format!("Invalid password or key. {left} attempt(s) left."));
+            pinentry
+                .get_pin("Password:", error.as_deref())
+                .map_err(pinentry_error)?
+                .ok_or_else(|| DatabaseOpenError::PasswordSource("Cancelled".to_string()))
+        });
+    }
+
+    if !is_tty(io::stdin()) {
+        return Err(DatabaseOpenError::NoInteraction);
+    }
+
+    wout!("Saving takes the password again");
+    save_attempts(keyfile, credentials, |left| {
+        if let Some(left) = left {
+            wout!("{} attempt(s) left.", left);
+        }
+        put!("Password: ");
+        Ok(STDIN.read_password())
+    })
+}
+
+fn save_attempts(
+    keyfile: Option<&Path>,
+    credentials: &mut Credentials,
+    mut read: impl FnMut(Option<u8>) -> Result<Pwd, DatabaseOpenError>,
+) -> Result<(), DatabaseOpenError> {
+    let mut att: u8 = 3;
+    let mut left = None;
+    loop {
+        let checked = credentials.add_password(read(left)?, keyfile);
         att -= 1;
 
-        if db.is_ok() || att == 0 {
-            break Ok((db?, password));
+        if checked.is_ok() || att == 0 {
+            break Ok(checked?);
         }
 
-        wout!("{} attempt(s) left.", att);
+        left = Some(att);
     }
 }
 
+/// Opens the database with no password, never stored in the keyring
+fn unlock_with_key_file(dbfile: &Path, keyfile: Option<&Path>) -> Option<(Database, Credentials)> {
+    let opened = unlock_database_with_key_file(dbfile, keyfile?).ok()?;
+    debug!("unlocked with the key file alone");
+    Some(opened)
+}
+
 struct EntryItem {
     idx: usize,
     title: String,
@@ -190,11 +459,14 @@ pub fn skim<T: EntryPath>(
         .iter()
         .enumerate()
         .map(|(idx, e)| {
-            let title = if hide_groups {
+            let mut title = if hide_groups {
                 e.get_title()
             } else {
                 e.entry_path()
             };
+            if e.is_expired() {
+                title.push_str(EXPIRED_MARKER);
+            }
 
             let props = if show_preview {
                 Some(show_entry(e.get_entry(), false))
@@ -239,6 +511,54 @@ impl SkimItem for EntryItem {
     }
 }
 
+/// Warns when the entry has expired, or refuses to use it in `strict` mode
+pub fn check_expiry(entry: &Entry, strict: bool) -> crate::Result<()> {
+    if !is_expired(entry) {
+        return Ok(());
+    }
+
+    let msg = format!(
+        "Entry `{}` expired on {}",
+        entry.get_title().unwrap_or_default(),
+        expiry(entry)
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default()
+    );
+
+    if strict {
+        return Err(msg.into());
+    }
+
+    werr!("Warning: {}", msg);
+    Ok(())
+}
+
+/// Parses durations like `30d`, `12h`, `2w`; bare numbers are days
+pub fn parse_duration(value: &str) -> Result<TimeDelta, String> {
+    let value = value.trim();
+    let (num, unit) = value
+        .find(|c: char| !c.is_ascii_digit())
+        .map_or((value, "d"), |idx| value.split_at(idx));
+    let num: i64 = num
+        .parse()
+        .map_err(|_| format!("invalid duration `{value}`"))?;
+
+    let duration = match unit {
+        "s" => TimeDelta::try_seconds(num),
+        "m" => TimeDelta::try_minutes(num),
+        "h" => TimeDelta::try_hours(num),
+        "d" => TimeDelta::try_days(num),
+        "w" => TimeDelta::try_weeks(num),
+        _ => {
+            return Err(format!(
+                "invalid duration unit `{unit}`, expected s, m, h, d or w"
+            ));
+        }
+    };
+
+    duration.ok_or_else(|| "Duration is too long".to_string())
+}
+
 pub fn is_tty(fd: impl std::os::unix::io::AsRawFd) -> bool {
     unsafe { ::libc::isatty(fd.as_raw_fd()) == 1 }
 }
@@ -0,0 +1,75 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+
+#[test]
+fn test_audit() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args([
+        "audit",
+        "-d",
+        "tests/files/test.kdbx",
+        "-k",
+        "tests/files/secret",
+        "--max-age",
+        "0",
+    ])
+    .write_stdin("test123")
+    .assert()
+    .failure()
+    .stdout("weak: /Root/test-pwd (strength score 0/4)\nempty: /Root/test-totp (no password set)\n")
+    .stderr("2 issue(s) found\n");
+}
+
+#[test]
+fn test_audit_json() {
+    let mut cmd = cargo_bin_cmd!();
+    let assert_cmd = cmd
+        .args([
+            "audit",
+            "-d",
+            "tests/files/test.kdbx",
+            "-k",
+            "tests/files/secret",
+            "--max-age",
+            "0",
+            "--min-score",
+            "0",
+            "--json",
+        ])
+        .write_stdin("test123")
+        .assert();
+    assert_cmd.failure().stdout(
+        r#"[
+  {
+    "kind": "empty",
+    "entry": "/Root/test-totp",
+    "detail": "no password set"
+  }
+]
+"#,
+    );
+}
+
+#[test]
+fn test_audit_hibp() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args([
+        "audit",
+        "-d",
+        "tests/files/test.kdbx",
+        "-k",
+        "tests/files/secret",
+        "--max-age",
+        "0",
+        "--min-score",
+        "0",
+        "--hibp-file",
+        "tests/files/pwned-passwords-sha1.txt",
+    ])
+    .write_stdin("test123")
+    .assert()
+    .failure()
+    .stdout(
+        "breached: /Root/test-pwd (seen 2562301 times in data breaches)\nempty: /Root/test-totp \
+         (no password set)\n",
+    );
+}
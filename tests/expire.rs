@@ -0,0 +1,110 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use predicates::prelude::*;
+
+#[test]
+fn test_expired_entry() {
+    let database = std::env::temp_dir().join(format!("kdbx-expire-{}.kdbx", std::process::id()));
+    std::fs::copy("tests/files/test.kdbx", &database).unwrap();
+
+    cargo_bin_cmd!()
+        .args(["expire", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret", "--at", "2020-01-31", "test-pwd"])
+        .write_stdin("test123")
+        .assert()
+        .success();
+
+    let list = cargo_bin_cmd!()
+        .args(["list", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret"])
+        .write_stdin("test123")
+        .assert();
+    let expiring = cargo_bin_cmd!()
+        .args(["expiring", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret"])
+        .write_stdin("test123")
+        .assert();
+    let pwd = cargo_bin_cmd!()
+        .args(["pwd", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret", "test-pwd"])
+        .write_stdin("test123")
+        .assert();
+    let strict = cargo_bin_cmd!()
+        .args(["pwd", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret", "--strict", "test-pwd"])
+        .write_stdin("test123")
+        .assert();
+    let _ = std::fs::remove_file(&database);
+
+    list.success()
+        .stdout("/Root/test-pwd [expired]\n/Root/test-totp\n");
+    expiring
+        .success()
+        .stdout("/Root/test-pwd (expired 2020-01-31 00:00)\n");
+    pwd.success().stderr(predicate::str::contains(
+        "Warning: Entry `test-pwd` expired on 2020-01-31 00:00",
+    ));
+    strict.failure().stderr(predicate::str::contains(
+        "Entry `test-pwd` expired on 2020-01-31 00:00",
+    ));
+}
+
+#[test]
+fn test_clear_expiry() {
+    let database =
+        std::env::temp_dir().join(format!("kdbx-clear-expiry-{}.kdbx", std::process::id()));
+    std::fs::copy("tests/files/test.kdbx", &database).unwrap();
+
+    let expire = |flags: &[&str]| {
+        cargo_bin_cmd!()
+            .args(["expire", "-d"])
+            .arg(&database)
+            .args(["-k", "tests/files/secret", "test-totp"])
+            .args(flags)
+            .write_stdin("test123")
+            .assert()
+    };
+    let expiring = || {
+        cargo_bin_cmd!()
+            .args(["expiring", "-d"])
+            .arg(&database)
+            .args(["-k", "tests/files/secret", "--within", "2d"])
+            .write_stdin("test123")
+            .assert()
+    };
+
+    let set = expire(&["--in", "1d"]);
+    let soon = expiring();
+    let clear = expire(&["--clear"]);
+    let none = expiring();
+    let _ = std::fs::remove_file(&database);
+
+    set.success();
+    soon.success()
+        .stdout(predicate::str::starts_with("/Root/test-totp (expires "));
+    clear.success();
+    none.success().stdout("");
+}
+
+#[test]
+fn test_too_long_duration() {
+    let parse = cargo_bin_cmd!()
+        .args(["expiring", "-d", "tests/files/test.kdbx"])
+        .args(["-k", "tests/files/secret", "--within", "999999999999999d"])
+        .write_stdin("test123")
+        .assert();
+    let add = cargo_bin_cmd!()
+        .args(["expiring", "-d", "tests/files/test.kdbx"])
+        .args(["-k", "tests/files/secret", "--within", "100000000000d"])
+        .write_stdin("test123")
+        .assert();
+
+    parse
+        .failure()
+        .stderr(predicate::str::contains("Duration is too long"));
+    add.failure().stderr("Duration is too long\n");
+}
@@ -0,0 +1,87 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+
+#[test]
+fn test_import_migration() {
+    let database = std::env::temp_dir().join(format!("kdbx-import-{}.kdbx", std::process::id()));
+    std::fs::copy("tests/files/test.kdbx", &database).unwrap();
+
+    let assert_cmd = cargo_bin_cmd!()
+        .args(["import", "-d"])
+        .arg(&database)
+        .args([
+            "-k",
+            "tests/files/secret",
+            "otpauth-migration://offline?data=CjYKCkhlbGxvId6tvu8SGUV4YW1wbGU6YWxpY2VAZXhhbXBsZS5jb20aB0V4YW1wbGUgASgBMAIKLAoUMTIzNDU2Nzg5MDEyMzQ1Njc4OTASA2JvYhoHQUNNRSBDbyACKAIwATgHEAEYASAAKLlg",
+        ])
+        .write_stdin("test123")
+        .assert();
+    let list = cargo_bin_cmd!()
+        .args(["list", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret"])
+        .write_stdin("test123")
+        .assert();
+    let hotp = cargo_bin_cmd!()
+        .args(["totp", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret", "ACME Co"])
+        .write_stdin("test123")
+        .assert();
+    let _ = std::fs::remove_file(&database);
+
+    assert_cmd
+        .success()
+        .stdout("Created: /Root/Example\nCreated: /Root/ACME Co\n")
+        .stderr("");
+    list.success()
+        .stdout("/Root/test-pwd\n/Root/test-totp\n/Root/Example\n/Root/ACME Co\n");
+    hotp.failure()
+        .stderr("Unable to compute HOTP codes, only time-based ones\n");
+}
+
+fn import_file(file: &str) -> (assert_cmd::assert::Assert, assert_cmd::assert::Assert) {
+    let name = file.trim_start_matches("tests/files/");
+    let database =
+        std::env::temp_dir().join(format!("kdbx-import-{}-{name}.kdbx", std::process::id()));
+    std::fs::copy("tests/files/test.kdbx", &database).unwrap();
+
+    let import = cargo_bin_cmd!()
+        .args(["import", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret", file])
+        .write_stdin("test123")
+        .assert();
+    let list = cargo_bin_cmd!()
+        .args(["list", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret"])
+        .write_stdin("test123")
+        .assert();
+    let _ = std::fs::remove_file(&database);
+
+    (import, list)
+}
+
+#[test]
+fn test_import_aegis() {
+    let (import, list) = import_file("tests/files/aegis.json");
+
+    import
+        .success()
+        .stdout("Created: /Root/Example\nCreated: /Root/ACME Co\nCreated: /Root/Steam\n")
+        .stderr("");
+    list.success()
+        .stdout("/Root/test-pwd\n/Root/test-totp\n/Root/Example\n/Root/ACME Co\n/Root/Steam\n");
+}
+
+#[test]
+fn test_import_andotp() {
+    let (import, list) = import_file("tests/files/andotp.json");
+
+    import
+        .success()
+        .stdout("Created: /Root/Example\nCreated: /Root/ACME Co\nCreated: /Root/Steam\n")
+        .stderr("");
+    list.success()
+        .stdout("/Root/test-pwd\n/Root/test-totp\n/Root/Example\n/Root/ACME Co\n/Root/Steam\n");
+}
@@ -0,0 +1,90 @@
+use std::{env, fs};
+
+use assert_cmd::cargo::cargo_bin_cmd;
+
+#[test]
+fn test_keyfile_generate_verify() {
+    let path = env::temp_dir().join(format!("kdbx-keyfile-{}.key", std::process::id()));
+    let _ = fs::remove_file(&path);
+
+    cargo_bin_cmd!()
+        .args(["keyfile", "generate", "-f", "xml-v2"])
+        .arg(&path)
+        .assert()
+        .success();
+
+    cargo_bin_cmd!()
+        .args(["keyfile", "generate"])
+        .arg(&path)
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("Unable to write"));
+
+    let assert_cmd = cargo_bin_cmd!()
+        .args(["keyfile", "verify"])
+        .arg(&path)
+        .assert();
+    fs::remove_file(&path).unwrap();
+
+    assert_cmd.success().stdout("XML v2.0, hash verified\n");
+}
+
+#[test]
+fn test_keyfile_verify_any_file() {
+    let assert_cmd = cargo_bin_cmd!()
+        .args(["keyfile", "verify", "tests/files/secret"])
+        .assert();
+
+    assert_cmd
+        .success()
+        .stdout("any file, SHA-256 of its contents\n");
+}
+
+#[test]
+fn test_key_file_only_database() {
+    let dir = env::temp_dir().join(format!("kdbx-key-only-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    let database = dir.join("test.kdbx");
+    let key_file = dir.join("test.key");
+
+    cargo_bin_cmd!()
+        .args(["init", "--generate-keyfile", "--no-password", "-d"])
+        .arg(&database)
+        .arg("-k")
+        .arg(&key_file)
+        .write_stdin("Keys\n")
+        .assert()
+        .success();
+
+    let assert_cmd = cargo_bin_cmd!()
+        .args(["list", "-d"])
+        .arg(&database)
+        .arg("-k")
+        .arg(&key_file)
+        .assert();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_cmd.success();
+}
+
+#[test]
+fn test_init_removes_generated_key_file_on_failure() {
+    let dir = env::temp_dir().join(format!("kdbx-init-fail-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    let key_file = dir.join("test.key");
+
+    let assert_cmd = cargo_bin_cmd!()
+        .args(["init", "--generate-keyfile", "--no-password", "-d"])
+        .arg(dir.join("missing/test.kdbx"))
+        .arg("-k")
+        .arg(&key_file)
+        .write_stdin("Keys\n")
+        .assert();
+    let exists = key_file.exists();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_cmd.failure();
+    assert!(!exists);
+}
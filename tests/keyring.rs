@@ -0,0 +1,114 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+
+#[test]
+fn test_keyring_status() {
+    let assert_cmd = cargo_bin_cmd!()
+        .env("KDBX_KEYRING_BACKEND", "none")
+        .args(["keyring", "status"])
+        .assert();
+
+    assert_cmd
+        .success()
+        .stdout(predicates::str::starts_with("Backend: none\n"))
+        .stdout(predicates::str::contains("* none            available\n"));
+}
+
+#[test]
+fn test_keyring_status_expiry() {
+    let assert_cmd = cargo_bin_cmd!()
+        .args([
+            "keyring",
+            "status",
+            "--keyring-backend",
+            "none",
+            "--keyring-ttl",
+            "8h",
+            "--keyring-idle",
+            "30m",
+        ])
+        .assert();
+
+    assert_cmd.success().stdout(predicates::str::starts_with(
+        "Backend: none\nForget after: 28800s\nForget when idle for: 1800s\n",
+    ));
+}
+
+#[test]
+fn test_keyring_status_cache() {
+    let assert_cmd = cargo_bin_cmd!()
+        .env("KDBX_KEYRING_CACHE", "key")
+        .args(["keyring", "status", "--keyring-backend", "none"])
+        .assert();
+
+    assert_cmd
+        .success()
+        .stdout(predicates::str::starts_with("Backend: none\nCache: key\n"));
+}
+
+/// Each `add` after the first is unlocked from the key alone, so saving asks
+/// for the password again
+#[cfg(target_os = "linux")]
+#[test]
+fn test_add_twice_with_cached_key() {
+    use std::{env, ffi::CString, fs, os::unix::fs::PermissionsExt};
+
+    const KEYCTL_JOIN_SESSION_KEYRING: libc::c_long = 1;
+
+    // a session keyring of our own, shared by the commands run from here
+    let id = std::process::id();
+    let name = CString::new(format!("kdbx-test-{id}")).unwrap();
+    let joined =
+        unsafe { libc::syscall(libc::SYS_keyctl, KEYCTL_JOIN_SESSION_KEYRING, name.as_ptr()) };
+    assert!(joined >= 0, "unable to join a session keyring");
+
+    let dir = env::temp_dir().join(format!("kdbx-cached-key-{id}"));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    let database = dir.join("test.kdbx");
+    fs::copy("tests/files/test.kdbx", &database).unwrap();
+    let pinentry = dir.join("pinentry");
+    fs::write(
+        &pinentry,
+        concat!(
+            "#!/bin/sh\n",
+            "echo 'OK Pleased to meet you'\n",
+            "while read -r cmd rest; do\n",
+            "  case \"$cmd\" in\n",
+            "    GETPIN) echo 'D test123'; echo OK;;\n",
+            "    BYE) echo OK; exit 0;;\n",
+            "    *) echo OK;;\n",
+            "  esac\n",
+            "done\n"
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(&pinentry, fs::Permissions::from_mode(0o700)).unwrap();
+
+    let run = |args: &[&str], stdin: &str| {
+        cargo_bin_cmd!()
+            .env("KDBX_KEYRING_BACKEND", "kernel")
+            .env("KDBX_KEYRING_CACHE", "key")
+            .args(args)
+            .arg("-p")
+            .arg("-d")
+            .arg(&database)
+            .args(["-k", "tests/files/secret", "--pinentry"])
+            .arg(&pinentry)
+            .write_stdin(stdin)
+            .assert()
+    };
+
+    let first = run(&["add"], "first");
+    let second = run(&["add"], "second");
+    let third = run(&["add"], "third");
+    let list = run(&["list", "--remove-key"], "");
+    fs::remove_dir_all(&dir).unwrap();
+
+    first.success();
+    second.success();
+    third.success();
+    list.success()
+        .stdout(predicates::str::contains("first"))
+        .stdout(predicates::str::contains("second"))
+        .stdout(predicates::str::contains("third"));
+}
@@ -17,3 +17,82 @@ fn test_list() {
         .success()
         .stdout(predicates::str::starts_with("/Root/test-pwd"));
 }
+
+#[test]
+fn test_list_profile() {
+    let config = std::env::temp_dir().join(format!("kdbx-config-{}.toml", std::process::id()));
+    std::fs::write(
+        &config,
+        "[defaults]\nno-group = true\n\n[profiles.test]\ndatabase = \
+         \"tests/files/test.kdbx\"\nkey-file = \"tests/files/secret\"\n",
+    )
+    .unwrap();
+
+    let assert_cmd = cargo_bin_cmd!()
+        .env("KDBX_CONFIG", &config)
+        .args(["list", "--profile", "test"])
+        .write_stdin("test123")
+        .assert();
+    let _ = std::fs::remove_file(&config);
+
+    assert_cmd.success().stdout("test-pwd\ntest-totp\n");
+}
+
+#[test]
+fn test_list_multiple_databases() {
+    let team = std::env::temp_dir().join(format!("kdbx-team-{}.kdbx", std::process::id()));
+    std::fs::copy("tests/files/test.kdbx", &team).unwrap();
+
+    let assert_cmd = cargo_bin_cmd!()
+        .args(["list", "-G", "-d", "tests/files/test.kdbx", "-d"])
+        .arg(&team)
+        .args(["-k", "tests/files/secret", "-k", "tests/files/secret"])
+        .write_stdin("test123")
+        .assert();
+    let _ = std::fs::remove_file(&team);
+
+    let name = team.file_stem().unwrap().to_string_lossy();
+    assert_cmd.success().stdout(format!(
+        "test:test-pwd\ntest:test-totp\n{name}:test-pwd\n{name}:test-totp\n"
+    ));
+}
+
+#[test]
+fn test_list_paired_key_files() {
+    let team = std::env::temp_dir().join(format!("kdbx-paired-{}.kdbx", std::process::id()));
+    std::fs::copy("tests/files/test.kdbx", &team).unwrap();
+
+    let ambiguous = cargo_bin_cmd!()
+        .args(["list", "-G", "-d", "tests/files/test.kdbx", "-d"])
+        .arg(&team)
+        .args(["-k", "tests/files/secret"])
+        .write_stdin("test123")
+        .assert();
+    let paired = cargo_bin_cmd!()
+        .args(["list", "-G", "-d", "tests/files/test.kdbx"])
+        .args(["-k", "tests/files/secret", "--pair"])
+        .arg(&team)
+        .arg("tests/files/secret")
+        .write_stdin("test123")
+        .assert();
+    let pairs_only = cargo_bin_cmd!()
+        .args([
+            "list",
+            "-G",
+            "--pair",
+            "tests/files/test.kdbx",
+            "tests/files/secret",
+        ])
+        .write_stdin("test123")
+        .assert();
+    let _ = std::fs::remove_file(&team);
+
+    ambiguous.failure().stderr(predicates::str::contains(
+        "1 key files for 2 databases, pair them with `--pair DATABASE KEY_FILE`",
+    ));
+    pairs_only.success().stdout("test-pwd\ntest-totp\n");
+    let name = team.file_stem().unwrap().to_string_lossy();
+    paired.success().stdout(format!(
+        "test:test-pwd\ntest:test-totp\n{name}:test-pwd\n{name}:test-totp\n"
+    ));
+}
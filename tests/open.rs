@@ -0,0 +1,17 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+
+#[test]
+fn test_open_needs_terminal() {
+    let mut cmd = cargo_bin_cmd!();
+    let assert_cmd = cmd
+        .args([
+            "open",
+            "-d",
+            "tests/files/test.kdbx",
+            "-k",
+            "tests/files/secret",
+        ])
+        .write_stdin("test123")
+        .assert();
+    assert_cmd.failure().stderr("`open` needs a terminal\n");
+}
@@ -55,3 +55,113 @@ fn test_show_sensitive() {
         .success()
         .stdout("Title: test-pwd\nUsername: test\nPassword: 1234");
 }
+
+#[test]
+fn test_show_password_command() {
+    let mut cmd = cargo_bin_cmd!();
+    let assert_cmd = cmd
+        .args([
+            "show",
+            "-d",
+            "tests/files/test.kdbx",
+            "-k",
+            "tests/files/secret",
+            "--password-command",
+            "echo test123",
+            "test-pwd",
+        ])
+        .assert();
+    assert_cmd
+        .success()
+        .stdout("Title: test-pwd\nUsername: test\nPassword: ******");
+}
+
+#[test]
+fn test_show_password_file() {
+    let path = std::env::temp_dir().join(format!("kdbx-password-{}", std::process::id()));
+    std::fs::write(&path, "test123\n").unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    let assert_cmd = cmd
+        .args([
+            "show",
+            "-d",
+            "tests/files/test.kdbx",
+            "-k",
+            "tests/files/secret",
+            "test-pwd",
+            "--password-file",
+        ])
+        .arg(&path)
+        .assert();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_cmd
+        .success()
+        .stdout("Title: test-pwd\nUsername: test\nPassword: ******");
+}
+
+#[test]
+fn test_show_password_command_failed() {
+    let mut cmd = cargo_bin_cmd!();
+    let assert_cmd = cmd
+        .args([
+            "show",
+            "-d",
+            "tests/files/test.kdbx",
+            "-k",
+            "tests/files/secret",
+            "--password-command",
+            "false",
+            "test-pwd",
+        ])
+        .assert();
+    assert_cmd.failure().stderr(predicates::str::contains(
+        "Unable to read password from `false`",
+    ));
+}
+
+#[test]
+fn test_show_pinentry() {
+    use std::os::unix::fs::PermissionsExt;
+
+    // wrong password first, the right one once told about the error
+    let path = std::env::temp_dir().join(format!("kdbx-pinentry-{}", std::process::id()));
+    std::fs::write(
+        &path,
+        concat!(
+            "#!/bin/sh\n",
+            "echo 'OK Pleased to meet you'\n",
+            "pin=wrong\n",
+            "while read -r cmd rest; do\n",
+            "  case \"$cmd\" in\n",
+            "    SETERROR) pin=test123; echo OK;;\n",
+            "    GETPIN) echo \"D $pin\"; echo OK;;\n",
+            "    BYE) echo OK; exit 0;;\n",
+            "    *) echo OK;;\n",
+            "  esac\n",
+            "done\n"
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700)).unwrap();
+
+    let mut cmd = cargo_bin_cmd!();
+    let assert_cmd = cmd
+        .args([
+            "show",
+            "-d",
+            "tests/files/test.kdbx",
+            "-k",
+            "tests/files/secret",
+            "test-pwd",
+            "--pinentry",
+        ])
+        .arg(&path)
+        .assert();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_cmd
+        .success()
+        .stdout("Title: test-pwd\nUsername: test\nPassword: ******");
+}
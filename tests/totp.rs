@@ -75,3 +75,156 @@ fn test_totp_missing_secret() {
     .failure()
     .stderr("Entry has no TOTP secret\n");
 }
+
+#[test]
+fn test_totp_set_qr() {
+    let database = std::env::temp_dir().join(format!("kdbx-set-qr-{}.kdbx", std::process::id()));
+    std::fs::copy("tests/files/test.kdbx", &database).unwrap();
+
+    cargo_bin_cmd!()
+        .args(["totp", "-d"])
+        .arg(&database)
+        .args([
+            "-k",
+            "tests/files/secret",
+            "--set-qr",
+            "tests/files/totp.png",
+            "test-pwd",
+        ])
+        .write_stdin("test123")
+        .assert()
+        .success();
+
+    let assert_cmd = cargo_bin_cmd!()
+        .args(["totp", "-d"])
+        .arg(&database)
+        .args(["-k", "tests/files/secret", "test-pwd", "--raw"])
+        .write_stdin("test123")
+        .assert();
+    let _ = std::fs::remove_file(&database);
+
+    assert_cmd.success().stdout(
+        "otpauth://totp/Example:alice@example.com?secret=JBSWY3DPEHPK3PXP&issuer=Example&\
+         period=30&digits=6",
+    );
+}
+
+#[test]
+fn test_totp_set_qr_multiple_databases() {
+    let personal = std::env::temp_dir().join(format!("kdbx-personal-{}.kdbx", std::process::id()));
+    let team = std::env::temp_dir().join(format!("kdbx-team-qr-{}.kdbx", std::process::id()));
+    std::fs::copy("tests/files/test.kdbx", &personal).unwrap();
+    std::fs::copy("tests/files/test.kdbx", &team).unwrap();
+    let name = team.file_stem().unwrap().to_string_lossy().into_owned();
+
+    cargo_bin_cmd!()
+        .args(["totp", "-d"])
+        .arg(&personal)
+        .arg("-d")
+        .arg(&team)
+        .args([
+            "-k",
+            "tests/files/secret",
+            "-k",
+            "tests/files/secret",
+            "--set-qr",
+            "tests/files/totp.png",
+            &format!("{name}:/Root/test-pwd"),
+        ])
+        .write_stdin("test123")
+        .assert()
+        .success();
+
+    let raw = |database: &std::path::Path| {
+        cargo_bin_cmd!()
+            .args(["totp", "-d"])
+            .arg(database)
+            .args(["-k", "tests/files/secret", "test-pwd", "--raw"])
+            .write_stdin("test123")
+            .assert()
+    };
+    let personal_cmd = raw(&personal);
+    let team_cmd = raw(&team);
+    let _ = std::fs::remove_file(&personal);
+    let _ = std::fs::remove_file(&team);
+
+    personal_cmd.failure();
+    team_cmd.success().stdout(predicates::str::starts_with(
+        "otpauth://totp/Example:alice@example.com",
+    ));
+}
+
+#[test]
+fn test_totp_qr_requires_tty() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args([
+        "totp",
+        "-d",
+        "tests/files/test.kdbx",
+        "-k",
+        "tests/files/secret",
+        "test-totp",
+        "--qr",
+    ])
+    .write_stdin("test123")
+    .assert()
+    .failure()
+    .stderr("Refusing to print a secret QR code to a non-terminal. Use --force.\n");
+}
+
+#[test]
+fn test_totp_qr_forced() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args([
+        "totp",
+        "-d",
+        "tests/files/test.kdbx",
+        "-k",
+        "tests/files/secret",
+        "test-totp",
+        "--qr",
+        "--force",
+    ])
+    .write_stdin("test123")
+    .assert()
+    .success()
+    .stdout(predicate::str::contains("\x1b[30;47m").and(predicate::str::contains("▀")));
+}
+
+#[test]
+fn test_totp_at() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args([
+        "totp",
+        "-d",
+        "tests/files/test.kdbx",
+        "-k",
+        "tests/files/secret",
+        "test-totp",
+        "--at",
+        "0",
+    ])
+    .write_stdin("test123")
+    .assert()
+    .success()
+    .stdout("282760");
+}
+
+#[test]
+fn test_totp_watch_snapshot() {
+    let mut cmd = cargo_bin_cmd!();
+    cmd.args([
+        "totp",
+        "-d",
+        "tests/files/test.kdbx",
+        "-k",
+        "tests/files/secret",
+        "--watch",
+        "--at",
+        "10",
+    ])
+    .write_stdin("test123")
+    .assert()
+    .success()
+    .stdout(" 1) /Root/test-totp  282760  20s  next: 996554\n");
+}